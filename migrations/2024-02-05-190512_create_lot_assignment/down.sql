@@ -0,0 +1,3 @@
+DROP TABLE lot_assignment;
+ALTER TABLE portfolio DROP COLUMN cost_basis_method;
+DROP TYPE cost_basis_method_type;
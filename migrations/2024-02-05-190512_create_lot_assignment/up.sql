@@ -0,0 +1,16 @@
+CREATE TYPE cost_basis_method_type AS ENUM ('fifo', 'lifo', 'average_cost', 'specific_lot');
+ALTER TABLE portfolio
+    ADD cost_basis_method cost_basis_method_type NOT NULL DEFAULT 'fifo';
+
+CREATE TABLE lot_assignment (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    portfolio_id UUID NOT NULL REFERENCES portfolio (id) ON DELETE CASCADE,
+    sell_trade_operation_id UUID NOT NULL REFERENCES trade_operation (id) ON DELETE CASCADE,
+    buy_trade_operation_id UUID NOT NULL REFERENCES trade_operation (id) ON DELETE CASCADE,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    cost_basis custom_money NOT NULL,
+    manual BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX ON lot_assignment (portfolio_id);
+CREATE INDEX ON lot_assignment (sell_trade_operation_id);
@@ -0,0 +1 @@
+ALTER TABLE trade_operation DROP COLUMN reported_pnl;
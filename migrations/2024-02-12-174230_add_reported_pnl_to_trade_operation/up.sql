@@ -0,0 +1,3 @@
+-- profit or loss of a trade as calculated by the broker itself
+ALTER TABLE trade_operation
+    ADD reported_pnl custom_money NULL;
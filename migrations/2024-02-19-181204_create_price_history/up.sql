@@ -0,0 +1,9 @@
+CREATE TABLE price_history (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    ticker VARCHAR NOT NULL,
+    date DATE NOT NULL,
+    close custom_money NOT NULL,
+    source VARCHAR NOT NULL
+);
+
+CREATE UNIQUE INDEX ON price_history (ticker, date);
@@ -0,0 +1,10 @@
+CREATE TABLE fx_rate (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    date DATE NOT NULL,
+    base_currency VARCHAR(3) NOT NULL,
+    quote_currency VARCHAR(3) NOT NULL,
+    rate DECIMAL NOT NULL CHECK (rate > 0),
+    source VARCHAR NOT NULL
+);
+
+CREATE UNIQUE INDEX ON fx_rate (base_currency, quote_currency, date);
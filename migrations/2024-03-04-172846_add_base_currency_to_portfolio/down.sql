@@ -0,0 +1 @@
+ALTER TABLE portfolio DROP COLUMN base_currency;
@@ -0,0 +1,2 @@
+ALTER TABLE portfolio
+    ADD base_currency VARCHAR(3) NOT NULL DEFAULT 'USD';
@@ -0,0 +1,2 @@
+DROP TABLE security_transfer;
+DROP TYPE transfer_direction_type;
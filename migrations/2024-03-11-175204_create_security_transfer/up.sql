@@ -0,0 +1,20 @@
+CREATE TYPE transfer_direction_type AS ENUM ('in', 'out');
+
+CREATE TABLE security_transfer (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    portfolio_id UUID NOT NULL REFERENCES portfolio (id) ON DELETE CASCADE,
+    report_upload_id UUID NULL REFERENCES report_upload (id) ON DELETE CASCADE,
+    operation_source operation_source_type NOT NULL,
+    broker broker_type NULL,
+    external_id VARCHAR NULL,
+    date_time TIMESTAMP NOT NULL,
+    direction transfer_direction_type NOT NULL,
+    instrument_symbol VARCHAR NOT NULL,
+    isin VARCHAR NULL,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    cost_basis custom_money NULL,
+    metadata jsonb NOT NULL
+);
+
+CREATE UNIQUE INDEX ON security_transfer (operation_source, external_id);
+CREATE INDEX ON security_transfer (portfolio_id);
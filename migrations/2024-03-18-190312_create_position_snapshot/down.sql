@@ -0,0 +1 @@
+DROP TABLE position_snapshot;
@@ -0,0 +1,14 @@
+CREATE TABLE position_snapshot (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    portfolio_id UUID NOT NULL REFERENCES portfolio (id) ON DELETE CASCADE,
+    report_upload_id UUID NOT NULL REFERENCES report_upload (id) ON DELETE CASCADE,
+    broker broker_type NOT NULL,
+    taken_at TIMESTAMP NOT NULL,
+    -- NULL for a cash balance
+    instrument_symbol VARCHAR NULL,
+    isin VARCHAR NULL,
+    quantity DECIMAL NOT NULL,
+    currency VARCHAR NOT NULL
+);
+
+CREATE INDEX ON position_snapshot (portfolio_id);
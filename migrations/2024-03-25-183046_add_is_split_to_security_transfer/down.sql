@@ -0,0 +1 @@
+ALTER TABLE security_transfer DROP COLUMN is_split;
@@ -0,0 +1,2 @@
+ALTER TABLE security_transfer
+    ADD is_split BOOLEAN NOT NULL DEFAULT FALSE;
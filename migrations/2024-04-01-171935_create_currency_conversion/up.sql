@@ -0,0 +1,16 @@
+CREATE TABLE currency_conversion (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    portfolio_id UUID NOT NULL REFERENCES portfolio (id) ON DELETE CASCADE,
+    report_upload_id UUID NULL REFERENCES report_upload (id) ON DELETE CASCADE,
+    operation_source operation_source_type NOT NULL,
+    broker broker_type NULL,
+    external_id VARCHAR NULL,
+    date_time TIMESTAMP NOT NULL,
+    sold custom_money NOT NULL,
+    bought custom_money NOT NULL,
+    rate DECIMAL NOT NULL,
+    metadata jsonb NOT NULL
+);
+
+CREATE UNIQUE INDEX ON currency_conversion (operation_source, external_id);
+CREATE INDEX ON currency_conversion (portfolio_id);
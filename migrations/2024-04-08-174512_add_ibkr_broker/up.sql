@@ -0,0 +1,2 @@
+ALTER TYPE broker_type ADD VALUE IF NOT EXISTS 'ibkr';
+ALTER TYPE operation_source_type ADD VALUE IF NOT EXISTS 'ibkr_report';
@@ -0,0 +1,24 @@
+-- values can't be dropped from an enum, so both types are recreated without them
+DELETE FROM report_upload WHERE broker = 'trading212';
+DELETE FROM fiscal_transaction WHERE broker = 'trading212' OR operation_source = 'trading212_report';
+DELETE FROM trade_operation WHERE broker = 'trading212' OR operation_source = 'trading212_report';
+DELETE FROM security_transfer WHERE broker = 'trading212' OR operation_source = 'trading212_report';
+DELETE FROM currency_conversion WHERE broker = 'trading212' OR operation_source = 'trading212_report';
+
+ALTER TYPE broker_type RENAME TO broker_type_old;
+CREATE TYPE broker_type AS ENUM ('exante', 'freedomfinance', 'ibkr', 'degiro');
+ALTER TABLE report_upload ALTER COLUMN broker TYPE broker_type USING broker::text::broker_type;
+ALTER TABLE position_snapshot ALTER COLUMN broker TYPE broker_type USING broker::text::broker_type;
+ALTER TABLE fiscal_transaction ALTER COLUMN broker TYPE broker_type USING broker::text::broker_type;
+ALTER TABLE trade_operation ALTER COLUMN broker TYPE broker_type USING broker::text::broker_type;
+ALTER TABLE security_transfer ALTER COLUMN broker TYPE broker_type USING broker::text::broker_type;
+ALTER TABLE currency_conversion ALTER COLUMN broker TYPE broker_type USING broker::text::broker_type;
+DROP TYPE broker_type_old;
+
+ALTER TYPE operation_source_type RENAME TO operation_source_type_old;
+CREATE TYPE operation_source_type AS ENUM ('exante_report', 'freedomfinance_report', 'manual', 'ibkr_report', 'degiro_report');
+ALTER TABLE fiscal_transaction ALTER COLUMN operation_source TYPE operation_source_type USING operation_source::text::operation_source_type;
+ALTER TABLE trade_operation ALTER COLUMN operation_source TYPE operation_source_type USING operation_source::text::operation_source_type;
+ALTER TABLE security_transfer ALTER COLUMN operation_source TYPE operation_source_type USING operation_source::text::operation_source_type;
+ALTER TABLE currency_conversion ALTER COLUMN operation_source TYPE operation_source_type USING operation_source::text::operation_source_type;
+DROP TYPE operation_source_type_old;
@@ -0,0 +1,12 @@
+ALTER TYPE broker_type ADD VALUE IF NOT EXISTS 'other';
+ALTER TYPE operation_source_type ADD VALUE IF NOT EXISTS 'custom_csv_report';
+
+CREATE TABLE csv_mapping_profile (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    portfolio_id UUID NOT NULL REFERENCES portfolio (id) ON DELETE CASCADE,
+    label VARCHAR NOT NULL,
+    mapping jsonb NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX ON csv_mapping_profile (portfolio_id);
@@ -0,0 +1,9 @@
+DROP INDEX trade_operation_portfolio_id_operation_source_external_id_idx;
+DROP INDEX fiscal_transaction_portfolio_id_operation_source_external_id_idx;
+DROP INDEX security_transfer_portfolio_id_operation_source_external_id_idx;
+DROP INDEX currency_conversion_portfolio_id_operation_source_external_id_idx;
+
+CREATE UNIQUE INDEX trade_operation_operation_source_external_id_idx ON trade_operation (operation_source, external_id);
+CREATE UNIQUE INDEX transaction_operation_source_external_id_idx ON fiscal_transaction (operation_source, external_id);
+CREATE UNIQUE INDEX security_transfer_operation_source_external_id_idx ON security_transfer (operation_source, external_id);
+CREATE UNIQUE INDEX currency_conversion_operation_source_external_id_idx ON currency_conversion (operation_source, external_id);
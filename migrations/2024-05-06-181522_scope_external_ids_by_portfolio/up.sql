@@ -0,0 +1,11 @@
+-- Ids made of the contents of a row, like the ones of dividends, are the same for
+-- every holder of a security, so they are only unique within a portfolio
+DROP INDEX trade_operation_operation_source_external_id_idx;
+DROP INDEX transaction_operation_source_external_id_idx;
+DROP INDEX security_transfer_operation_source_external_id_idx;
+DROP INDEX currency_conversion_operation_source_external_id_idx;
+
+CREATE UNIQUE INDEX trade_operation_portfolio_id_operation_source_external_id_idx ON trade_operation (portfolio_id, operation_source, external_id);
+CREATE UNIQUE INDEX fiscal_transaction_portfolio_id_operation_source_external_id_idx ON fiscal_transaction (portfolio_id, operation_source, external_id);
+CREATE UNIQUE INDEX security_transfer_portfolio_id_operation_source_external_id_idx ON security_transfer (portfolio_id, operation_source, external_id);
+CREATE UNIQUE INDEX currency_conversion_portfolio_id_operation_source_external_id_idx ON currency_conversion (portfolio_id, operation_source, external_id);
@@ -0,0 +1,5 @@
+DELETE FROM lot_assignment WHERE buy_trade_operation_id IS NULL;
+ALTER TABLE lot_assignment
+    DROP CONSTRAINT lot_assignment_buy_check,
+    DROP buy_security_transfer_id,
+    ALTER buy_trade_operation_id SET NOT NULL;
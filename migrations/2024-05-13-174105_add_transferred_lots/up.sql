@@ -0,0 +1,5 @@
+-- lots are opened by purchases or by securities transferred in
+ALTER TABLE lot_assignment
+    ALTER buy_trade_operation_id DROP NOT NULL,
+    ADD buy_security_transfer_id UUID REFERENCES security_transfer (id) ON DELETE CASCADE,
+    ADD CONSTRAINT lot_assignment_buy_check CHECK (num_nonnulls(buy_trade_operation_id, buy_security_transfer_id) = 1);
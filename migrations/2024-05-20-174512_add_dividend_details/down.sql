@@ -0,0 +1,7 @@
+UPDATE fiscal_transaction
+SET metadata = metadata || jsonb_build_object('ex_date', ex_date, 'amount_per_share', amount_per_share::TEXT)
+WHERE ex_date IS NOT NULL;
+
+ALTER TABLE fiscal_transaction
+    DROP ex_date,
+    DROP amount_per_share;
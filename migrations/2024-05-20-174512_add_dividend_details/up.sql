@@ -0,0 +1,10 @@
+-- dividends of brokers which report their corporate actions
+ALTER TABLE fiscal_transaction
+    ADD ex_date DATE,
+    ADD amount_per_share NUMERIC;
+
+UPDATE fiscal_transaction
+SET ex_date = (metadata->>'ex_date')::DATE,
+    amount_per_share = (metadata->>'amount_per_share')::NUMERIC,
+    metadata = metadata - 'ex_date' - 'amount_per_share'
+WHERE operation_source = 'freedomfinance_report' AND metadata ? 'ex_date';
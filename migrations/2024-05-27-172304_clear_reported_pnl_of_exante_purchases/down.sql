@@ -0,0 +1,3 @@
+UPDATE trade_operation
+SET reported_pnl = ROW(0, (price).currency)::custom_money
+WHERE operation_source = 'exante_report' AND side = 'buy';
@@ -0,0 +1,4 @@
+-- Exante reports a zero P&L of every purchase, which is not a result reported by the broker
+UPDATE trade_operation
+SET reported_pnl = NULL
+WHERE operation_source = 'exante_report' AND side = 'buy';
@@ -0,0 +1,158 @@
+use async_graphql::{Enum, InputObject, SimpleObject};
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Queryable, Selectable};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::database::schema;
+
+/// How rows of a CSV file of a broker without an own importer become operations
+#[derive(Serialize, Deserialize, Clone, Debug, SimpleObject, InputObject)]
+#[serde(rename_all = "camelCase")]
+#[graphql(input_name = "CsvMappingInput")]
+pub struct CsvMapping {
+    /// Single character between fields, like "," or "\t"
+    pub delimiter: String,
+    /// Format of dates as in https://docs.rs/chrono/latest/chrono/format/strftime, like "%d.%m.%Y %H:%M".
+    /// When the time is in a column of its own, the format covers the date and the time joined with a space.
+    pub date_format: String,
+    /// Numbers are written with a decimal comma, as in "1.234,56"
+    #[serde(default)]
+    #[graphql(default)]
+    pub decimal_comma: bool,
+    /// Lines above the header, like a title of the export
+    #[serde(default)]
+    #[graphql(default)]
+    pub skip_lines: i32,
+    /// Currency of rows when the file has no column of it
+    pub default_currency: Option<String>,
+    pub columns: CsvColumns,
+    /// Values of the operation type and side columns with the operations they stand for.
+    /// Values named like an operation, as "Dividend" or "buy", don't need a mapping.
+    #[serde(default)]
+    #[graphql(default)]
+    pub value_mappings: Vec<CsvValueMapping>,
+}
+
+/// Names of columns in the header of the file
+#[derive(Serialize, Deserialize, Clone, Debug, SimpleObject, InputObject)]
+#[serde(rename_all = "camelCase")]
+#[graphql(input_name = "CsvColumnsInput")]
+pub struct CsvColumns {
+    pub date: String,
+    pub time: Option<String>,
+    /// Kind of an operation. Without it every row is a trade.
+    pub operation_type: Option<String>,
+    /// Whether a trade is a buy or a sell
+    pub side: Option<String>,
+    pub ticker: Option<String>,
+    pub isin: Option<String>,
+    pub quantity: Option<String>,
+    pub price: Option<String>,
+    /// Money of a row, the volume of a trade or the amount of any other operation
+    pub amount: Option<String>,
+    pub currency: Option<String>,
+    pub commission: Option<String>,
+    /// Identifier of a row in the broker, which makes repeated imports update the same operations
+    pub external_id: Option<String>,
+    pub comment: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, SimpleObject, InputObject)]
+#[serde(rename_all = "camelCase")]
+#[graphql(input_name = "CsvValueMappingInput")]
+pub struct CsvValueMapping {
+    pub value: String,
+    pub operation: CsvOperation,
+}
+
+#[derive(Serialize, Deserialize, Enum, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CsvOperation {
+    Buy,
+    Sell,
+    /// A buy or a sell, depending on the side column
+    Trade,
+    Dividend,
+    Tax,
+    Commission,
+    FundingWithdrawal,
+    Interest,
+    /// Rows which are not imported
+    Ignore,
+}
+
+impl CsvMapping {
+    /// The operation a value of the operation type or side column stands for
+    pub fn operation_of(&self, value: &str) -> Option<CsvOperation> {
+        let value = value.trim();
+        self.value_mappings.iter()
+            .find(|m| m.value.trim().eq_ignore_ascii_case(value))
+            .map(|m| m.operation)
+            .or_else(|| {
+                let name = value.to_lowercase().replace([' ', '_', '/'], "");
+                [
+                    ("buy", CsvOperation::Buy),
+                    ("sell", CsvOperation::Sell),
+                    ("trade", CsvOperation::Trade),
+                    ("dividend", CsvOperation::Dividend),
+                    ("tax", CsvOperation::Tax),
+                    ("commission", CsvOperation::Commission),
+                    ("fundingwithdrawal", CsvOperation::FundingWithdrawal),
+                    ("interest", CsvOperation::Interest),
+                ].into_iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, operation)| operation)
+            })
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct CsvMappingProfile {
+    pub id: Uuid,
+    pub portfolio_id: Uuid,
+    pub label: String,
+    pub mapping: CsvMapping,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Mapping of the profile {id} can't be read: {source}")]
+pub struct CsvMappingReadError {
+    pub id: Uuid,
+    pub source: serde_json::Error,
+}
+
+impl TryFrom<SelectCsvMappingProfile> for CsvMappingProfile {
+    type Error = CsvMappingReadError;
+
+    fn try_from(value: SelectCsvMappingProfile) -> Result<Self, Self::Error> {
+        Ok(CsvMappingProfile {
+            id: value.id,
+            portfolio_id: value.portfolio_id,
+            label: value.label,
+            mapping: serde_json::from_value(value.mapping)
+                .map_err(|source| CsvMappingReadError { id: value.id, source })?,
+        })
+    }
+}
+
+// --- orm model
+
+#[derive(Deserialize, Insertable)]
+#[diesel(table_name = schema::csv_mapping_profile )]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct InsertCsvMappingProfile {
+    pub portfolio_id: Uuid,
+    pub label: String,
+    pub mapping: serde_json::Value,
+}
+
+#[derive(Deserialize, Queryable, Selectable)]
+#[diesel(table_name = schema::csv_mapping_profile )]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct SelectCsvMappingProfile {
+    pub id: Uuid,
+    pub portfolio_id: Uuid,
+    pub label: String,
+    pub mapping: serde_json::Value,
+    pub created_at: NaiveDateTime,
+}
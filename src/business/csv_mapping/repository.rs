@@ -0,0 +1,44 @@
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::database::{schema::csv_mapping_profile::dsl, CommonRepository, RepositoryError};
+
+use super::model::{InsertCsvMappingProfile, SelectCsvMappingProfile};
+
+impl CommonRepository {
+    pub fn list_csv_mapping_profiles(&self, portfolio_id: Uuid) -> Result<Vec<SelectCsvMappingProfile>, RepositoryError> {
+        Ok(dsl::csv_mapping_profile
+            .filter(dsl::portfolio_id.eq(portfolio_id))
+            .order(dsl::created_at.asc())
+            .select(SelectCsvMappingProfile::as_select())
+            .load(&mut self.pool.get()?)?)
+    }
+
+    pub fn find_csv_mapping_profile(&self, id: Uuid) -> Result<Option<SelectCsvMappingProfile>, RepositoryError> {
+        Ok(dsl::csv_mapping_profile
+            .filter(dsl::id.eq(id))
+            .select(SelectCsvMappingProfile::as_select())
+            .first(&mut self.pool.get()?)
+            .optional()?)
+    }
+
+    pub fn create_csv_mapping_profile(&self, profile: InsertCsvMappingProfile) -> Result<Uuid, RepositoryError> {
+        Ok(diesel::insert_into(dsl::csv_mapping_profile)
+            .values(profile)
+            .returning(dsl::id)
+            .get_result::<Uuid>(&mut self.pool.get()?)?)
+    }
+
+    pub fn delete_csv_mapping_profiles_with_user_id(&self, ids: Vec<Uuid>, app_user_id: Uuid) -> Result<usize, RepositoryError> {
+        let valid_ids: Vec<Uuid> = dsl::csv_mapping_profile
+            .inner_join(crate::database::schema::portfolio::dsl::portfolio)
+            .filter(crate::database::schema::portfolio::dsl::app_user_id.eq(app_user_id))
+            .filter(dsl::id.eq_any(ids))
+            .select(dsl::id)
+            .load(&mut self.pool.get()?)?;
+        let affected = diesel::delete(dsl::csv_mapping_profile
+            .filter(dsl::id.eq_any(valid_ids)))
+            .execute(&mut self.pool.get()?)?;
+        Ok(affected)
+    }
+}
@@ -0,0 +1,79 @@
+use async_graphql::{Context, CustomValidator, InputObject, InputValueError, Object};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::business::portfolio::security::is_portfolio_owner;
+use crate::web::graphql::{get_claims, get_state};
+
+use super::model::{CsvMapping, CsvOperation, InsertCsvMappingProfile};
+
+
+#[derive(Default)]
+pub struct CsvMappingMutation;
+#[Object(rename_fields="camelCase", rename_args="camelCase")]
+impl CsvMappingMutation {
+    /// Save how CSV files of a broker without an own importer are read into a portfolio.
+    /// Reports are uploaded with the brokerage OTHER and the id of the profile.
+    async fn create_csv_mapping_profile(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(custom = "CreateCsvMappingProfileValidator{}"))]
+        create_request: CreateCsvMappingProfile
+    ) -> async_graphql::Result<Uuid> {
+        let claims = get_claims(ctx)?;
+        let state = get_state(ctx)?;
+
+        is_portfolio_owner(state, claims.sub, create_request.portfolio_id)?;
+        Ok(state.repository.create_csv_mapping_profile(InsertCsvMappingProfile {
+            portfolio_id: create_request.portfolio_id,
+            label: create_request.label,
+            mapping: serde_json::to_value(create_request.mapping)?,
+        })?)
+    }
+
+    /// Delete multiple CSV mapping profiles. Returns number of deleted rows.
+    async fn delete_csv_mapping_profiles(&self, ctx: &Context<'_>, ids: Vec<Uuid>) -> async_graphql::Result<usize> {
+        let claims = get_claims(ctx)?;
+        let state = get_state(ctx)?;
+        Ok(state.repository.delete_csv_mapping_profiles_with_user_id(ids, claims.sub)?)
+    }
+}
+
+// --- model
+
+#[derive(InputObject,Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateCsvMappingProfile {
+    pub portfolio_id: Uuid,
+    /// Name of the profile, like the broker whose files it reads
+    pub label: String,
+    pub mapping: CsvMapping,
+}
+
+// --- validation
+
+struct CreateCsvMappingProfileValidator { }
+
+impl CustomValidator<CreateCsvMappingProfile> for CreateCsvMappingProfileValidator {
+    fn check(&self, value: &CreateCsvMappingProfile) -> Result<(), InputValueError<CreateCsvMappingProfile>> {
+        let mapping = &value.mapping;
+        let columns = &mapping.columns;
+        if !matches!(mapping.delimiter.as_bytes(), [delimiter] if delimiter.is_ascii()) {
+            Err(InputValueError::custom("mapping.delimiter must be a single character"))
+        } else if mapping.date_format.trim().is_empty() {
+            Err(InputValueError::custom("mapping.dateFormat must not be empty"))
+        } else if mapping.skip_lines < 0 {
+            Err(InputValueError::custom("mapping.skipLines must not be negative"))
+        } else if columns.operation_type.is_none() && columns.side.is_none() {
+            Err(InputValueError::custom("mapping.columns needs an operationType or a side column"))
+        } else if columns.currency.is_none() && mapping.default_currency.is_none() {
+            Err(InputValueError::custom("mapping.columns.currency or mapping.defaultCurrency is required"))
+        } else if columns.amount.is_none() && columns.price.is_none() {
+            Err(InputValueError::custom("mapping.columns needs an amount or a price column"))
+        } else if mapping.value_mappings.iter().any(|m| m.operation == CsvOperation::Trade) && columns.side.is_none() {
+            Err(InputValueError::custom("values mapped to TRADE need a side column"))
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Selectable, Queryable};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{business::{fiscal_transaction::model::{FiscalTransaction, FiscalTransactionType}, model::{BrokerType, FieldChange, Money, OperationSource}}, database::schema};
+
+/// Money of one currency exchanged for money of an other one
+#[derive(Serialize,Deserialize,Insertable,Selectable,Queryable)]
+#[diesel(table_name = crate::database::schema::currency_conversion)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CurrencyConversion {
+    pub operation_source: OperationSource,
+    pub broker: Option<BrokerType>,
+    pub external_id: Option<String>,
+    pub date_time: NaiveDateTime,
+    pub sold: Money, // always positive
+    pub bought: Money, // always positive
+    pub rate: Decimal, // units of the bought currency paid for a unit of the sold one
+    pub metadata: serde_json::Value,
+}
+
+impl CurrencyConversion {
+    /// Fields which an import of this conversion changes in the already imported one
+    pub fn changes_from(&self, imported: &CurrencyConversion) -> Vec<FieldChange> {
+        [
+            FieldChange::of("dateTime", &imported.date_time, &self.date_time),
+            FieldChange::of("sold", &imported.sold, &self.sold),
+            FieldChange::of("bought", &imported.bought, &self.bought),
+            FieldChange::of("rate", &imported.rate, &self.rate),
+            FieldChange::of("metadata", &imported.metadata, &self.metadata),
+        ].into_iter().flatten().collect()
+    }
+
+    /// Units of the bought currency paid for a unit of the sold one
+    pub fn implied_rate(sold: &Money, bought: &Money) -> Decimal {
+        if sold.amount.is_zero() {
+            Decimal::ZERO
+        } else {
+            (bought.amount / sold.amount).round_dp(10).normalize()
+        }
+    }
+
+    /// The conversion as a withdrawal of the sold money and a deposit of the bought one
+    pub fn legs(&self) -> [FiscalTransaction; 2] {
+        let leg = |amount: Money| FiscalTransaction {
+            operation_source: self.operation_source,
+            broker: self.broker,
+            external_id: None,
+            date_time: self.date_time,
+            symbol_id: None,
+            amount,
+            operation_type: FiscalTransactionType::CurrencyExchange,
+            commission: None,
+            metadata: serde_json::Value::Null,
+            ex_date: None,
+            amount_per_share: None,
+        };
+        [leg(self.sold.clone() * -1), leg(self.bought.clone())]
+    }
+}
+
+// --- orm model
+
+#[derive(Deserialize, Insertable)]
+#[diesel(table_name = schema::currency_conversion )]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct InsertCurrencyConversion {
+    pub portfolio_id: Uuid,
+    pub report_upload_id: Option<Uuid>,
+    #[diesel(embed)]
+    pub currency_conversion: CurrencyConversion
+}
+
+#[derive(Deserialize, Queryable, Selectable)]
+#[diesel(table_name = schema::currency_conversion )]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct SelectCurrencyConversion {
+    pub id: Uuid,
+    pub portfolio_id: Uuid,
+    #[diesel(embed)]
+    pub i: CurrencyConversion
+}
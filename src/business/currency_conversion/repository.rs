@@ -0,0 +1,59 @@
+use diesel::{insert_into, prelude::*, upsert::excluded};
+use uuid::Uuid;
+
+use crate::database::{schema::{self, currency_conversion::dsl}, CommonRepository, RepositoryError};
+
+use super::model::{InsertCurrencyConversion, SelectCurrencyConversion};
+
+impl CommonRepository {
+    pub fn list_currency_conversions(&self, portfolio_id: Uuid) -> Result<Vec<SelectCurrencyConversion>, RepositoryError> {
+        Ok(dsl::currency_conversion
+            .filter(dsl::portfolio_id.eq(portfolio_id))
+            .select(SelectCurrencyConversion::as_select())
+            .load(&mut self.pool.get()?)?)
+    }
+
+    pub fn list_currency_conversions_by_external_ids(&self, portfolio_id: Uuid, external_ids: &[String]) -> Result<Vec<SelectCurrencyConversion>, RepositoryError> {
+        Ok(dsl::currency_conversion
+            .filter(dsl::portfolio_id.eq(portfolio_id))
+            .filter(dsl::external_id.eq_any(external_ids))
+            .select(SelectCurrencyConversion::as_select())
+            .load(&mut self.pool.get()?)?)
+    }
+
+    pub fn create_currency_conversion(&self, currency_conversion: InsertCurrencyConversion) -> Result<Uuid, RepositoryError> {
+        Ok(diesel::insert_into(dsl::currency_conversion)
+            .values(currency_conversion)
+            .returning(dsl::id)
+            .get_result::<Uuid>(&mut self.pool.get()?)?)
+    }
+
+    pub fn delete_currency_conversions_with_user_id(&self, ids: Vec<Uuid>, app_user_id: Uuid) -> Result<usize, RepositoryError> {
+        let valid_ids: Vec<Uuid> = dsl::currency_conversion
+            .inner_join(crate::database::schema::portfolio::dsl::portfolio)
+            .filter(crate::database::schema::portfolio::dsl::app_user_id.eq(app_user_id))
+            .filter(dsl::id.eq_any(ids))
+            .select(dsl::id)
+            .load(&mut self.pool.get()?)?;
+        let affected = diesel::delete(dsl::currency_conversion
+            .filter(dsl::id.eq_any(valid_ids)))
+            .execute(&mut self.pool.get()?)?;
+        Ok(affected)
+    }
+
+    pub fn create_currency_conversions(&self, currency_conversions: Vec<InsertCurrencyConversion>) -> Result<usize, RepositoryError> {
+        Ok(insert_into(schema::currency_conversion::dsl::currency_conversion)
+            .values(currency_conversions)
+            .on_conflict((dsl::portfolio_id, dsl::operation_source, dsl::external_id))
+            .do_update()
+            .set((
+                dsl::report_upload_id.eq(excluded(dsl::report_upload_id)),
+                dsl::date_time.eq(excluded(dsl::date_time)),
+                dsl::sold.eq(excluded(dsl::sold)),
+                dsl::bought.eq(excluded(dsl::bought)),
+                dsl::rate.eq(excluded(dsl::rate)),
+                dsl::metadata.eq(excluded(dsl::metadata)),
+            ))
+            .execute(&mut self.pool.get()?)?)
+    }
+}
@@ -0,0 +1,90 @@
+use async_graphql::{Context, CustomValidator, InputObject, InputValueError, Object};
+use chrono::NaiveDateTime;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::business::portfolio::security::is_portfolio_owner;
+use crate::business::model::{BrokerType, Money, OperationSource};
+use crate::web::graphql::{get_claims, get_state};
+
+use super::model::{CurrencyConversion, InsertCurrencyConversion};
+
+
+#[derive(Default)]
+pub struct CurrencyConversionMutation;
+#[Object(rename_fields="camelCase", rename_args="camelCase")]
+impl CurrencyConversionMutation {
+    /// Record an exchange of money between currencies within a portfolio identified by id
+    async fn create_currency_conversion(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(custom = "CreateCurrencyConversionValidator{}"))]
+        create_request: CreateCurrencyConversion
+    ) -> async_graphql::Result<Uuid> {
+        let claims = get_claims(ctx)?;
+        let state = get_state(ctx)?;
+
+        is_portfolio_owner(state, claims.sub, create_request.portfolio_id)?;
+        Ok(state.repository.create_currency_conversion(create_request.into())?)
+    }
+
+    /// Delete multiple currency conversions. Returns number of deleted rows.
+    async fn delete_currency_conversions(&self, ctx: &Context<'_>, ids: Vec<Uuid>) -> async_graphql::Result<usize> {
+        let claims = get_claims(ctx)?;
+        let state = get_state(ctx)?;
+        Ok(state.repository.delete_currency_conversions_with_user_id(ids, claims.sub)?)
+    }
+}
+
+// --- model
+
+#[derive(InputObject,Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateCurrencyConversion {
+    pub portfolio_id: Uuid,
+    /// Money given away in the conversion. Must be positive.
+    pub sold: Money,
+    /// Money received in the conversion. Must be positive and in an other currency.
+    pub bought: Money,
+    /// Date and time at which the conversion occurred in a local timezone.
+    pub date_time: NaiveDateTime,
+    /// Associate this conversion with a broker. Not required.
+    pub brokerage: Option<BrokerType>,
+}
+
+impl From<CreateCurrencyConversion> for InsertCurrencyConversion {
+    fn from(val: CreateCurrencyConversion) -> Self {
+        InsertCurrencyConversion {
+            portfolio_id: val.portfolio_id,
+            report_upload_id: None,
+            currency_conversion: CurrencyConversion {
+                operation_source: OperationSource::Manual,
+                broker: val.brokerage,
+                external_id: None,
+                date_time: val.date_time,
+                rate: CurrencyConversion::implied_rate(&val.sold, &val.bought),
+                sold: val.sold,
+                bought: val.bought,
+                metadata: serde_json::Value::Null,
+            },
+        }
+    }
+}
+
+// --- validation
+
+struct CreateCurrencyConversionValidator { }
+
+impl CustomValidator<CreateCurrencyConversion> for CreateCurrencyConversionValidator {
+    fn check(&self, value: &CreateCurrencyConversion) -> Result<(), InputValueError<CreateCurrencyConversion>> {
+        if value.sold.amount <= rust_decimal::Decimal::ZERO {
+            Err(InputValueError::custom("sold.amount must be positive"))
+        } else if value.bought.amount <= rust_decimal::Decimal::ZERO {
+            Err(InputValueError::custom("bought.amount must be positive"))
+        } else if value.sold.currency == value.bought.currency {
+            Err(InputValueError::custom("sold and bought currencies must differ"))
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,19 @@
+use uuid::Uuid;
+
+use crate::{business::fiscal_transaction::model::SelectFiscalTransaction, web::errors::DescriptiveError, ApplicationState};
+
+
+/// Fiscal transactions of a portfolio together with both legs of every currency conversion,
+/// which together are every change of its cash apart from trades. The legs carry the id of
+/// their conversion.
+pub fn list_fiscal_transactions_with_conversions(state: &ApplicationState, portfolio_id: Uuid) -> Result<Vec<SelectFiscalTransaction>, DescriptiveError> {
+    let mut fiscal_transactions = state.repository.list_fiscal_transactions(portfolio_id)?;
+    for conversion in state.repository.list_currency_conversions(portfolio_id)? {
+        fiscal_transactions.extend(conversion.i.legs().into_iter().map(|leg| SelectFiscalTransaction {
+            id: conversion.id,
+            portfolio_id: conversion.portfolio_id,
+            i: leg,
+        }));
+    }
+    Ok(fiscal_transactions)
+}
@@ -1,12 +1,13 @@
-use chrono::NaiveDateTime;
+use chrono::{NaiveDate, NaiveDateTime};
 use diesel::{expression::AsExpression, pg::{Pg, PgValue}, Insertable, Selectable, Queryable};
 use diesel::deserialize::{FromSqlRow,FromSql};
 use diesel::serialize::{Output,ToSql};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use serde_enum_str::{Deserialize_enum_str,Serialize_enum_str};
 use uuid::Uuid;
 
-use crate::{business::model::{BrokerType, Money, OperationSource}, database::schema};
+use crate::{business::model::{BrokerType, FieldChange, Money, OperationSource}, database::schema};
 
 #[derive(Serialize,Deserialize,Insertable,Selectable,Queryable)]
 #[diesel(table_name = crate::database::schema::fiscal_transaction)]
@@ -21,10 +22,14 @@ pub struct FiscalTransaction {
     pub operation_type: FiscalTransactionType,
     pub commission: Option<Money>,
     pub metadata: serde_json::Value,
+    /// First day the security of a dividend trades without it, when the broker reports it
+    pub ex_date: Option<NaiveDate>,
+    /// Dividend paid for a single security, in the currency of the amount
+    pub amount_per_share: Option<Decimal>,
 }
 
 #[derive(Deserialize_enum_str, Serialize_enum_str)]
-#[derive(Debug, AsExpression, FromSqlRow)]
+#[derive(Debug, PartialEq, AsExpression, FromSqlRow)]
 #[diesel(sql_type = diesel::sql_types::Varchar)]
 pub enum FiscalTransactionType {
     Tax,
@@ -32,11 +37,32 @@ pub enum FiscalTransactionType {
     Commission,
     FundingWithdrawal,
     RevertedDividend,
+    Interest,
+    /// One side of a conversion of money between currencies, the other side is a separate transaction
+    CurrencyExchange,
+    Rollover,
+    CorporateAction,
     #[serde(other)]
     Unrecognized(String),
 }
 
 
+impl FiscalTransaction {
+    /// Fields which an import of this transaction changes in the already imported one
+    pub fn changes_from(&self, imported: &FiscalTransaction) -> Vec<FieldChange> {
+        [
+            FieldChange::of("dateTime", &imported.date_time, &self.date_time),
+            FieldChange::of("symbolId", &imported.symbol_id, &self.symbol_id),
+            FieldChange::of("amount", &imported.amount, &self.amount),
+            FieldChange::of("operationType", &imported.operation_type, &self.operation_type),
+            FieldChange::of("commission", &imported.commission, &self.commission),
+            FieldChange::of("metadata", &imported.metadata, &self.metadata),
+            FieldChange::of("exDate", &imported.ex_date, &self.ex_date),
+            FieldChange::of("amountPerShare", &imported.amount_per_share, &self.amount_per_share),
+        ].into_iter().flatten().collect()
+    }
+}
+
 // --- orm model
 
 #[derive(Deserialize, Insertable)]
@@ -30,6 +30,15 @@ impl CommonRepository {
             .optional()?)
     }
 
+    /// Transactions of the portfolio with the given external ids, of any operation source
+    pub fn list_fiscal_transactions_by_external_ids(&self, portfolio_id: Uuid, external_ids: &[String]) -> Result<Vec<SelectFiscalTransaction>, RepositoryError> {
+        Ok(dsl::fiscal_transaction
+            .filter(dsl::portfolio_id.eq(portfolio_id))
+            .filter(dsl::external_id.eq_any(external_ids))
+            .select(SelectFiscalTransaction::as_select())
+            .load(&mut self.pool.get()?)?)
+    }
+
     pub fn create_fiscal_transaction(&self, fiscal_transaction: InsertFiscalTransaction) -> Result<Uuid, RepositoryError> {
         Ok(diesel::insert_into(dsl::fiscal_transaction)
             .values(fiscal_transaction)
@@ -60,7 +69,7 @@ impl CommonRepository {
     pub fn create_fiscal_transactions(&self, fiscal_transactions: Vec<InsertFiscalTransaction>) -> Result<usize, RepositoryError> {
         Ok(insert_into(dsl::fiscal_transaction)
             .values(fiscal_transactions)
-            .on_conflict((dsl::operation_source, dsl::external_id))
+            .on_conflict((dsl::portfolio_id, dsl::operation_source, dsl::external_id))
             .do_update()
             .set((
                 dsl::report_upload_id.eq(excluded(dsl::report_upload_id)),
@@ -71,7 +80,9 @@ impl CommonRepository {
                 dsl::amount.eq(excluded(dsl::amount)),
                 dsl::operation_type.eq(excluded(dsl::operation_type)),
                 dsl::commission.eq(excluded(dsl::commission)),
-                dsl::metadata.eq(excluded(dsl::metadata))
+                dsl::metadata.eq(excluded(dsl::metadata)),
+                dsl::ex_date.eq(excluded(dsl::ex_date)),
+                dsl::amount_per_share.eq(excluded(dsl::amount_per_share)),
             ))
             .execute(&mut self.pool.get()?)?)
     }
@@ -78,6 +78,8 @@ impl From<CreateFiscalTransaction> for InsertFiscalTransaction {
                 operation_type: val.transaction_type.into(),
                 commission: None,
                 metadata: serde_json::Value::Null,
+                ex_date: None,
+                amount_per_share: None,
             },
         }
     }
@@ -0,0 +1,100 @@
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use quick_xml::{events::Event, Reader};
+use rust_decimal::Decimal;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::model::{FxRate, FxRateParsingError};
+
+/// Reference rates of the European Central Bank are quoted against euro
+pub const ECB_BASE_CURRENCY: &str = "EUR";
+pub const ECB_SOURCE: &str = "ecb";
+
+/// Parses reference rates in the format of `eurofxref-hist.xml` or `eurofxref-daily.xml`,
+/// where every `<Cube time="...">` contains `<Cube currency="..." rate="..."/>` of a single day.
+pub async fn parse_rates<R: AsyncRead + Unpin>(mut reader: R) -> Result<Vec<FxRate>, FxRateParsingError> {
+    let mut content = Vec::new();
+    reader.read_to_end(&mut content).await?;
+    parse_rates_xml(&content)
+}
+
+fn parse_rates_xml(content: &[u8]) -> Result<Vec<FxRate>, FxRateParsingError> {
+    let mut reader = Reader::from_reader(content);
+    reader.trim_text(true);
+
+    let mut rates = Vec::new();
+    let mut date: Option<NaiveDate> = None;
+    let mut buffer = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buffer)? {
+            Event::Start(element) | Event::Empty(element) if element.local_name().as_ref() == b"Cube" => {
+                let (mut time, mut currency, mut rate) = (None, None, None);
+                for attribute in element.attributes() {
+                    let attribute = attribute.map_err(quick_xml::Error::from)?;
+                    let value = attribute.unescape_value()?.into_owned();
+                    match attribute.key.local_name().as_ref() {
+                        b"time" => time = Some(value),
+                        b"currency" => currency = Some(value),
+                        b"rate" => rate = Some(value),
+                        _ => {},
+                    }
+                }
+                if let Some(time) = time {
+                    date = Some(NaiveDate::parse_from_str(&time, "%Y-%m-%d")
+                        .map_err(|_| FxRateParsingError::MalformedRate(format!("unknown date '{time}'")))?);
+                }
+                if let (Some(currency), Some(rate)) = (currency, rate) {
+                    let date = date.ok_or_else(|| FxRateParsingError::MalformedRate(format!("rate of {currency} has no date")))?;
+                    let rate = Decimal::from_str(&rate)
+                        .ok()
+                        .filter(|r| r.is_sign_positive() && !r.is_zero())
+                        .ok_or_else(|| FxRateParsingError::MalformedRate(format!("rate of {currency} on {date} is '{rate}'")))?;
+                    rates.push(FxRate {
+                        date,
+                        base_currency: ECB_BASE_CURRENCY.to_string(),
+                        quote_currency: currency,
+                        rate,
+                    });
+                }
+            },
+            Event::Eof => break,
+            _ => {},
+        }
+        buffer.clear();
+    }
+    Ok(rates)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_rates_of_every_day() {
+        let content = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gesmes:Envelope xmlns:gesmes="http://www.gesmes.org/xml/2002-08-01" xmlns="http://www.ecb.int/vocabulary/2002-08-01/eurofxref">
+    <gesmes:subject>Reference rates</gesmes:subject>
+    <gesmes:Sender><gesmes:name>European Central Bank</gesmes:name></gesmes:Sender>
+    <Cube>
+        <Cube time="2024-02-23"><Cube currency="USD" rate="1.0824"/><Cube currency="PLN" rate="4.3178"/></Cube>
+        <Cube time="2024-02-22"><Cube currency="USD" rate="1.0822"/></Cube>
+    </Cube>
+</gesmes:Envelope>"#;
+
+        let rates = parse_rates_xml(content.as_bytes()).unwrap();
+        let summary: Vec<(String, &str, Decimal)> = rates.iter()
+            .map(|r| (r.date.to_string(), r.quote_currency.as_str(), r.rate))
+            .collect();
+        assert_eq!(summary, vec![
+            ("2024-02-23".to_string(), "USD", Decimal::new(10824, 4)),
+            ("2024-02-23".to_string(), "PLN", Decimal::new(43178, 4)),
+            ("2024-02-22".to_string(), "USD", Decimal::new(10822, 4)),
+        ]);
+        assert!(rates.iter().all(|r| r.base_currency == "EUR"));
+
+        let malformed = r#"<Cube><Cube time="2024-02-23"><Cube currency="USD" rate="N/A"/></Cube></Cube>"#;
+        assert!(matches!(parse_rates_xml(malformed.as_bytes()), Err(FxRateParsingError::MalformedRate(_))));
+    }
+}
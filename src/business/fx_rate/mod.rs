@@ -0,0 +1,5 @@
+pub mod ecb;
+pub mod model;
+pub mod repository;
+pub mod resource;
+pub mod service;
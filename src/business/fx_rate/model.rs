@@ -0,0 +1,58 @@
+use chrono::NaiveDate;
+use diesel::{Insertable, Selectable, Queryable};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::database::schema;
+
+/// Amount of `quote_currency` that a single unit of `base_currency` was worth on a day
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Insertable, Selectable, Queryable)]
+#[diesel(table_name = crate::database::schema::fx_rate)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct FxRate {
+    pub date: NaiveDate,
+    pub base_currency: String,
+    pub quote_currency: String,
+    pub rate: Decimal,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum FxRateParsingError {
+    #[error(transparent)]
+    IO { #[from] source: std::io::Error },
+    #[error(transparent)]
+    Xml { #[from] source: quick_xml::Error },
+    #[error("Malformed exchange rate: {0}")]
+    MalformedRate(String),
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Exchange rate of {from} to {to} on {date} is not known")]
+pub struct MissingFxRateError {
+    pub from: String,
+    pub to: String,
+    pub date: NaiveDate,
+}
+
+// --- orm model
+
+#[derive(Deserialize, Insertable)]
+#[diesel(table_name = schema::fx_rate )]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct InsertFxRate {
+    #[diesel(embed)]
+    pub fx_rate: FxRate,
+    /// Name of the source the rate was imported from
+    pub source: String,
+}
+
+#[derive(Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = schema::fx_rate )]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct SelectFxRate {
+    pub id: Uuid,
+    #[diesel(embed)]
+    pub i: FxRate,
+    pub source: String,
+}
@@ -0,0 +1,49 @@
+use chrono::NaiveDate;
+use diesel::{insert_into, prelude::*, upsert::excluded};
+
+use crate::database::{schema::fx_rate::dsl, CommonRepository, RepositoryError};
+
+use super::model::{InsertFxRate, SelectFxRate};
+
+/// Keeps amount of bound parameters of a single statement within the limit of postgres
+const FX_RATES_PER_STATEMENT: usize = 10_000;
+
+impl CommonRepository {
+    /// Rates that have any of `currencies` as a base or a quote currency
+    pub fn list_fx_rates(&self, currencies: &[String], from: NaiveDate, to: NaiveDate) -> Result<Vec<SelectFxRate>, RepositoryError> {
+        Ok(dsl::fx_rate
+            .filter(dsl::base_currency.eq_any(currencies).or(dsl::quote_currency.eq_any(currencies)))
+            .filter(dsl::date.between(from, to))
+            .order(dsl::date)
+            .select(SelectFxRate::as_select())
+            .load(&mut self.pool.get()?)?)
+    }
+
+    /// The latest rate of every pair of currencies with any of `currencies` before `before`
+    pub fn list_latest_fx_rates(&self, currencies: &[String], before: NaiveDate) -> Result<Vec<SelectFxRate>, RepositoryError> {
+        Ok(dsl::fx_rate
+            .filter(dsl::base_currency.eq_any(currencies).or(dsl::quote_currency.eq_any(currencies)))
+            .filter(dsl::date.lt(before))
+            .distinct_on((dsl::base_currency, dsl::quote_currency))
+            .order((dsl::base_currency, dsl::quote_currency, dsl::date.desc()))
+            .select(SelectFxRate::as_select())
+            .load(&mut self.pool.get()?)?)
+    }
+
+    /// Inserts rates, replacing previously stored rates of the same currencies and day
+    pub fn upsert_fx_rates(&self, fx_rates: Vec<InsertFxRate>) -> Result<usize, RepositoryError> {
+        let mut connection = self.pool.get()?;
+        Ok(connection.transaction(|connection| {
+            let mut affected = 0;
+            for chunk in fx_rates.chunks(FX_RATES_PER_STATEMENT) {
+                affected += insert_into(dsl::fx_rate)
+                    .values(chunk)
+                    .on_conflict((dsl::base_currency, dsl::quote_currency, dsl::date))
+                    .do_update()
+                    .set((dsl::rate.eq(excluded(dsl::rate)), dsl::source.eq(excluded(dsl::source))))
+                    .execute(connection)?;
+            }
+            Ok::<usize, diesel::result::Error>(affected)
+        })?)
+    }
+}
@@ -0,0 +1,41 @@
+use async_graphql::{Context, Object, Upload, UploadValue};
+use chrono::NaiveDate;
+use tokio_util::compat::FuturesAsyncReadCompatExt;
+
+use crate::business::model::Money;
+use crate::web::graphql::{get_admin_claims, get_claims, get_state};
+
+use super::service::{convert_money, import_ecb_rates};
+
+
+#[derive(Default)]
+pub struct FxRateQuery;
+#[Object(rename_fields="camelCase", rename_args="camelCase")]
+impl FxRateQuery {
+    /// Convert money to another currency at the exchange rate of a day (today by default).
+    /// When no rate was published that day, the rate of the closest previous day is used.
+    async fn convert_money<'ctx>(&self, ctx: &Context<'ctx>, money: Money, currency: String, date: Option<NaiveDate>) -> async_graphql::Result<Money> {
+        get_claims(ctx)?;
+        let state = get_state(ctx)?;
+        let date = date.unwrap_or_else(|| chrono::Utc::now().date_naive());
+        Ok(convert_money(state, &money, &currency, date)?)
+    }
+}
+
+
+#[derive(Default)]
+pub struct FxRateMutation;
+#[Object(rename_fields="camelCase", rename_args="camelCase")]
+impl FxRateMutation {
+    /// Import euro reference rates of the European Central Bank from `eurofxref-hist.xml`
+    /// or `eurofxref-daily.xml`. Returns amount of imported rates. Rates are shared by every
+    /// user, so only admins may import them.
+    async fn upload_ecb_fx_rates(&self, ctx: &Context<'_>, upload: Upload) -> async_graphql::Result<usize> {
+        let upload_value: UploadValue = upload.value(ctx)?;
+        get_admin_claims(ctx)?;
+        let state = get_state(ctx)?;
+
+        let async_read = FuturesAsyncReadCompatExt::compat(upload_value.into_async_read());
+        Ok(import_ecb_rates(state, async_read).await?)
+    }
+}
@@ -0,0 +1,157 @@
+use std::{cell::Cell, collections::{BTreeMap, HashMap}};
+
+use chrono::{Duration, NaiveDate};
+use rust_decimal::Decimal;
+
+use crate::{business::model::Money, web::errors::DescriptiveError, ApplicationState};
+
+use super::{ecb::{parse_rates, ECB_SOURCE}, model::{FxRate, InsertFxRate, MissingFxRateError}};
+
+/// Rates are not published on weekends and holidays, so the rate of the closest previous
+/// day is used instead. An older rate is used as well when nothing newer is stored, but
+/// conversions with it are considered stale.
+const MAX_RATE_AGE_DAYS: i64 = 7;
+
+/// Stores reference rates of the European Central Bank. Returns amount of imported rates.
+pub async fn import_ecb_rates<R: tokio::io::AsyncRead + Unpin>(state: &ApplicationState, reader: R) -> Result<usize, DescriptiveError> {
+    let rates = parse_rates(reader).await
+        .map_err(|e| DescriptiveError::BadRequest(e.to_string()))?;
+    Ok(state.repository.upsert_fx_rates(rates.into_iter()
+        .map(|fx_rate| InsertFxRate { fx_rate, source: ECB_SOURCE.to_string() })
+        .collect())?)
+}
+
+/// Converts `money` to `currency` at the rate of `date`, or of the closest previous day with a known rate
+pub fn convert_money(state: &ApplicationState, money: &Money, currency: &str, date: NaiveDate) -> Result<Money, DescriptiveError> {
+    let book = FxRateBook::load(state, &[money.currency.clone(), currency.to_string()], date, date)?;
+    let amount = book.try_convert(money, currency, date)?;
+    Ok(Money::new(amount, currency.to_string()))
+}
+
+/// Stored exchange rates, looked up by a pair of currencies and a day. Remembers the oldest
+/// stale rate any conversion had to use.
+#[derive(Default)]
+pub struct FxRateBook {
+    rates: HashMap<(String, String), BTreeMap<NaiveDate, Decimal>>,
+    stale_since: Cell<Option<NaiveDate>>,
+}
+
+impl FxRateBook {
+    /// Loads rates between `currencies` and every currency they are quoted against, that are
+    /// needed for conversions within `from` and `to`, and the latest rates before them
+    pub fn load(state: &ApplicationState, currencies: &[String], from: NaiveDate, to: NaiveDate) -> Result<Self, DescriptiveError> {
+        let since = from - Duration::days(MAX_RATE_AGE_DAYS);
+        let latest_before = state.repository.list_latest_fx_rates(currencies, since)?;
+        let rates = state.repository.list_fx_rates(currencies, since, to)?;
+        Ok(latest_before.into_iter().chain(rates).map(|r| r.i).collect())
+    }
+
+    /// Rate of the closest day not after `date`, and that day
+    fn direct_rate(&self, base_currency: &str, quote_currency: &str, date: NaiveDate) -> Option<(NaiveDate, Decimal)> {
+        self.rates.get(&(base_currency.to_string(), quote_currency.to_string()))?
+            .range(..=date)
+            .next_back()
+            .map(|(day, rate)| (*day, *rate))
+    }
+
+    fn dated_rate(&self, base_currency: &str, quote_currency: &str, date: NaiveDate) -> Option<(NaiveDate, Decimal)> {
+        if base_currency == quote_currency {
+            return Some((date, Decimal::ONE));
+        }
+        if let Some(rate) = self.direct_rate(base_currency, quote_currency, date) {
+            return Some(rate);
+        }
+        if let Some((day, rate)) = self.direct_rate(quote_currency, base_currency, date) {
+            return Some((day, Decimal::ONE / rate));
+        }
+        self.rates.keys()
+            .filter(|(pivot, quote)| quote == base_currency && pivot != quote_currency)
+            .find_map(|(pivot, _)| {
+                let (base_day, to_base) = self.direct_rate(pivot, base_currency, date)?;
+                let (quote_day, to_quote) = self.direct_rate(pivot, quote_currency, date)?;
+                Some((base_day.min(quote_day), to_quote / to_base))
+            })
+    }
+
+    /// Amount of `quote_currency` a unit of `base_currency` was worth on `date`. Uses
+    /// a direct rate, an inverse one, or a cross rate through a common currency.
+    pub fn rate(&self, base_currency: &str, quote_currency: &str, date: NaiveDate) -> Option<Decimal> {
+        let (day, rate) = self.dated_rate(base_currency, quote_currency, date)?;
+        if day < date - Duration::days(MAX_RATE_AGE_DAYS) {
+            self.stale_since.set(Some(self.stale_since.get().map_or(day, |since| since.min(day))));
+        }
+        Some(rate)
+    }
+
+    /// Day of the oldest rate used by conversions, which was published more than
+    /// `MAX_RATE_AGE_DAYS` before the day of its conversion
+    pub fn stale_since(&self) -> Option<NaiveDate> {
+        self.stale_since.get()
+    }
+
+    pub fn convert(&self, money: &Money, currency: &str, date: NaiveDate) -> Option<Money> {
+        self.rate(&money.currency, currency, date)
+            .map(|rate| Money::new((money.amount * rate).round_dp(8).normalize(), currency.to_string()))
+    }
+
+    /// Amount of `money` in `currency`, failing when the exchange rate is not known
+    pub fn try_convert(&self, money: &Money, currency: &str, date: NaiveDate) -> Result<Decimal, MissingFxRateError> {
+        self.convert(money, currency, date)
+            .map(|converted| converted.amount)
+            .ok_or_else(|| MissingFxRateError { from: money.currency.clone(), to: currency.to_string(), date })
+    }
+}
+
+impl FromIterator<FxRate> for FxRateBook {
+    fn from_iter<T: IntoIterator<Item = FxRate>>(rates: T) -> Self {
+        let mut book = FxRateBook::default();
+        for rate in rates {
+            book.rates
+                .entry((rate.base_currency, rate.quote_currency))
+                .or_default()
+                .insert(rate.date, rate.rate);
+        }
+        book
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rate(day: u32, quote_currency: &str, rate: i64) -> FxRate {
+        FxRate {
+            date: NaiveDate::from_ymd_opt(2024, 2, day).unwrap(),
+            base_currency: "EUR".to_string(),
+            quote_currency: quote_currency.to_string(),
+            rate: Decimal::new(rate, 2),
+        }
+    }
+
+    #[test]
+    fn converts_through_common_currency_with_previous_day_fallback() {
+        let book: FxRateBook = [rate(1, "USD", 110), rate(2, "USD", 120), rate(2, "PLN", 480)].into_iter().collect();
+        let date = |day| NaiveDate::from_ymd_opt(2024, 2, day).unwrap();
+        let usd = |amount| Money::new(Decimal::from(amount), "USD".to_string());
+
+        assert_eq!(book.convert(&usd(11), "EUR", date(1)), Some(Money::new(Decimal::from(10), "EUR".to_string())));
+        // saturday and sunday use the rate of friday
+        assert_eq!(book.convert(&usd(12), "EUR", date(4)), Some(Money::new(Decimal::from(10), "EUR".to_string())));
+        assert_eq!(book.convert(&usd(3), "PLN", date(2)), Some(Money::new(Decimal::from(12), "PLN".to_string())));
+        assert_eq!(book.convert(&usd(3), "USD", date(2)), Some(usd(3)));
+        // no rate of PLN was published before
+        assert_eq!(book.convert(&usd(3), "PLN", date(1)), None);
+        assert_eq!(book.stale_since(), None);
+    }
+
+    #[test]
+    fn falls_back_to_stale_rates() {
+        let book: FxRateBook = [rate(1, "USD", 110), rate(2, "USD", 120)].into_iter().collect();
+        let date = |day| NaiveDate::from_ymd_opt(2024, 2, day).unwrap();
+
+        assert_eq!(book.convert(&Money::new(Decimal::from(12), "USD".to_string()), "EUR", date(20)),
+            Some(Money::new(Decimal::from(10), "EUR".to_string())));
+        assert_eq!(book.stale_since(), Some(date(2)));
+    }
+}
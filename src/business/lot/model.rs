@@ -0,0 +1,54 @@
+use diesel::{Insertable, Selectable, Queryable};
+use serde::{Deserialize, Serialize};
+use serde_enum_str::{Deserialize_enum_str,Serialize_enum_str};
+use uuid::Uuid;
+
+use crate::{business::model::Money, database::schema};
+
+/// Method of matching sold securities against previously bought lots
+#[derive(Deserialize_enum_str, Serialize_enum_str)]
+#[derive(diesel_derive_enum::DbEnum, Debug, async_graphql::Enum, Copy, Clone, Eq, PartialEq)]
+#[ExistingTypePath = "crate::database::schema::sql_types::CostBasisMethodType"]
+pub enum CostBasisMethod {
+    /// First in, first out: the oldest lots are sold first
+    Fifo,
+    /// Last in, first out: the newest lots are sold first
+    Lifo,
+    /// Every sold security costs the weighted average price of all held securities
+    AverageCost,
+    /// Lots are chosen manually for every sell, the rest is matched first in, first out
+    SpecificLot,
+}
+
+// --- orm model
+
+#[derive(Deserialize, Insertable, Debug, PartialEq)]
+#[diesel(table_name = schema::lot_assignment )]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct InsertLotAssignment {
+    pub portfolio_id: Uuid,
+    pub sell_trade_operation_id: Uuid,
+    /// Purchase that opened the lot, unless it was opened by a transfer
+    pub buy_trade_operation_id: Option<Uuid>,
+    /// Securities transferred in that opened the lot
+    pub buy_security_transfer_id: Option<Uuid>,
+    pub quantity: i32,
+    pub cost_basis: Money,
+    pub manual: bool,
+}
+
+#[derive(Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = schema::lot_assignment )]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct SelectLotAssignment {
+    pub id: Uuid,
+    pub portfolio_id: Uuid,
+    pub sell_trade_operation_id: Uuid,
+    /// Purchase that opened the lot, unless it was opened by a transfer
+    pub buy_trade_operation_id: Option<Uuid>,
+    /// Securities transferred in that opened the lot
+    pub buy_security_transfer_id: Option<Uuid>,
+    pub quantity: i32,
+    pub cost_basis: Money,
+    pub manual: bool,
+}
@@ -0,0 +1,41 @@
+use diesel::{insert_into, prelude::*};
+use uuid::Uuid;
+
+use crate::database::{schema::lot_assignment::dsl, CommonRepository, RepositoryError};
+
+use super::model::{InsertLotAssignment, SelectLotAssignment};
+
+impl CommonRepository {
+    pub fn list_lot_assignments(&self, portfolio_id: Uuid) -> Result<Vec<SelectLotAssignment>, RepositoryError> {
+        Ok(dsl::lot_assignment
+            .filter(dsl::portfolio_id.eq(portfolio_id))
+            .select(SelectLotAssignment::as_select())
+            .load(&mut self.pool.get()?)?)
+    }
+
+    /// Atomically replaces all lot assignments of a portfolio
+    pub fn replace_lot_assignments(&self, portfolio_id: Uuid, lot_assignments: Vec<InsertLotAssignment>) -> Result<usize, RepositoryError> {
+        let mut connection = self.pool.get()?;
+        Ok(connection.transaction(|connection| {
+            diesel::delete(dsl::lot_assignment
+                .filter(dsl::portfolio_id.eq(portfolio_id)))
+                .execute(connection)?;
+            insert_into(dsl::lot_assignment)
+                .values(lot_assignments)
+                .execute(connection)
+        })?)
+    }
+
+    /// Atomically replaces lot assignments of a single sell trade operation
+    pub fn replace_sell_lot_assignments(&self, sell_trade_operation_id: Uuid, lot_assignments: Vec<InsertLotAssignment>) -> Result<usize, RepositoryError> {
+        let mut connection = self.pool.get()?;
+        Ok(connection.transaction(|connection| {
+            diesel::delete(dsl::lot_assignment
+                .filter(dsl::sell_trade_operation_id.eq(sell_trade_operation_id)))
+                .execute(connection)?;
+            insert_into(dsl::lot_assignment)
+                .values(lot_assignments)
+                .execute(connection)
+        })?)
+    }
+}
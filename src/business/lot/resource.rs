@@ -0,0 +1,242 @@
+use async_graphql::{Context, CustomValidator, InputObject, InputValueError, Object, SimpleObject};
+use chrono::NaiveDateTime;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::business::model::{BrokerType, Money};
+use crate::business::portfolio::security::is_portfolio_owner;
+use crate::business::trade_operation::model::TradeOperationSide;
+use crate::web::errors::DescriptiveError;
+use crate::web::graphql::{get_claims, get_state};
+
+use super::model::{CostBasisMethod, InsertLotAssignment, SelectLotAssignment};
+use super::service::recalculate_lot_assignments;
+
+
+#[derive(Default)]
+pub struct LotMutation;
+#[Object(rename_fields="camelCase", rename_args="camelCase")]
+impl LotMutation {
+    /// Change the method of matching sells against bought lots within a portfolio.
+    /// Switching away from SPECIFIC_LOT discards manually chosen lots.
+    async fn set_cost_basis_method(&self, ctx: &Context<'_>, portfolio_id: Uuid, method: CostBasisMethod) -> async_graphql::Result<CostBasisMethod> {
+        let claims = get_claims(ctx)?;
+        let state = get_state(ctx)?;
+
+        is_portfolio_owner(state, claims.sub, portfolio_id)?;
+        state.repository.update_portfolio_cost_basis_method(portfolio_id, method)?;
+        recalculate_lot_assignments(state, portfolio_id)?;
+        Ok(method)
+    }
+
+    /// Choose lots sold by a sell trade operation. Requires a portfolio with SPECIFIC_LOT
+    /// cost basis method. Securities that are not covered by chosen lots are matched
+    /// first in, first out. Returns resulting lots of the sell trade operation.
+    async fn assign_lots(
+        &self,
+        ctx: &Context<'_>,
+        sell_trade_operation_id: Uuid,
+        #[graphql(validator(custom = "LotSelectionValidator{}"))]
+        lots: Vec<LotSelection>,
+    ) -> async_graphql::Result<Vec<LotAssignment>> {
+        let claims = get_claims(ctx)?;
+        let state = get_state(ctx)?;
+
+        let sell = state.repository.find_trade_operation_by_id(sell_trade_operation_id)?
+            .ok_or(DescriptiveError::NotFound { resource: "trade operation".to_string() })?;
+        is_portfolio_owner(state, claims.sub, sell.portfolio_id)?;
+        let portfolio = state.repository.find_portfolio_by_id(sell.portfolio_id)?
+            .ok_or(DescriptiveError::NotFound { resource: "portfolio".to_string() })?;
+
+        if portfolio.cost_basis_method != CostBasisMethod::SpecificLot {
+            return Err(DescriptiveError::BadRequest("lots can only be chosen in a portfolio with SPECIFIC_LOT cost basis method".to_string()).into());
+        }
+        if sell.i.side != TradeOperationSide::Sell {
+            return Err(DescriptiveError::BadRequest("lots can only be chosen for a SELL trade operation".to_string()).into());
+        }
+        // summed wider than the quantities themselves, so that large ones don't overflow
+        if lots.iter().map(|l| i64::from(l.quantity)).sum::<i64>() > i64::from(sell.i.quantity) {
+            return Err(DescriptiveError::BadRequest("chosen lots contain more securities than were sold".to_string()).into());
+        }
+
+        let mut manual_assignments = Vec::with_capacity(lots.len());
+        for lot in lots {
+            let buy = state.repository.find_trade_operation_by_id(lot.buy_trade_operation_id)?
+                .filter(|b| b.portfolio_id == sell.portfolio_id)
+                .ok_or(DescriptiveError::NotFound { resource: "trade operation".to_string() })?;
+            if buy.i.side != TradeOperationSide::Buy
+                || buy.i.instrument_symbol != sell.i.instrument_symbol
+                || buy.i.summ.currency != sell.i.summ.currency
+                || buy.i.date_time > sell.i.date_time
+            {
+                return Err(DescriptiveError::BadRequest(format!(
+                    "trade operation {} is not a purchase of the same instrument made before the sell", buy.id
+                )).into());
+            }
+            manual_assignments.push(InsertLotAssignment {
+                portfolio_id: sell.portfolio_id,
+                sell_trade_operation_id,
+                buy_trade_operation_id: Some(buy.id),
+                buy_security_transfer_id: None,
+                quantity: lot.quantity,
+                cost_basis: Money::new(buy.i.summ.amount / Decimal::from(buy.i.quantity) * Decimal::from(lot.quantity), buy.i.summ.currency),
+                manual: true,
+            });
+        }
+
+        state.repository.replace_sell_lot_assignments(sell_trade_operation_id, manual_assignments)?;
+        recalculate_lot_assignments(state, sell.portfolio_id)?;
+        Ok(state.repository.list_lot_assignments(sell.portfolio_id)?
+            .into_iter()
+            .filter(|a| a.sell_trade_operation_id == sell_trade_operation_id)
+            .map(LotAssignment::from)
+            .collect())
+    }
+}
+
+// --- model
+
+/// Part of a sell trade operation that closed securities of a single buy trade operation,
+/// or of a single transfer of securities into the portfolio
+#[derive(Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct LotAssignment {
+    pub sell_trade_operation_id: Uuid,
+    pub buy_trade_operation_id: Option<Uuid>,
+    pub buy_security_transfer_id: Option<Uuid>,
+    /// Amount of sold securities that were taken from the bought lot.
+    pub quantity: i32,
+    /// Purchase cost of the sold securities, according to the cost basis method.
+    pub cost_basis: Money,
+    /// True when the lot was chosen manually.
+    pub manual: bool,
+}
+
+impl From<SelectLotAssignment> for LotAssignment {
+    fn from(value: SelectLotAssignment) -> Self {
+        LotAssignment {
+            sell_trade_operation_id: value.sell_trade_operation_id,
+            buy_trade_operation_id: value.buy_trade_operation_id,
+            buy_security_transfer_id: value.buy_security_transfer_id,
+            quantity: value.quantity,
+            cost_basis: value.cost_basis,
+            manual: value.manual,
+        }
+    }
+}
+
+/// Profit or loss realized by a sell trade operation, according to its lot assignments
+#[derive(Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct RealizedTrade {
+    pub sell_trade_operation_id: Uuid,
+    pub ticker: String,
+    pub brokerage: Option<BrokerType>,
+    pub date_time: NaiveDateTime,
+    /// Amount of sold securities that were matched to known purchases.
+    pub quantity: i32,
+    /// Amount of sold securities without a known purchase. These are not accounted for.
+    pub unmatched_quantity: i32,
+    /// Amount of currency received for the matched securities.
+    pub proceeds: Money,
+    pub cost_basis: Money,
+    /// Commissions of the sell and of the matched purchases.
+    pub commission: Money,
+    /// Proceeds without cost basis and commissions.
+    pub realized_pnl: Money,
+    /// Profit or loss of the trade as reported by the broker, when available.
+    pub reported_pnl: Option<Money>,
+}
+
+#[derive(Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct TickerRealizedPnl {
+    pub ticker: String,
+    pub realized_pnl: Money,
+    /// Sum of profits or losses reported by brokers, for trades where one was reported.
+    pub reported_pnl: Option<Money>,
+}
+
+#[derive(Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct YearRealizedPnl {
+    pub year: i32,
+    pub realized_pnl: Money,
+    /// Sum of profits or losses reported by brokers, for trades where one was reported.
+    pub reported_pnl: Option<Money>,
+}
+
+#[derive(Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct BrokerageRealizedPnl {
+    pub brokerage: Option<BrokerType>,
+    pub realized_pnl: Money,
+    /// Sum of profits or losses reported by brokers, for trades where one was reported.
+    pub reported_pnl: Option<Money>,
+}
+
+/// Realized profit or loss of a portfolio. Every sum is split by currency.
+#[derive(Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct RealizedPnl {
+    pub trades: Vec<RealizedTrade>,
+    pub by_ticker: Vec<TickerRealizedPnl>,
+    /// Grouped by the year of a sell.
+    pub by_year: Vec<YearRealizedPnl>,
+    pub by_brokerage: Vec<BrokerageRealizedPnl>,
+}
+
+#[derive(InputObject, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LotSelection {
+    /// Buy trade operation that opened the lot.
+    pub buy_trade_operation_id: Uuid,
+    /// Amount of securities sold from this lot. Must be a positive whole number,
+    /// fractions of a security are refused rather than rounded.
+    pub quantity: i32,
+}
+
+// --- validation
+
+struct LotSelectionValidator { }
+
+impl CustomValidator<Vec<LotSelection>> for LotSelectionValidator {
+    fn check(&self, value: &Vec<LotSelection>) -> Result<(), InputValueError<Vec<LotSelection>>> {
+        if value.iter().any(|l| l.quantity <= 0) {
+            Err(InputValueError::custom("quantity of every lot must be positive"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use async_graphql::{EmptySubscription, Schema};
+
+    use super::*;
+
+    struct Query;
+    #[Object]
+    impl Query {
+        async fn version(&self) -> &str {
+            ""
+        }
+    }
+
+    async fn assign_lot_errors(quantity: &str) -> Vec<String> {
+        let schema = Schema::new(Query, LotMutation, EmptySubscription);
+        let request = format!(r#"mutation {{
+            assignLots(sellTradeOperationId: "{id}", lots: [{{ buyTradeOperationId: "{id}", quantity: {quantity} }}]) {{ quantity }}
+        }}"#, id = Uuid::nil());
+        schema.execute(request).await.errors.into_iter().map(|e| e.message).collect()
+    }
+
+    #[tokio::test]
+    async fn refuses_lot_quantities_which_are_not_positive_whole_numbers() {
+        assert_eq!(assign_lot_errors("1.5").await, vec![r#"Invalid value for argument "lots.0.quantity", expected type "Int""#]);
+        let errors = assign_lot_errors("0").await;
+        assert!(errors.len() == 1 && errors[0].contains("quantity of every lot must be positive"), "{errors:?}");
+    }
+}
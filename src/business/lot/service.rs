@@ -0,0 +1,509 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{Datelike, NaiveDateTime};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+use crate::{business::{model::{BrokerType, Money}, security_transfer::model::{SelectSecurityTransfer, TransferDirection}, trade_operation::model::{SelectTradeOperation, TradeOperationSide}}, web::errors::DescriptiveError, ApplicationState};
+
+use super::model::{CostBasisMethod, InsertLotAssignment, SelectLotAssignment};
+use super::resource::{BrokerageRealizedPnl, RealizedPnl, RealizedTrade, TickerRealizedPnl, YearRealizedPnl};
+
+
+/// Matches all sells of a portfolio against its buys and securities transferred in with the
+/// cost basis method chosen for the portfolio, and persists the result. Manually chosen lots
+/// are preserved.
+pub fn recalculate_lot_assignments(state: &ApplicationState, portfolio_id: Uuid) -> Result<usize, DescriptiveError> {
+    let portfolio = state.repository.find_portfolio_by_id(portfolio_id)?
+        .ok_or(DescriptiveError::NotFound { resource: "portfolio".to_string() })?;
+    let trade_operations = state.repository.list_trade_operations(portfolio_id)?;
+    let security_transfers = state.repository.list_security_transfers(portfolio_id)?;
+    let manual_assignments: Vec<SelectLotAssignment> = state.repository.list_lot_assignments(portfolio_id)?
+        .into_iter()
+        .filter(|a| a.manual)
+        .collect();
+
+    let lot_assignments = match_lots(portfolio_id, &trade_operations, &security_transfers, portfolio.cost_basis_method, &manual_assignments);
+    Ok(state.repository.replace_lot_assignments(portfolio_id, lot_assignments)?)
+}
+
+/// Operation that opened a lot
+#[derive(Clone, Copy, PartialEq)]
+enum LotOrigin {
+    Purchase(Uuid),
+    Transfer(Uuid),
+}
+
+struct OpenLot {
+    origin: LotOrigin,
+    remaining: i32,
+    unit_cost: Decimal,
+}
+
+/// Lots of a single instrument traded in a single currency, in the order of purchase
+#[derive(Default)]
+struct LotPool {
+    lots: Vec<OpenLot>,
+    quantity: i32,
+    total_cost: Decimal,
+}
+
+impl LotPool {
+    fn open(&mut self, origin: LotOrigin, quantity: i32, cost: Decimal) {
+        if quantity <= 0 {
+            return;
+        }
+        self.lots.push(OpenLot { origin, remaining: quantity, unit_cost: cost / Decimal::from(quantity) });
+        self.quantity += quantity;
+        self.total_cost += cost;
+    }
+
+    /// Removes securities from a lot and returns their cost basis
+    fn take(&mut self, index: usize, quantity: i32, method: CostBasisMethod) -> Decimal {
+        let lot = &mut self.lots[index];
+        let cost = match method {
+            CostBasisMethod::AverageCost => self.total_cost / Decimal::from(self.quantity) * Decimal::from(quantity),
+            _ => lot.unit_cost * Decimal::from(quantity),
+        };
+        lot.remaining -= quantity;
+        self.quantity -= quantity;
+        self.total_cost -= cost;
+        cost
+    }
+
+    /// Removes securities which leave the portfolio without being sold, and returns their cost basis
+    fn release(&mut self, quantity: i32, method: CostBasisMethod) -> Decimal {
+        let mut unreleased = quantity;
+        let mut cost = Decimal::ZERO;
+        while unreleased > 0 {
+            let Some(index) = self.next_lot(method) else { break };
+            let quantity = self.lots[index].remaining.min(unreleased);
+            cost += self.take(index, quantity, method);
+            unreleased -= quantity;
+        }
+        cost
+    }
+
+    fn next_lot(&self, method: CostBasisMethod) -> Option<usize> {
+        match method {
+            CostBasisMethod::Lifo => self.lots.iter().rposition(|l| l.remaining > 0),
+            _ => self.lots.iter().position(|l| l.remaining > 0),
+        }
+    }
+}
+
+enum LotChange<'a> {
+    Trade(&'a SelectTradeOperation),
+    Transfer(&'a SelectSecurityTransfer),
+}
+
+impl LotChange<'_> {
+    /// Within the same moment old securities of a split are replaced with new ones first,
+    /// and lots are opened before securities are taken from them
+    fn order(&self) -> (NaiveDateTime, u8) {
+        match self {
+            LotChange::Transfer(t) if t.i.is_split => (t.i.date_time, if t.i.direction == TransferDirection::Out { 0 } else { 1 }),
+            LotChange::Trade(o) => (o.i.date_time, if o.i.side == TradeOperationSide::Buy { 2 } else { 3 }),
+            LotChange::Transfer(t) => (t.i.date_time, if t.i.direction == TransferDirection::In { 2 } else { 3 }),
+        }
+    }
+}
+
+/// Replays trade operations and security transfers in chronological order and assigns every
+/// sold security to a lot bought or transferred in before it. Securities sold without a known
+/// origin stay unassigned. Securities transferred in open a lot at their carried-over cost basis,
+/// and join a pool of the same instrument when their currency isn't known. Securities transferred
+/// out close lots without an assignment. A split replaces lots of the old securities with a lot
+/// of the new ones, which carries over their cost.
+/// Manual assignments are only honoured with `CostBasisMethod::SpecificLot`.
+pub fn match_lots(
+    portfolio_id: Uuid,
+    trade_operations: &[SelectTradeOperation],
+    security_transfers: &[SelectSecurityTransfer],
+    method: CostBasisMethod,
+    manual_assignments: &[SelectLotAssignment],
+) -> Vec<InsertLotAssignment> {
+    let mut changes: Vec<LotChange> = trade_operations.iter().map(LotChange::Trade)
+        .chain(security_transfers.iter().map(LotChange::Transfer))
+        .collect();
+    changes.sort_by_key(|c| c.order());
+
+    let mut pools: BTreeMap<(String, String), LotPool> = BTreeMap::new();
+    // cost of securities replaced by a split, by the moment of the split and the currency
+    let mut split_costs: BTreeMap<(NaiveDateTime, String), Decimal> = BTreeMap::new();
+    let mut lot_assignments = Vec::new();
+    for change in changes {
+        let operation = match change {
+            LotChange::Trade(operation) => operation,
+            LotChange::Transfer(transfer) => {
+                let currency = transfer.i.cost_basis.as_ref()
+                    .map(|cost_basis| cost_basis.currency.clone())
+                    .or_else(|| pools.keys()
+                        .find(|(ticker, _)| *ticker == transfer.i.instrument_symbol)
+                        .map(|(_, currency)| currency.clone()))
+                    .or_else(|| split_costs.keys()
+                        .find(|(date_time, _)| transfer.i.is_split && *date_time == transfer.i.date_time)
+                        .map(|(_, currency)| currency.clone()));
+                let Some(currency) = currency else { continue };
+                let cost_basis = transfer.i.cost_basis.as_ref().map(|c| c.amount).unwrap_or_default();
+                let pool = pools.entry((transfer.i.instrument_symbol.clone(), currency.clone())).or_default();
+                match transfer.i.direction {
+                    TransferDirection::In => {
+                        let carried_cost = if transfer.i.is_split {
+                            split_costs.remove(&(transfer.i.date_time, currency)).unwrap_or_default()
+                        } else {
+                            Decimal::ZERO
+                        };
+                        pool.open(LotOrigin::Transfer(transfer.id), transfer.i.quantity, cost_basis + carried_cost);
+                    },
+                    TransferDirection::Out => {
+                        let cost = pool.release(transfer.i.quantity, method);
+                        if transfer.i.is_split {
+                            *split_costs.entry((transfer.i.date_time, currency)).or_default() += cost;
+                        }
+                    },
+                }
+                continue;
+            },
+        };
+
+        let currency = operation.i.summ.currency.clone();
+        let pool = pools.entry((operation.i.instrument_symbol.clone(), currency.clone())).or_default();
+        match operation.i.side {
+            TradeOperationSide::Buy => pool.open(LotOrigin::Purchase(operation.id), operation.i.quantity, operation.i.summ.amount),
+            TradeOperationSide::Sell => {
+                let mut unmatched = operation.i.quantity;
+                let mut assign = |pool: &mut LotPool, index: usize, quantity: i32, manual: bool| {
+                    let (buy_trade_operation_id, buy_security_transfer_id) = match pool.lots[index].origin {
+                        LotOrigin::Purchase(id) => (Some(id), None),
+                        LotOrigin::Transfer(id) => (None, Some(id)),
+                    };
+                    let cost = pool.take(index, quantity, method);
+                    lot_assignments.push(InsertLotAssignment {
+                        portfolio_id,
+                        sell_trade_operation_id: operation.id,
+                        buy_trade_operation_id,
+                        buy_security_transfer_id,
+                        quantity,
+                        cost_basis: Money::new(cost.normalize(), currency.clone()),
+                        manual,
+                    });
+                };
+
+                if method == CostBasisMethod::SpecificLot {
+                    for manual in manual_assignments.iter().filter(|a| a.sell_trade_operation_id == operation.id) {
+                        let Some(index) = pool.lots.iter().position(|l| match l.origin {
+                            LotOrigin::Purchase(id) => manual.buy_trade_operation_id == Some(id),
+                            LotOrigin::Transfer(id) => manual.buy_security_transfer_id == Some(id),
+                        }) else {
+                            continue;
+                        };
+                        let quantity = manual.quantity.min(pool.lots[index].remaining).min(unmatched);
+                        if quantity > 0 {
+                            assign(pool, index, quantity, true);
+                            unmatched -= quantity;
+                        }
+                    }
+                }
+                while unmatched > 0 {
+                    let Some(index) = pool.next_lot(method) else { break };
+                    let quantity = pool.lots[index].remaining.min(unmatched);
+                    assign(pool, index, quantity, false);
+                    unmatched -= quantity;
+                }
+            },
+        }
+    }
+    lot_assignments
+}
+
+pub fn calculate_realized_pnl(state: &ApplicationState, portfolio_id: Uuid) -> Result<RealizedPnl, DescriptiveError> {
+    let trade_operations = state.repository.list_trade_operations(portfolio_id)?;
+    let lot_assignments = state.repository.list_lot_assignments(portfolio_id)?;
+    Ok(fold_realized_pnl(&trade_operations, &lot_assignments))
+}
+
+fn share_of(amount: Decimal, part: i32, whole: i32) -> Decimal {
+    if whole == 0 { Decimal::ZERO } else { amount * Decimal::from(part) / Decimal::from(whole) }
+}
+
+/// Sums of realized profit or loss within a group of sells, per currency
+#[derive(Default)]
+struct RealizedPnlAccumulator {
+    realized_pnl: Decimal,
+    reported_pnl: Option<Decimal>,
+}
+
+impl RealizedPnlAccumulator {
+    fn add(&mut self, trade: &RealizedTrade) {
+        self.realized_pnl += trade.realized_pnl.amount;
+        if let Some(reported) = trade.reported_pnl.as_ref() {
+            *self.reported_pnl.get_or_insert(Decimal::ZERO) += reported.amount;
+        }
+    }
+
+    fn into_money(self, currency: String) -> (Money, Option<Money>) {
+        (
+            Money::new(self.realized_pnl.normalize(), currency.clone()),
+            self.reported_pnl.map(|reported| Money::new(reported.normalize(), currency)),
+        )
+    }
+}
+
+/// Calculates realized profit or loss of every sell from its lot assignments. Proceeds and
+/// commissions are only accounted for the part of a sell that was matched to known purchases.
+/// Commissions in a currency other than the trade currency can't be accounted for yet.
+pub fn fold_realized_pnl(trade_operations: &[SelectTradeOperation], lot_assignments: &[SelectLotAssignment]) -> RealizedPnl {
+    let trade_operations_by_id: HashMap<Uuid, &SelectTradeOperation> = trade_operations.iter()
+        .map(|o| (o.id, o))
+        .collect();
+    let mut sells: Vec<&SelectTradeOperation> = trade_operations.iter()
+        .filter(|o| o.i.side == TradeOperationSide::Sell)
+        .collect();
+    sells.sort_by_key(|o| o.i.date_time);
+
+    let commission_of = |operation: &SelectTradeOperation| operation.i.commission.as_ref()
+        .filter(|c| c.currency == operation.i.summ.currency)
+        .map(|c| c.amount.abs())
+        .unwrap_or(Decimal::ZERO);
+
+    let mut trades = Vec::with_capacity(sells.len());
+    for sell in sells {
+        let currency = sell.i.summ.currency.clone();
+        let mut quantity = 0;
+        let mut cost_basis = Decimal::ZERO;
+        let mut commission = Decimal::ZERO;
+        for assignment in lot_assignments.iter().filter(|a| a.sell_trade_operation_id == sell.id) {
+            quantity += assignment.quantity;
+            cost_basis += assignment.cost_basis.amount;
+            if let Some(buy) = assignment.buy_trade_operation_id.and_then(|id| trade_operations_by_id.get(&id)) {
+                commission += share_of(commission_of(buy), assignment.quantity, buy.i.quantity);
+            }
+        }
+        commission += share_of(commission_of(sell), quantity, sell.i.quantity);
+        let proceeds = share_of(sell.i.summ.amount, quantity, sell.i.quantity);
+
+        trades.push(RealizedTrade {
+            sell_trade_operation_id: sell.id,
+            ticker: sell.i.instrument_symbol.clone(),
+            brokerage: sell.i.broker,
+            date_time: sell.i.date_time,
+            quantity,
+            unmatched_quantity: sell.i.quantity - quantity,
+            proceeds: Money::new(proceeds.round_dp(2).normalize(), currency.clone()),
+            cost_basis: Money::new(cost_basis.round_dp(2).normalize(), currency.clone()),
+            commission: Money::new(commission.round_dp(2).normalize(), currency.clone()),
+            realized_pnl: Money::new((proceeds - cost_basis - commission).round_dp(2).normalize(), currency),
+            reported_pnl: sell.i.reported_pnl.clone(),
+        });
+    }
+
+    let mut by_ticker: BTreeMap<(String, String), RealizedPnlAccumulator> = BTreeMap::new();
+    let mut by_year: BTreeMap<(i32, String), RealizedPnlAccumulator> = BTreeMap::new();
+    let mut by_brokerage: BTreeMap<(Option<BrokerType>, String), RealizedPnlAccumulator> = BTreeMap::new();
+    for trade in trades.iter().filter(|t| t.quantity > 0) {
+        let currency = trade.realized_pnl.currency.clone();
+        by_ticker.entry((trade.ticker.clone(), currency.clone())).or_default().add(trade);
+        by_year.entry((trade.date_time.year(), currency.clone())).or_default().add(trade);
+        by_brokerage.entry((trade.brokerage, currency)).or_default().add(trade);
+    }
+
+    RealizedPnl {
+        trades,
+        by_ticker: by_ticker.into_iter().map(|((ticker, currency), accumulator)| {
+            let (realized_pnl, reported_pnl) = accumulator.into_money(currency);
+            TickerRealizedPnl { ticker, realized_pnl, reported_pnl }
+        }).collect(),
+        by_year: by_year.into_iter().map(|((year, currency), accumulator)| {
+            let (realized_pnl, reported_pnl) = accumulator.into_money(currency);
+            YearRealizedPnl { year, realized_pnl, reported_pnl }
+        }).collect(),
+        by_brokerage: by_brokerage.into_iter().map(|((brokerage, currency), accumulator)| {
+            let (realized_pnl, reported_pnl) = accumulator.into_money(currency);
+            BrokerageRealizedPnl { brokerage, realized_pnl, reported_pnl }
+        }).collect(),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDateTime;
+
+    use crate::business::{model::OperationSource, security_transfer::model::SecurityTransfer, trade_operation::model::TradeOperation};
+
+    use super::*;
+
+    fn trade(day: u32, side: TradeOperationSide, quantity: i32, price: i64) -> SelectTradeOperation {
+        SelectTradeOperation {
+            id: Uuid::new_v4(),
+            portfolio_id: Uuid::nil(),
+            i: TradeOperation {
+                operation_source: OperationSource::Manual,
+                broker: None,
+                external_id: None,
+                date_time: NaiveDateTime::parse_from_str(&format!("2023-01-{day:02} 10:00:00"), "%Y-%m-%d %H:%M:%S").unwrap(),
+                side,
+                instrument_symbol: "VOO".to_string(),
+                isin: None,
+                price: Money::new(Decimal::from(price), "USD".to_string()),
+                quantity,
+                commission: None,
+                order_id: None,
+                summ: Money::new(Decimal::from(price * i64::from(quantity)), "USD".to_string()),
+                metadata: serde_json::Value::Null,
+                reported_pnl: None,
+            },
+        }
+    }
+
+    fn matched(assignments: &[InsertLotAssignment], buy: &SelectTradeOperation) -> (i32, Decimal) {
+        assignments.iter()
+            .filter(|a| a.buy_trade_operation_id == Some(buy.id))
+            .fold((0, Decimal::ZERO), |(q, c), a| (q + a.quantity, c + a.cost_basis.amount))
+    }
+
+    #[test]
+    fn matches_sells_by_cost_basis_method() {
+        let operations = [
+            trade(1, TradeOperationSide::Buy, 10, 100),
+            trade(2, TradeOperationSide::Buy, 10, 200),
+            trade(3, TradeOperationSide::Sell, 15, 300),
+        ];
+
+        let fifo = match_lots(Uuid::nil(), &operations, &[], CostBasisMethod::Fifo, &[]);
+        assert_eq!(matched(&fifo, &operations[0]), (10, Decimal::from(1000)));
+        assert_eq!(matched(&fifo, &operations[1]), (5, Decimal::from(1000)));
+
+        let lifo = match_lots(Uuid::nil(), &operations, &[], CostBasisMethod::Lifo, &[]);
+        assert_eq!(matched(&lifo, &operations[0]), (5, Decimal::from(500)));
+        assert_eq!(matched(&lifo, &operations[1]), (10, Decimal::from(2000)));
+
+        let average = match_lots(Uuid::nil(), &operations, &[], CostBasisMethod::AverageCost, &[]);
+        let total_cost: Decimal = average.iter().map(|a| a.cost_basis.amount).sum();
+        assert_eq!(total_cost, Decimal::from(2250));
+    }
+
+    #[test]
+    fn realized_pnl_is_net_of_commissions() {
+        let mut operations = [
+            trade(1, TradeOperationSide::Buy, 10, 100),
+            trade(2, TradeOperationSide::Sell, 15, 150),
+        ];
+        operations[0].i.commission = Some(Money::new(Decimal::from(2), "USD".to_string()));
+        operations[1].i.commission = Some(Money::new(Decimal::from(3), "USD".to_string()));
+        operations[1].i.reported_pnl = Some(Money::new(Decimal::from(495), "USD".to_string()));
+        let lot_assignments: Vec<SelectLotAssignment> = match_lots(Uuid::nil(), &operations, &[], CostBasisMethod::Fifo, &[])
+            .into_iter()
+            .map(|a| SelectLotAssignment {
+                id: Uuid::new_v4(),
+                portfolio_id: a.portfolio_id,
+                sell_trade_operation_id: a.sell_trade_operation_id,
+                buy_trade_operation_id: a.buy_trade_operation_id,
+                buy_security_transfer_id: a.buy_security_transfer_id,
+                quantity: a.quantity,
+                cost_basis: a.cost_basis,
+                manual: a.manual,
+            })
+            .collect();
+
+        let realized = fold_realized_pnl(&operations, &lot_assignments);
+        let sell = &realized.trades[0];
+        assert_eq!(sell.quantity, 10);
+        assert_eq!(sell.unmatched_quantity, 5);
+        // 1500 of proceeds - 1000 of cost - 2 of buy commission - 2 of sell commission
+        assert_eq!(sell.realized_pnl.amount, Decimal::from(496));
+        assert_eq!(realized.by_year[0].year, 2023);
+        assert_eq!(realized.by_ticker[0].reported_pnl.as_ref().map(|m| m.amount), Some(Decimal::from(495)));
+    }
+
+    #[test]
+    fn honours_manually_chosen_lots() {
+        let operations = [
+            trade(1, TradeOperationSide::Buy, 10, 100),
+            trade(2, TradeOperationSide::Buy, 10, 200),
+            trade(3, TradeOperationSide::Sell, 15, 300),
+            trade(4, TradeOperationSide::Sell, 10, 300),
+        ];
+        let manual = [SelectLotAssignment {
+            id: Uuid::new_v4(),
+            portfolio_id: Uuid::nil(),
+            sell_trade_operation_id: operations[2].id,
+            buy_trade_operation_id: Some(operations[1].id),
+            buy_security_transfer_id: None,
+            quantity: 10,
+            cost_basis: Money::new(Decimal::ZERO, "USD".to_string()),
+            manual: true,
+        }];
+
+        let assignments = match_lots(Uuid::nil(), &operations, &[], CostBasisMethod::SpecificLot, &manual);
+        assert_eq!(matched(&assignments, &operations[1]), (10, Decimal::from(2000)));
+        assert_eq!(matched(&assignments, &operations[0]), (10, Decimal::from(1000)));
+        // the second sell can only be matched partially
+        let second_sell: i32 = assignments.iter()
+            .filter(|a| a.sell_trade_operation_id == operations[3].id)
+            .map(|a| a.quantity)
+            .sum();
+        assert_eq!(second_sell, 5);
+    }
+
+    fn transfer(day: u32, direction: TransferDirection, quantity: i32, cost_basis: Option<i64>, is_split: bool) -> SelectSecurityTransfer {
+        SelectSecurityTransfer {
+            id: Uuid::new_v4(),
+            portfolio_id: Uuid::nil(),
+            i: SecurityTransfer {
+                operation_source: OperationSource::Manual,
+                broker: None,
+                external_id: None,
+                date_time: NaiveDateTime::parse_from_str(&format!("2023-01-{day:02} 10:00:00"), "%Y-%m-%d %H:%M:%S").unwrap(),
+                direction,
+                instrument_symbol: "VOO".to_string(),
+                isin: None,
+                quantity,
+                cost_basis: cost_basis.map(|amount| Money::new(Decimal::from(amount), "USD".to_string())),
+                metadata: serde_json::Value::Null,
+                is_split,
+            },
+        }
+    }
+
+    #[test]
+    fn opens_lots_of_transferred_securities() {
+        let operations = [
+            trade(2, TradeOperationSide::Buy, 10, 200),
+            trade(4, TradeOperationSide::Sell, 10, 300),
+        ];
+        let transfers = [
+            transfer(1, TransferDirection::In, 10, Some(1000), false),
+            transfer(3, TransferDirection::Out, 5, None, false),
+        ];
+
+        let assignments = match_lots(Uuid::nil(), &operations, &transfers, CostBasisMethod::Fifo, &[]);
+        // the transfer out takes the oldest securities, which are not sold
+        let transferred: Vec<(i32, Decimal)> = assignments.iter()
+            .filter(|a| a.buy_security_transfer_id == Some(transfers[0].id))
+            .map(|a| (a.quantity, a.cost_basis.amount))
+            .collect();
+        assert_eq!(transferred, vec![(5, Decimal::from(500))]);
+        assert_eq!(matched(&assignments, &operations[0]), (5, Decimal::from(1000)));
+    }
+
+    #[test]
+    fn carries_cost_of_lots_over_splits() {
+        let operations = [
+            trade(1, TradeOperationSide::Buy, 10, 100),
+            trade(3, TradeOperationSide::Sell, 20, 60),
+        ];
+        let transfers = [
+            transfer(2, TransferDirection::In, 20, None, true),
+            transfer(2, TransferDirection::Out, 10, None, true),
+        ];
+
+        let assignments = match_lots(Uuid::nil(), &operations, &transfers, CostBasisMethod::Fifo, &[]);
+        let split: Vec<(i32, Decimal)> = assignments.iter()
+            .map(|a| (a.quantity, a.cost_basis.amount))
+            .collect();
+        assert_eq!(split, vec![(20, Decimal::from(1000))]);
+        assert_eq!(assignments[0].buy_security_transfer_id, Some(transfers[0].id));
+    }
+}
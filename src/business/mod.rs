@@ -1,6 +1,14 @@
+pub mod csv_mapping;
+pub mod currency_conversion;
 pub mod fiscal_transaction;
+pub mod fx_rate;
+pub mod lot;
 pub mod model;
+pub mod performance;
 pub mod portfolio;
+pub mod position_snapshot;
+pub mod price;
 pub mod report;
+pub mod security_transfer;
 pub mod trade_operation;
 pub mod user_transaction;
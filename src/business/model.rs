@@ -19,20 +19,24 @@ use crate::database::schema::{self, sql_types::CustomMoney};
 #[derive(diesel_derive_enum::DbEnum, Debug, async_graphql::Enum, Copy, Clone, Eq, PartialEq, PartialOrd, Ord)]
 #[ExistingTypePath = "crate::database::schema::sql_types::BrokerType"]
 pub enum BrokerType {
-    Exante, Freedomfinance
+    Exante, Freedomfinance, Ibkr, Degiro,
+    #[graphql(name = "TRADING212")]
+    Trading212,
+    /// A broker without an own importer, whose reports are read with a CSV mapping profile
+    Other,
 }
 
 #[derive(Deserialize_enum_str, Serialize_enum_str)]
-#[derive(diesel_derive_enum::DbEnum, Debug)]
+#[derive(diesel_derive_enum::DbEnum, Debug, Copy, Clone, Eq, PartialEq, Hash)]
 #[ExistingTypePath = "crate::database::schema::sql_types::OperationSourceType"]
 pub enum OperationSource {
-    ExanteReport, FreedomfinanceReport, Manual
+    ExanteReport, FreedomfinanceReport, IbkrReport, DegiroReport, Trading212Report, CustomCsvReport, Manual
 }
 
 
 // --- money
 
-#[derive(Debug, PartialEq, FromSqlRow, AsExpression, Serialize, Deserialize, SimpleObject, InputObject)]
+#[derive(Debug, Clone, PartialEq, FromSqlRow, AsExpression, Serialize, Deserialize, SimpleObject, InputObject)]
 #[diesel(sql_type = schema::sql_types::CustomMoney)]
 #[graphql(input_name = "MoneyInput")]
 pub struct Money {
@@ -46,6 +50,25 @@ impl Money {
     }
 }
 
+/// A field of an imported operation which differs in another import of it
+#[derive(Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldChange {
+    pub field: String,
+    pub imported_value: serde_json::Value,
+    pub new_value: serde_json::Value,
+}
+
+impl FieldChange {
+    pub fn of<T: PartialEq + Serialize>(field: &str, imported: &T, new: &T) -> Option<Self> {
+        (imported != new).then(|| FieldChange {
+            field: field.to_string(),
+            imported_value: serde_json::to_value(imported).unwrap_or_default(),
+            new_value: serde_json::to_value(new).unwrap_or_default(),
+        })
+    }
+}
+
 impl std::ops::Mul<i32> for Money {
     type Output = Self;
 
@@ -0,0 +1,27 @@
+use async_graphql::SimpleObject;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::business::model::Money;
+
+
+/// Distance between points of a value history
+#[derive(async_graphql::Enum, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ValueInterval {
+    Day,
+    Week,
+    Month,
+}
+
+/// Value of a portfolio at the end of a day
+#[derive(Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct ValuePoint {
+    pub date: NaiveDate,
+    /// Sum of cash and securities.
+    pub value: Money,
+    /// Cash balance, negative when more was spent than funded.
+    pub cash: Money,
+    /// Market value of held securities.
+    pub securities: Money,
+}
@@ -0,0 +1,782 @@
+use std::collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap, HashSet};
+
+use chrono::{Days, Duration, Months, NaiveDate, NaiveDateTime};
+use rust_decimal::{prelude::{FromPrimitive, ToPrimitive}, Decimal};
+use uuid::Uuid;
+
+use crate::{business::{currency_conversion::service::list_fiscal_transactions_with_conversions, fiscal_transaction::model::{FiscalTransaction, FiscalTransactionType, SelectFiscalTransaction}, fx_rate::{model::MissingFxRateError, service::FxRateBook}, model::Money, portfolio::service::{is_trade_commission_booked_separately, trades_with_booked_commission}, price::service::PriceBook, security_transfer::model::{SecurityTransfer, SelectSecurityTransfer, TransferDirection}, trade_operation::model::{SelectTradeOperation, TradeOperation, TradeOperationSide}}, web::errors::DescriptiveError, ApplicationState};
+
+use super::resource::{ValueInterval, ValuePoint};
+
+
+#[derive(Clone)]
+pub struct Performance {
+    /// Time-weighted return over the period in percents, not annualized. None when the
+    /// portfolio had no value to grow during the period.
+    pub time_weighted_return: Option<Decimal>,
+    /// Money-weighted return, annualized internal rate of return of external cash flows
+    /// in percents. None when the cash flows have no rate of return.
+    pub money_weighted_return: Option<Decimal>,
+}
+
+/// Calculates performance of a portfolio valued in `currency` between the start of `from`
+/// and the end of `to`. The period starts at the first operation of the portfolio and ends
+/// today by default.
+pub fn calculate_performance(
+    state: &ApplicationState,
+    portfolio_id: Uuid,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    currency: &str,
+) -> Result<Performance, DescriptiveError> {
+    let trade_operations = state.repository.list_trade_operations(portfolio_id)?;
+    let fiscal_transactions = list_fiscal_transactions_with_conversions(state, portfolio_id)?;
+    let security_transfers = state.repository.list_security_transfers(portfolio_id)?;
+    let (from, to) = resolve_period(&trade_operations, &fiscal_transactions, &security_transfers, from, to)?;
+    let market_data = MarketData::load(state, &trade_operations, &fiscal_transactions, &security_transfers, currency, to)?;
+
+    Ok(fold_performance(
+        trade_operations.iter().map(|o| &o.i),
+        fiscal_transactions.iter().map(|t| &t.i),
+        security_transfers.iter().map(|t| &t.i),
+        currency,
+        &market_data,
+        from.and_time(chrono::NaiveTime::MIN),
+        (to + Duration::days(1)).and_time(chrono::NaiveTime::MIN),
+    )?)
+}
+
+/// Fills in the missing bounds of a period with the day of the first operation and today
+fn resolve_period(
+    trade_operations: &[SelectTradeOperation],
+    fiscal_transactions: &[SelectFiscalTransaction],
+    security_transfers: &[SelectSecurityTransfer],
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> Result<(NaiveDate, NaiveDate), DescriptiveError> {
+    let from = from
+        .or(first_operation_date(trade_operations, fiscal_transactions, security_transfers))
+        .unwrap_or_else(|| chrono::Utc::now().date_naive());
+    let to = to.unwrap_or_else(|| chrono::Utc::now().date_naive());
+    if from > to {
+        return Err(DescriptiveError::BadRequest("start of the period must not be after its end".to_string()));
+    }
+    Ok((from, to))
+}
+
+fn first_operation_date(
+    trade_operations: &[SelectTradeOperation],
+    fiscal_transactions: &[SelectFiscalTransaction],
+    security_transfers: &[SelectSecurityTransfer],
+) -> Option<NaiveDate> {
+    trade_operations.iter().map(|o| o.i.date_time)
+        .chain(fiscal_transactions.iter().map(|t| t.i.date_time))
+        .chain(security_transfers.iter().map(|t| t.i.date_time))
+        .min()
+        .map(|d| d.date())
+}
+
+/// Stored prices and exchange rates that are needed to value a portfolio
+#[derive(Default)]
+pub struct MarketData {
+    pub prices: PriceBook,
+    pub fx_rates: FxRateBook,
+}
+
+impl MarketData {
+    /// Loads prices of every traded or transferred instrument and exchange rates between
+    /// `currency` and every currency of the operations, up to `to`
+    pub fn load(
+        state: &ApplicationState,
+        trade_operations: &[SelectTradeOperation],
+        fiscal_transactions: &[SelectFiscalTransaction],
+        security_transfers: &[SelectSecurityTransfer],
+        currency: &str,
+        to: NaiveDate,
+    ) -> Result<Self, DescriptiveError> {
+        let tickers: BTreeSet<String> = trade_operations.iter()
+            .map(|o| o.i.instrument_symbol.clone())
+            .chain(security_transfers.iter().map(|t| t.i.instrument_symbol.clone()))
+            .collect();
+        let mut currencies: BTreeSet<String> = trade_operations.iter()
+            .flat_map(|o| [Some(&o.i.summ), o.i.commission.as_ref()])
+            .chain(fiscal_transactions.iter().flat_map(|t| [Some(&t.i.amount), t.i.commission.as_ref()]))
+            .chain(security_transfers.iter().map(|t| t.i.cost_basis.as_ref()))
+            .flatten()
+            .map(|m| m.currency.clone())
+            .collect();
+        currencies.insert(currency.to_string());
+
+        let fx_rates = if currencies.len() > 1 {
+            let from = first_operation_date(trade_operations, fiscal_transactions, security_transfers).unwrap_or(to).min(to);
+            FxRateBook::load(state, &currencies.into_iter().collect::<Vec<_>>(), from, to)?
+        } else {
+            FxRateBook::default()
+        };
+        Ok(MarketData {
+            prices: PriceBook::load(state, &tickers.into_iter().collect::<Vec<_>>(), to)?,
+            fx_rates,
+        })
+    }
+}
+
+const MAX_VALUE_HISTORY_POINTS: usize = 10_000;
+
+/// Values a portfolio in `currency` at the end of every `interval` between `from` and `to`,
+/// the end of `to` is always included
+pub fn calculate_value_history(
+    state: &ApplicationState,
+    portfolio_id: Uuid,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    interval: ValueInterval,
+    currency: &str,
+) -> Result<Vec<ValuePoint>, DescriptiveError> {
+    let trade_operations = state.repository.list_trade_operations(portfolio_id)?;
+    let fiscal_transactions = list_fiscal_transactions_with_conversions(state, portfolio_id)?;
+    let security_transfers = state.repository.list_security_transfers(portfolio_id)?;
+    let (from, to) = resolve_period(&trade_operations, &fiscal_transactions, &security_transfers, from, to)?;
+
+    let dates = value_history_dates(from, to, interval);
+    if dates.len() > MAX_VALUE_HISTORY_POINTS {
+        return Err(DescriptiveError::BadRequest(format!(
+            "value history can't contain more than {MAX_VALUE_HISTORY_POINTS} points, choose a shorter period or a longer interval"
+        )));
+    }
+    let market_data = MarketData::load(state, &trade_operations, &fiscal_transactions, &security_transfers, currency, to)?;
+    Ok(fold_value_history(
+        trade_operations.iter().map(|o| &o.i),
+        fiscal_transactions.iter().map(|t| &t.i),
+        security_transfers.iter().map(|t| &t.i),
+        currency,
+        &market_data,
+        &dates,
+    )?)
+}
+
+/// Days from `from` stepping by `interval`, followed by `to`
+fn value_history_dates(from: NaiveDate, to: NaiveDate, interval: ValueInterval) -> Vec<NaiveDate> {
+    let mut dates = Vec::new();
+    for step in 0.. {
+        let date = match interval {
+            ValueInterval::Day => from.checked_add_days(Days::new(step)),
+            ValueInterval::Week => from.checked_add_days(Days::new(step * 7)),
+            ValueInterval::Month => u32::try_from(step).ok().and_then(|months| from.checked_add_months(Months::new(months))),
+        };
+        match date {
+            Some(date) if date < to && dates.len() <= MAX_VALUE_HISTORY_POINTS => dates.push(date),
+            _ => break,
+        }
+    }
+    dates.push(to);
+    dates
+}
+
+/// Replays operations and values cash and securities in `currency` at the end of each
+/// of the ascending `dates`
+pub fn fold_value_history<'a>(
+    trade_operations: impl Iterator<Item = &'a TradeOperation>,
+    fiscal_transactions: impl Iterator<Item = &'a FiscalTransaction>,
+    security_transfers: impl Iterator<Item = &'a SecurityTransfer>,
+    currency: &str,
+    market_data: &MarketData,
+    dates: &[NaiveDate],
+) -> Result<Vec<ValuePoint>, MissingFxRateError> {
+    let events = chronological_events(trade_operations, fiscal_transactions, security_transfers);
+    let mut valuation = Valuation::new(market_data, &events);
+    let mut events = events.iter().peekable();
+    let money = |amount: Decimal| Money::new(amount.round_dp(2).normalize(), currency.to_string());
+    dates.iter()
+        .map(|date| {
+            let end_of_day = (*date + Duration::days(1)).and_time(chrono::NaiveTime::MIN);
+            while let Some(event) = events.next_if(|e| e.date_time() < end_of_day) {
+                valuation.apply(event);
+            }
+            let (cash, securities) = valuation.value_parts(currency, end_of_day)?;
+            Ok(ValuePoint {
+                date: *date,
+                value: money(cash + securities),
+                cash: money(cash),
+                securities: money(securities),
+            })
+        })
+        .collect()
+}
+
+enum Event<'a> {
+    Trade(&'a TradeOperation),
+    Fiscal(&'a FiscalTransaction),
+    Transfer(&'a SecurityTransfer),
+}
+
+impl Event<'_> {
+    fn date_time(&self) -> NaiveDateTime {
+        match self {
+            Event::Trade(operation) => operation.date_time,
+            Event::Fiscal(transaction) => transaction.date_time,
+            Event::Transfer(transfer) => transfer.date_time,
+        }
+    }
+
+    /// Funding, withdrawals and transfers of securities move value between the portfolio
+    /// and its owner. Splits don't change the value.
+    fn is_external_flow(&self) -> bool {
+        match self {
+            Event::Fiscal(transaction) => matches!(transaction.operation_type, FiscalTransactionType::FundingWithdrawal),
+            Event::Transfer(transfer) => !transfer.is_split,
+            Event::Trade(_) => false,
+        }
+    }
+}
+
+fn chronological_events<'a>(
+    trade_operations: impl Iterator<Item = &'a TradeOperation>,
+    fiscal_transactions: impl Iterator<Item = &'a FiscalTransaction>,
+    security_transfers: impl Iterator<Item = &'a SecurityTransfer>,
+) -> Vec<Event<'a>> {
+    let mut events: Vec<Event> = trade_operations.map(Event::Trade)
+        .chain(fiscal_transactions.map(Event::Fiscal))
+        .chain(security_transfers.map(Event::Transfer))
+        .collect();
+    // money and securities arrive before they are spent within the same moment
+    events.sort_by_key(|e| (e.date_time(), !e.is_external_flow()));
+    events
+}
+
+/// Day which exchange rate applies at the moment `at`, the start of a day belongs to the previous one
+fn rate_date(at: NaiveDateTime) -> NaiveDate {
+    (at - Duration::seconds(1)).date()
+}
+
+/// Cash and securities of a portfolio, kept in the currencies they are denominated in.
+/// Securities are valued at the latest of the stored close price and the price they were
+/// last traded at, every currency is converted at the exchange rate of the valuation day.
+/// Securities transferred in without either are valued at their carried-over cost basis.
+pub struct Valuation<'a> {
+    market_data: &'a MarketData,
+    cash: BTreeMap<String, Decimal>,
+    positions: HashMap<(String, String), Position>,
+    trades_with_booked_commission: HashSet<String>,
+}
+
+/// Securities of an instrument traded in a currency. A position is only opened by a trade
+/// or a transfer with a cost basis, so it always has a price to be valued at.
+struct Position {
+    quantity: i32,
+    /// Moment and price of the last trade, or the cost basis per security of the transfer
+    /// which opened the position
+    price: (NaiveDateTime, Decimal),
+}
+
+impl<'a> Valuation<'a> {
+    /// Valuation before any of `events`, which are known to tell commissions booked separately
+    fn new(market_data: &'a MarketData, events: &[Event]) -> Self {
+        let trades_with_booked_commission = trades_with_booked_commission(events.iter().filter_map(|e| match e {
+            Event::Fiscal(transaction) => Some(*transaction),
+            Event::Trade(_) | Event::Transfer(_) => None,
+        }));
+        Self { market_data, cash: BTreeMap::new(), positions: HashMap::new(), trades_with_booked_commission }
+    }
+
+    /// Replays operations made before `until`
+    pub fn replay<'b>(
+        market_data: &'a MarketData,
+        trade_operations: impl Iterator<Item = &'b TradeOperation>,
+        fiscal_transactions: impl Iterator<Item = &'b FiscalTransaction>,
+        security_transfers: impl Iterator<Item = &'b SecurityTransfer>,
+        until: NaiveDateTime,
+    ) -> Self {
+        let events = chronological_events(trade_operations, fiscal_transactions, security_transfers);
+        let mut valuation = Self::new(market_data, &events);
+        for event in events.iter().filter(|e| e.date_time() < until) {
+            valuation.apply(event);
+        }
+        valuation
+    }
+
+    fn apply(&mut self, event: &Event) {
+        match event {
+            Event::Trade(operation) => {
+                let key = (operation.instrument_symbol.clone(), operation.summ.currency.clone());
+                let cash = self.cash.entry(operation.summ.currency.clone()).or_default();
+                let price = (operation.date_time, operation.price.amount);
+                let position = self.positions.entry(key).or_insert(Position { quantity: 0, price });
+                position.price = price;
+                match operation.side {
+                    TradeOperationSide::Buy => {
+                        *cash -= operation.summ.amount;
+                        position.quantity += operation.quantity;
+                    },
+                    TradeOperationSide::Sell => {
+                        *cash += operation.summ.amount;
+                        position.quantity -= operation.quantity;
+                    },
+                }
+                if !is_trade_commission_booked_separately(operation, &self.trades_with_booked_commission) {
+                    if let Some(commission) = operation.commission.as_ref() {
+                        *self.cash.entry(commission.currency.clone()).or_default() -= commission.amount.abs();
+                    }
+                }
+            },
+            Event::Fiscal(transaction) => {
+                if let Some(commission) = transaction.commission.as_ref() {
+                    *self.cash.entry(commission.currency.clone()).or_default() -= commission.amount.abs();
+                }
+                if !matches!(transaction.operation_type, FiscalTransactionType::Unrecognized(_)) {
+                    *self.cash.entry(transaction.amount.currency.clone()).or_default() += transaction.amount.amount;
+                }
+            },
+            Event::Transfer(transfer) => {
+                // a transfer without a cost basis joins a position of the same instrument
+                let currency = transfer.cost_basis.as_ref()
+                    .map(|cost_basis| cost_basis.currency.clone())
+                    .or_else(|| self.positions.keys()
+                        .find(|(ticker, _)| *ticker == transfer.instrument_symbol)
+                        .map(|(_, currency)| currency.clone()));
+                let Some(currency) = currency else { return };
+                let key = (transfer.instrument_symbol.clone(), currency);
+                let position = match self.positions.entry(key) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        // a new position is valued at the cost basis it was transferred at
+                        let Some(cost_basis) = transfer.cost_basis.as_ref().filter(|_| transfer.quantity > 0) else { return };
+                        let price = (transfer.date_time, cost_basis.amount / Decimal::from(transfer.quantity));
+                        entry.insert(Position { quantity: 0, price })
+                    },
+                };
+                match transfer.direction {
+                    TransferDirection::In => position.quantity += transfer.quantity,
+                    TransferDirection::Out => position.quantity -= transfer.quantity,
+                }
+            },
+        }
+    }
+
+    /// Cash balances in every currency that has any
+    pub fn cash_balances(&self) -> Vec<Money> {
+        self.cash.iter()
+            .filter(|(_, amount)| !amount.is_zero())
+            .map(|(currency, amount)| Money::new(amount.normalize(), currency.clone()))
+            .collect()
+    }
+
+    /// Price of a security traded in `currency` at the moment `at`. A close price becomes
+    /// known at the end of its day.
+    pub fn price(&self, ticker: &str, currency: &str, at: NaiveDateTime) -> Option<Decimal> {
+        match self.positions.get(&(ticker.to_string(), currency.to_string())) {
+            Some(position) => Some(self.position_price(ticker, currency, position, at)),
+            None => self.close_price(ticker, currency, at).map(|(_, close)| close),
+        }
+    }
+
+    /// Latest of the close price and the price the position was last traded or transferred at
+    fn position_price(&self, ticker: &str, currency: &str, position: &Position, at: NaiveDateTime) -> Decimal {
+        match self.close_price(ticker, currency, at) {
+            Some((known_at, close)) if known_at > position.price.0 => close,
+            _ => position.price.1,
+        }
+    }
+
+    /// Last close price known at the moment `at`, and the moment it became known
+    fn close_price(&self, ticker: &str, currency: &str, at: NaiveDateTime) -> Option<(NaiveDateTime, Decimal)> {
+        at.date().pred_opt()
+            .and_then(|last_closed_day| self.market_data.prices.close_at(ticker, currency, last_closed_day))
+            .map(|(date, close)| ((date + Duration::days(1)).and_time(chrono::NaiveTime::MIN), close))
+    }
+
+    /// Cash and market value of securities converted to `currency` at the moment `at`
+    pub fn value_parts(&self, currency: &str, at: NaiveDateTime) -> Result<(Decimal, Decimal), MissingFxRateError> {
+        let date = rate_date(at);
+        let mut cash = Decimal::ZERO;
+        for balance in self.cash_balances() {
+            cash += self.market_data.fx_rates.try_convert(&balance, currency, date)?;
+        }
+        let mut securities = Decimal::ZERO;
+        for ((ticker, position_currency), position) in self.positions.iter().filter(|(_, position)| position.quantity != 0) {
+            let price = self.position_price(ticker, position_currency, position, at);
+            let value = Money::new(price * Decimal::from(position.quantity), position_currency.clone());
+            securities += self.market_data.fx_rates.try_convert(&value, currency, date)?;
+        }
+        Ok((cash, securities))
+    }
+
+    pub fn value(&self, currency: &str, at: NaiveDateTime) -> Result<Decimal, MissingFxRateError> {
+        let (cash, securities) = self.value_parts(currency, at)?;
+        Ok(cash + securities)
+    }
+}
+
+/// Value of securities transferred into the portfolio less value of those transferred out
+/// before `until`, each valued in `currency` at the moment of its transfer
+pub fn fold_transferred_value<'a>(
+    trade_operations: impl Iterator<Item = &'a TradeOperation>,
+    fiscal_transactions: impl Iterator<Item = &'a FiscalTransaction>,
+    security_transfers: impl Iterator<Item = &'a SecurityTransfer>,
+    currency: &str,
+    market_data: &MarketData,
+    until: NaiveDateTime,
+) -> Result<Decimal, MissingFxRateError> {
+    let events = chronological_events(trade_operations, fiscal_transactions, security_transfers);
+    let mut valuation = Valuation::new(market_data, &events);
+    let mut transferred = Decimal::ZERO;
+    for event in events.iter().filter(|e| e.date_time() < until) {
+        if matches!(event, Event::Transfer(_)) && event.is_external_flow() {
+            let date_time = event.date_time();
+            let value = valuation.value(currency, date_time)?;
+            valuation.apply(event);
+            transferred += valuation.value(currency, date_time)? - value;
+        } else {
+            valuation.apply(event);
+        }
+    }
+    Ok(transferred)
+}
+
+/// Replays operations and measures performance of a portfolio valued in `currency` within
+/// `[from, to)`. Funding, withdrawals and transfers of securities are external cash flows:
+/// the time-weighted return chains returns of the sub-periods between them, the money-weighted
+/// return treats them as investments. Sub-periods that start without a positive value are skipped by the
+/// time-weighted return.
+pub fn fold_performance<'a>(
+    trade_operations: impl Iterator<Item = &'a TradeOperation>,
+    fiscal_transactions: impl Iterator<Item = &'a FiscalTransaction>,
+    security_transfers: impl Iterator<Item = &'a SecurityTransfer>,
+    currency: &str,
+    market_data: &MarketData,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> Result<Performance, MissingFxRateError> {
+    let events = chronological_events(trade_operations, fiscal_transactions, security_transfers);
+    let (before, within): (Vec<&Event>, Vec<&Event>) = events.iter()
+        .filter(|e| e.date_time() < to)
+        .partition(|e| e.date_time() < from);
+    let mut valuation = Valuation::new(market_data, &events);
+    for event in before {
+        valuation.apply(event);
+    }
+
+    let mut growth = Decimal::ONE;
+    let mut has_growth = false;
+    let mut period_start_value = valuation.value(currency, from)?;
+    let mut cash_flows = Vec::new();
+    if !period_start_value.is_zero() {
+        cash_flows.push((from, -period_start_value));
+    }
+
+    for event in within {
+        if event.is_external_flow() {
+            let date_time = event.date_time();
+            let value = valuation.value(currency, date_time)?;
+            if period_start_value > Decimal::ZERO {
+                growth *= value / period_start_value;
+                has_growth = true;
+            }
+            valuation.apply(event);
+            period_start_value = valuation.value(currency, date_time)?;
+            // the flow is what the portfolio gained or lost by it
+            cash_flows.push((date_time, value - period_start_value));
+        } else {
+            valuation.apply(event);
+        }
+    }
+
+    let end_value = valuation.value(currency, to)?;
+    if period_start_value > Decimal::ZERO {
+        growth *= end_value / period_start_value;
+        has_growth = true;
+    }
+    cash_flows.push((to, end_value));
+
+    Ok(Performance {
+        time_weighted_return: has_growth.then(|| ((growth - Decimal::ONE) * Decimal::ONE_HUNDRED).round_dp(2)),
+        money_weighted_return: internal_rate_of_return(&cash_flows)
+            .and_then(|rate| Decimal::from_f64(rate * 100.0))
+            .map(|rate| rate.round_dp(2)),
+    })
+}
+
+const XIRR_PRECISION: f64 = 1e-9;
+const XIRR_MAX_ITERATIONS: usize = 100;
+
+/// Annual rate at which the net present value of `cash_flows` is zero. Newton's method is
+/// tried first, bisection is the fallback when it does not converge.
+fn internal_rate_of_return(cash_flows: &[(NaiveDateTime, Decimal)]) -> Option<f64> {
+    let first = cash_flows.iter().map(|(d, _)| *d).min()?;
+    let flows: Vec<(f64, f64)> = cash_flows.iter()
+        .filter_map(|(date_time, amount)| {
+            let years = (*date_time - first).num_seconds() as f64 / (365.0 * 24.0 * 3600.0);
+            amount.to_f64().map(|amount| (years, amount))
+        })
+        .collect();
+    if !flows.iter().any(|(_, a)| *a > 0.0) || !flows.iter().any(|(_, a)| *a < 0.0) {
+        return None;
+    }
+
+    let net_present_value = |rate: f64| flows.iter()
+        .map(|(years, amount)| amount / (1.0 + rate).powf(*years))
+        .sum::<f64>();
+    let derivative = |rate: f64| flows.iter()
+        .map(|(years, amount)| -years * amount / (1.0 + rate).powf(years + 1.0))
+        .sum::<f64>();
+
+    let mut rate = 0.1;
+    for _ in 0..XIRR_MAX_ITERATIONS {
+        let slope = derivative(rate);
+        if slope == 0.0 || !slope.is_finite() {
+            break;
+        }
+        let next = rate - net_present_value(rate) / slope;
+        if !next.is_finite() || next <= -1.0 {
+            break;
+        }
+        if (next - rate).abs() < XIRR_PRECISION {
+            return Some(next);
+        }
+        rate = next;
+    }
+
+    let (mut low, mut high) = (-0.999_999, 1_000.0);
+    if net_present_value(low).signum() == net_present_value(high).signum() {
+        return None;
+    }
+    for _ in 0..1_000 {
+        let middle = (low + high) / 2.0;
+        if net_present_value(middle).signum() == net_present_value(low).signum() {
+            low = middle;
+        } else {
+            high = middle;
+        }
+        if high - low < XIRR_PRECISION {
+            break;
+        }
+    }
+    Some((low + high) / 2.0)
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::business::{fx_rate::model::FxRate, model::OperationSource, price::model::DailyPrice};
+
+    use super::*;
+
+    fn date_time(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn trade(at: &str, side: TradeOperationSide, quantity: i32, price: i64) -> TradeOperation {
+        TradeOperation {
+            operation_source: OperationSource::Manual,
+            broker: None,
+            external_id: None,
+            date_time: date_time(at),
+            side,
+            instrument_symbol: "VOO".to_string(),
+            isin: None,
+            price: Money::new(Decimal::from(price), "USD".to_string()),
+            quantity,
+            commission: None,
+            order_id: None,
+            summ: Money::new(Decimal::from(price * i64::from(quantity)), "USD".to_string()),
+            metadata: serde_json::Value::Null,
+            reported_pnl: None,
+        }
+    }
+
+    fn funding(at: &str, amount: i64) -> FiscalTransaction {
+        FiscalTransaction {
+            operation_source: OperationSource::Manual,
+            broker: None,
+            external_id: None,
+            date_time: date_time(at),
+            symbol_id: None,
+            amount: Money::new(Decimal::from(amount), "USD".to_string()),
+            operation_type: FiscalTransactionType::FundingWithdrawal,
+            commission: None,
+            metadata: serde_json::Value::Null,
+            ex_date: None,
+            amount_per_share: None,
+        }
+    }
+
+    #[test]
+    fn chains_returns_between_external_cash_flows() {
+        let operations = [
+            trade("2023-01-01 10:00:00", TradeOperationSide::Buy, 10, 100),
+            trade("2023-06-30 10:00:00", TradeOperationSide::Buy, 1, 120),
+            trade("2023-12-31 10:00:00", TradeOperationSide::Sell, 1, 132),
+        ];
+        let transactions = [
+            funding("2023-01-01 10:00:00", 1000),
+            funding("2023-07-01 10:00:00", 1000),
+        ];
+
+        let performance = fold_performance(operations.iter(), transactions.iter(), [].iter(), "USD", &MarketData::default(),
+            date_time("2023-01-01 00:00:00"), date_time("2024-01-01 00:00:00")).unwrap();
+        // grows by 20% before the second funding, then by 6%
+        assert_eq!(performance.time_weighted_return, Some(Decimal::new(2720, 2)));
+        // the second half of the money missed the growth of the first half of the year
+        assert_eq!(performance.money_weighted_return, Some(Decimal::new(2249, 2)));
+
+        let later = fold_performance(operations.iter(), transactions.iter(), [].iter(), "USD", &MarketData::default(),
+            date_time("2023-07-01 00:00:00"), date_time("2024-01-01 00:00:00")).unwrap();
+        // the period starts with 1200 invested and grows by 6% after the funding
+        assert_eq!(later.time_weighted_return, Some(Decimal::new(600, 2)));
+
+        let without_rates = fold_performance(operations.iter(), transactions.iter(), [].iter(), "EUR", &MarketData::default(),
+            date_time("2023-01-01 00:00:00"), date_time("2024-01-01 00:00:00"));
+        assert!(without_rates.is_err());
+    }
+
+    #[test]
+    fn treats_transferred_securities_as_external_flows() {
+        let transfers = [SecurityTransfer {
+            operation_source: OperationSource::Manual,
+            broker: None,
+            external_id: None,
+            date_time: date_time("2023-01-01 10:00:00"),
+            direction: TransferDirection::In,
+            instrument_symbol: "VOO".to_string(),
+            isin: None,
+            quantity: 10,
+            cost_basis: Some(Money::new(Decimal::from(1000), "USD".to_string())),
+            metadata: serde_json::Value::Null,
+            is_split: false,
+        }];
+        let operations = [trade("2023-12-31 10:00:00", TradeOperationSide::Sell, 1, 110)];
+
+        let performance = fold_performance(operations.iter(), [].iter(), transfers.iter(), "USD", &MarketData::default(),
+            date_time("2023-01-01 00:00:00"), date_time("2024-01-01 00:00:00")).unwrap();
+        // the securities arrive worth their cost basis and grow by 10%
+        assert_eq!(performance.time_weighted_return, Some(Decimal::new(1000, 2)));
+        assert_eq!(fold_transferred_value(operations.iter(), [].iter(), transfers.iter(), "USD", &MarketData::default(),
+            date_time("2024-01-01 00:00:00")).unwrap(), Decimal::from(1000));
+    }
+
+    #[test]
+    fn values_positions_opened_by_transfers_at_their_cost_basis() {
+        let transfer = |direction, ticker: &str, quantity, cost_basis| SecurityTransfer {
+            operation_source: OperationSource::Manual,
+            broker: None,
+            external_id: None,
+            date_time: date_time("2023-01-01 10:00:00"),
+            direction,
+            instrument_symbol: ticker.to_string(),
+            isin: None,
+            quantity,
+            cost_basis: Some(Money::new(Decimal::from(cost_basis), "USD".to_string())),
+            metadata: serde_json::Value::Null,
+            is_split: false,
+        };
+        let transfers = [
+            transfer(TransferDirection::In, "VOO", 10, 1000),
+            // moved out before anything of it was known to the portfolio
+            transfer(TransferDirection::Out, "SCHD", 4, 300),
+        ];
+        let market_data = MarketData::default();
+        let valuation = Valuation::replay(&market_data, [].iter(), [].iter(), transfers.iter(), date_time("2024-01-01 00:00:00"));
+
+        assert_eq!(valuation.value_parts("USD", date_time("2024-01-01 00:00:00")).unwrap(), (Decimal::ZERO, Decimal::from(700)));
+    }
+
+    #[test]
+    fn values_cash_and_securities_at_the_end_of_each_interval() {
+        let operations = [
+            trade("2023-01-01 10:00:00", TradeOperationSide::Buy, 10, 100),
+            trade("2023-01-15 10:00:00", TradeOperationSide::Buy, 1, 120),
+        ];
+        let transactions = [funding("2023-01-01 09:00:00", 1000)];
+
+        let dates = value_history_dates(
+            NaiveDate::from_ymd_opt(2022, 12, 31).unwrap(),
+            NaiveDate::from_ymd_opt(2023, 1, 20).unwrap(),
+            ValueInterval::Week,
+        );
+        assert_eq!(dates.iter().map(|d| d.to_string()).collect::<Vec<_>>(),
+            vec!["2022-12-31", "2023-01-07", "2023-01-14", "2023-01-20"]);
+
+        let history = fold_value_history(operations.iter(), transactions.iter(), [].iter(), "USD", &MarketData::default(), &dates).unwrap();
+        let values: Vec<(Decimal, Decimal)> = history.iter().map(|p| (p.cash.amount, p.securities.amount)).collect();
+        assert_eq!(values, vec![
+            (Decimal::ZERO, Decimal::ZERO),
+            (Decimal::ZERO, Decimal::from(1000)),
+            (Decimal::ZERO, Decimal::from(1000)),
+            // the last purchase revalues the whole position
+            (Decimal::from(-120), Decimal::from(1320)),
+        ]);
+        assert_eq!(history[3].value.amount, Decimal::from(1200));
+    }
+
+    #[test]
+    fn values_securities_at_stored_close_prices_newer_than_trades() {
+        let operations = [trade("2023-01-02 10:00:00", TradeOperationSide::Buy, 10, 100)];
+        let price = |day, close| DailyPrice {
+            ticker: "VOO".to_string(),
+            date: NaiveDate::from_ymd_opt(2023, 1, day).unwrap(),
+            close: Money::new(Decimal::from(close), "USD".to_string()),
+        };
+        let price_book: PriceBook = [price(1, 90), price(3, 105), price(5, 110)].into_iter().collect();
+        let dates: Vec<NaiveDate> = [2, 3, 4, 5].into_iter().map(|day| NaiveDate::from_ymd_opt(2023, 1, day).unwrap()).collect();
+
+        let market_data = MarketData { prices: price_book, ..Default::default() };
+        let history = fold_value_history(operations.iter(), [].iter(), [].iter(), "USD", &market_data, &dates).unwrap();
+        let securities: Vec<Decimal> = history.iter().map(|p| p.securities.amount).collect();
+        // the close of the first day is older than the trade
+        assert_eq!(securities, vec![Decimal::from(1000), Decimal::from(1050), Decimal::from(1050), Decimal::from(1100)]);
+    }
+
+    #[test]
+    fn keeps_cash_in_its_currency_and_converts_at_the_rate_of_the_valuation_day() {
+        let operations = [trade("2023-01-03 10:00:00", TradeOperationSide::Buy, 5, 100)];
+        let transactions = [FiscalTransaction {
+            amount: Money::new(Decimal::from(1000), "EUR".to_string()),
+            ..funding("2023-01-02 10:00:00", 0)
+        }];
+        let rate = |day, rate| FxRate {
+            date: NaiveDate::from_ymd_opt(2023, 1, day).unwrap(),
+            base_currency: "EUR".to_string(),
+            quote_currency: "USD".to_string(),
+            rate: Decimal::new(rate, 2),
+        };
+        let market_data = MarketData { fx_rates: [rate(2, 110), rate(5, 120)].into_iter().collect(), ..Default::default() };
+        let dates: Vec<NaiveDate> = [2, 3, 5].into_iter().map(|day| NaiveDate::from_ymd_opt(2023, 1, day).unwrap()).collect();
+
+        let history = fold_value_history(operations.iter(), transactions.iter(), [].iter(), "USD", &market_data, &dates).unwrap();
+        let values: Vec<(Decimal, Decimal)> = history.iter().map(|p| (p.cash.amount, p.securities.amount)).collect();
+        assert_eq!(values, vec![
+            (Decimal::from(1100), Decimal::ZERO),
+            (Decimal::from(600), Decimal::from(500)),
+            (Decimal::from(700), Decimal::from(500)),
+        ]);
+
+        let valuation = Valuation::replay(&market_data, operations.iter(), transactions.iter(), [].iter(), date_time("2023-01-06 00:00:00"));
+        assert_eq!(valuation.cash_balances(), vec![
+            Money::new(Decimal::from(1000), "EUR".to_string()),
+            Money::new(Decimal::from(-500), "USD".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn counts_commissions_of_freedomfinance_trades_without_ledger_rows() {
+        let commission = Money::new(Decimal::from(2), "USD".to_string());
+        let ff_trade = |external_id: &str| TradeOperation {
+            operation_source: OperationSource::FreedomfinanceReport,
+            external_id: Some(external_id.to_string()),
+            commission: Some(commission.clone()),
+            ..trade("2023-01-02 10:00:00", TradeOperationSide::Buy, 1, 100)
+        };
+        let operations = [ff_trade("1"), ff_trade("2")];
+        let transactions = [FiscalTransaction {
+            operation_source: OperationSource::FreedomfinanceReport,
+            amount: Money::new(-commission.amount, "USD".to_string()),
+            operation_type: FiscalTransactionType::Commission,
+            metadata: serde_json::json!({ "trade_external_id": "1" }),
+            ..funding("2023-01-02 10:00:00", 0)
+        }];
+
+        let market_data = MarketData::default();
+        let valuation = Valuation::replay(&market_data, operations.iter(), transactions.iter(), [].iter(), date_time("2023-01-03 00:00:00"));
+        // the commission of the first trade is booked in the ledger, of the second one only with the trade
+        assert_eq!(valuation.cash_balances(), vec![Money::new(Decimal::from(-204), "USD".to_string())]);
+    }
+}
@@ -2,3 +2,4 @@ pub mod model;
 pub mod repository;
 pub mod resource;
 pub mod security;
+pub mod service;
@@ -5,7 +5,7 @@ use serde::Deserialize;
 
 use uuid::Uuid;
 
-use crate::database::schema;
+use crate::{business::lot::model::CostBasisMethod, database::schema};
 
 // --- orm only
 
@@ -23,5 +23,7 @@ pub struct InsertPortfolio<'a> {
 pub struct SelectPortfolio {
     pub id: Uuid,
     pub label: String,
-    pub app_user_id: Uuid
+    pub app_user_id: Uuid,
+    pub cost_basis_method: CostBasisMethod,
+    pub base_currency: String,
 }
@@ -1,7 +1,7 @@
 use diesel::prelude::*;
 use uuid::Uuid;
 
-use crate::{business::model::BrokerType, database::{schema::{self, portfolio::dsl}, CommonRepository, RepositoryError}};
+use crate::{business::{lot::model::CostBasisMethod, model::BrokerType}, database::{schema::{self, portfolio::dsl}, CommonRepository, RepositoryError}};
 
 use super::model::{SelectPortfolio, InsertPortfolio};
 
@@ -55,6 +55,18 @@ impl CommonRepository {
         Ok(result)
     }
 
+    pub fn update_portfolio_cost_basis_method(&self, portfolio_id: Uuid, cost_basis_method: CostBasisMethod) -> Result<usize, RepositoryError> {
+        Ok(diesel::update(dsl::portfolio.find(portfolio_id))
+            .set(dsl::cost_basis_method.eq(cost_basis_method))
+            .execute(&mut self.pool.get()?)?)
+    }
+
+    pub fn update_portfolio_base_currency(&self, portfolio_id: Uuid, base_currency: &str) -> Result<usize, RepositoryError> {
+        Ok(diesel::update(dsl::portfolio.find(portfolio_id))
+            .set(dsl::base_currency.eq(base_currency))
+            .execute(&mut self.pool.get()?)?)
+    }
+
     pub fn delete_portfolio(&self, user_id: Uuid, porfolio_id: Uuid) -> Result<usize, RepositoryError> {
         let affected = diesel::delete(dsl::portfolio
             .filter(dsl::app_user_id.eq(user_id))
@@ -1,19 +1,61 @@
-use async_graphql::{Context, InputObject, Object};
+use std::{collections::HashMap, sync::Mutex};
+
+use async_graphql::{Context, InputObject, Object, SimpleObject};
+use chrono::NaiveDate;
 use rust_decimal::Decimal;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::{business::model::{BrokerType, Money}, web::{errors::DescriptiveError, graphql::{get_claims, get_state}}};
+use super::security::is_portfolio_owner;
+use crate::{business::{csv_mapping::model::CsvMappingProfile, lot::{model::CostBasisMethod, resource::{LotAssignment, RealizedPnl}}, model::{BrokerType, Money}, performance::{resource::{ValueInterval, ValuePoint}, service::{calculate_performance, calculate_value_history, Performance}}, position_snapshot::{resource::BrokerReconciliation, service::calculate_reconciliations}}, web::{errors::DescriptiveError, graphql::{get_claims, get_state}}, ApplicationState};
+
+/// Period between the start of `from` and the end of `to`, and the currency of a performance
+type PerformanceKey = (Option<NaiveDate>, Option<NaiveDate>, String);
 
 pub struct Portfolio {
     pub id: Uuid,
-    pub title: String
+    pub title: String,
+    pub cost_basis_method: CostBasisMethod,
+    pub base_currency: String,
+    /// Total returns calculated for fields of this portfolio, by currency
+    total_returns: Mutex<HashMap<String, TotalReturn>>,
+    /// Performance calculated for fields of this portfolio, by period and currency
+    performances: Mutex<HashMap<PerformanceKey, Performance>>,
+}
+
+impl Portfolio {
+    /// Calculates the total return once for every currency, which is shared by every field of it
+    fn total_return_in(&self, state: &ApplicationState, currency: &str) -> Result<TotalReturn, DescriptiveError> {
+        let mut total_returns = self.total_returns.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(total_return) = total_returns.get(currency) {
+            return Ok(total_return.clone());
+        }
+        let total_return = super::service::calculate_total_return(state, self.id, currency)?;
+        total_returns.insert(currency.to_string(), total_return.clone());
+        Ok(total_return)
+    }
+
+    /// Calculates the performance once for every period and currency, so that both of its
+    /// returns requested together share it
+    fn performance_in(&self, state: &ApplicationState, from: Option<NaiveDate>, to: Option<NaiveDate>, currency: &str) -> Result<Performance, DescriptiveError> {
+        let mut performances = self.performances.lock().unwrap_or_else(|err| err.into_inner());
+        let key = (from, to, currency.to_string());
+        if let Some(performance) = performances.get(&key) {
+            return Ok(performance.clone());
+        }
+        let performance = calculate_performance(state, self.id, from, to, currency)?;
+        performances.insert(key, performance.clone());
+        Ok(performance)
+    }
 }
 
 #[Object(rename_fields="camelCase", rename_args="camelCase")]
 impl Portfolio {
     async fn id(&self) -> Uuid { self.id }
     async fn title(&self) -> String { self.title.clone() }
+    async fn cost_basis_method(&self) -> CostBasisMethod { self.cost_basis_method }
+    /// Currency in which aggregates of this portfolio are calculated by default
+    async fn base_currency(&self) -> String { self.base_currency.clone() }
     async fn brokerages<'ctx>(&self, ctx: &Context<'ctx>) -> async_graphql::Result<Vec<BrokerType>> {
         let state = get_state(ctx)?;
         Ok(state.repository.list_portfolio_brokerages(self.id)?)
@@ -22,15 +64,193 @@ impl Portfolio {
         let state = get_state(ctx)?;
         Ok(super::super::user_transaction::service::count_user_transactions(state, self.id)?)
     }
-    async fn total_return_percentage(&self) -> async_graphql::Result<Decimal> {
-        Ok(Decimal::ZERO)
+    /// Positions that are currently open, derived from the trade operations of this portfolio
+    async fn holdings<'ctx>(&self, ctx: &Context<'ctx>) -> async_graphql::Result<Vec<Holding>> {
+        let state = get_state(ctx)?;
+        Ok(super::service::calculate_holdings(state, self.id)?)
+    }
+    /// Market value of open positions, converted to the requested currency (the base currency
+    /// by default) at current exchange rates. When a rate is older than a week the latest
+    /// known one is used, which `totalReturn` tells with `staleFxRateDate`.
+    async fn holdings_value<'ctx>(&self, ctx: &Context<'ctx>, currency: Option<String>) -> async_graphql::Result<Money> {
+        let state = get_state(ctx)?;
+        let currency = currency.unwrap_or_else(|| self.base_currency.clone());
+        Ok(super::service::calculate_holdings_value(state, self.id, &currency)?)
+    }
+    /// Cash left after every operation of this portfolio, in each currency
+    async fn cash_balances<'ctx>(&self, ctx: &Context<'ctx>, currency: Option<String>) -> async_graphql::Result<CashBalances> {
+        let state = get_state(ctx)?;
+        let currency = currency.unwrap_or_else(|| self.base_currency.clone());
+        Ok(super::service::calculate_cash_balances(state, self.id, &currency)?)
+    }
+    /// Lots closed by every sell trade operation of this portfolio
+    async fn lot_assignments<'ctx>(&self, ctx: &Context<'ctx>) -> async_graphql::Result<Vec<LotAssignment>> {
+        let state = get_state(ctx)?;
+        Ok(state.repository.list_lot_assignments(self.id)?
+            .into_iter()
+            .map(LotAssignment::from)
+            .collect())
+    }
+    /// Profit or loss realized by sells of this portfolio, net of commissions
+    async fn realized_pnl<'ctx>(&self, ctx: &Context<'ctx>) -> async_graphql::Result<RealizedPnl> {
+        let state = get_state(ctx)?;
+        Ok(crate::business::lot::service::calculate_realized_pnl(state, self.id)?)
+    }
+    /// Total return valued at the last known prices, including dividends, taxes and commissions,
+    /// absolute and relative to net funding. Calculated in the requested currency, the base
+    /// currency by default.
+    async fn total_return<'ctx>(&self, ctx: &Context<'ctx>, currency: Option<String>) -> async_graphql::Result<TotalReturn> {
+        let state = get_state(ctx)?;
+        let currency = currency.unwrap_or_else(|| self.base_currency.clone());
+        Ok(self.total_return_in(state, &currency)?)
+    }
+    /// Total return relative to net funding of the portfolio, in percents. Calculated in the
+    /// requested currency, the base currency by default.
+    async fn total_return_percentage<'ctx>(&self, ctx: &Context<'ctx>, currency: Option<String>) -> async_graphql::Result<Decimal> {
+        let state = get_state(ctx)?;
+        let currency = currency.unwrap_or_else(|| self.base_currency.clone());
+        Ok(self.total_return_in(state, &currency)?.percentage)
     }
-    async fn total_return_value(&self) -> async_graphql::Result<Money> {
-        Ok(Money { amount: Decimal::ZERO, currency: "USD".to_string() })
+    /// Total return valued at the last known prices, including dividends, taxes and
+    /// commissions. Calculated in the requested currency, the base currency by default.
+    async fn total_return_value<'ctx>(&self, ctx: &Context<'ctx>, currency: Option<String>) -> async_graphql::Result<Money> {
+        let state = get_state(ctx)?;
+        let currency = currency.unwrap_or_else(|| self.base_currency.clone());
+        Ok(self.total_return_in(state, &currency)?.value)
     }
-    async fn annual_income(&self) -> async_graphql::Result<Money> {
-        Ok(Money { amount: Decimal::ZERO, currency: "USD".to_string() })
+    /// Time-weighted return between the start of `from` and the end of `to`, in percents.
+    /// Eliminates the effect of funding and withdrawals, so returns of portfolios with
+    /// different contributions can be compared. Calculated in the requested currency, the
+    /// base currency by default.
+    async fn time_weighted_return<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+        currency: Option<String>,
+    ) -> async_graphql::Result<Option<Decimal>> {
+        let state = get_state(ctx)?;
+        let currency = currency.unwrap_or_else(|| self.base_currency.clone());
+        Ok(self.performance_in(state, from, to, &currency)?.time_weighted_return)
+    }
+    /// Money-weighted return (XIRR) between the start of `from` and the end of `to`, annualized,
+    /// in percents. Accounts for timing and size of funding and withdrawals. Calculated in
+    /// the requested currency, the base currency by default.
+    async fn money_weighted_return<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+        currency: Option<String>,
+    ) -> async_graphql::Result<Option<Decimal>> {
+        let state = get_state(ctx)?;
+        let currency = currency.unwrap_or_else(|| self.base_currency.clone());
+        Ok(self.performance_in(state, from, to, &currency)?.money_weighted_return)
     }
+    /// Value of the portfolio at the end of every interval (a day by default) between `from`
+    /// and `to`, suitable for a chart. Securities are valued at the last known price, and
+    /// converted to the requested currency (the base currency by default) at the exchange
+    /// rates of every day.
+    async fn value_history<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+        interval: Option<ValueInterval>,
+        currency: Option<String>,
+    ) -> async_graphql::Result<Vec<ValuePoint>> {
+        let state = get_state(ctx)?;
+        let currency = currency.unwrap_or_else(|| self.base_currency.clone());
+        let interval = interval.unwrap_or(ValueInterval::Day);
+        Ok(calculate_value_history(state, self.id, from, to, interval, &currency)?)
+    }
+    /// Latest positions and cash reported by every broker compared with the ones derived from
+    /// imported operations. Mismatches point at operations missing from the imports.
+    async fn reconciliation<'ctx>(&self, ctx: &Context<'ctx>) -> async_graphql::Result<Vec<BrokerReconciliation>> {
+        let state = get_state(ctx)?;
+        Ok(calculate_reconciliations(state, self.id)?)
+    }
+    /// Saved ways of reading CSV files of brokers without an own importer
+    async fn csv_mapping_profiles<'ctx>(&self, ctx: &Context<'ctx>) -> async_graphql::Result<Vec<CsvMappingProfile>> {
+        let state = get_state(ctx)?;
+        Ok(state.repository.list_csv_mapping_profiles(self.id)?
+            .into_iter()
+            .map(CsvMappingProfile::try_from)
+            .collect::<Result<_, _>>()?)
+    }
+    /// Dividend income net of withholding taxes, received and expected within a year.
+    /// Totals are calculated in the requested currency, the base currency by default.
+    async fn annual_income<'ctx>(&self, ctx: &Context<'ctx>, currency: Option<String>) -> async_graphql::Result<AnnualIncome> {
+        let state = get_state(ctx)?;
+        let currency = currency.unwrap_or_else(|| self.base_currency.clone());
+        Ok(super::service::calculate_annual_income(state, self.id, &currency)?)
+    }
+}
+
+/// An open position in a single instrument, traded in a single currency.
+#[derive(Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct Holding {
+    /// Ticker of the held instrument, instrument symbol.
+    pub ticker: String,
+    pub isin: Option<String>,
+    /// Amount of held securities. Negative when more securities were sold than bought.
+    pub quantity: i32,
+    /// Weighted average price paid for a single security of this position.
+    pub average_cost: Money,
+    /// Amount of currency spent on securities of this position that are still held.
+    pub total_invested: Money,
+    /// Brokerages through which this position was traded.
+    pub brokerages: Vec<BrokerType>,
+    /// Currency in which the instrument is traded.
+    pub currency: String,
+    /// Value of held securities at the last known price, in the currency of the instrument.
+    pub market_value: Option<Money>,
+}
+
+/// Dividend income of a portfolio net of withholding taxes, grouped by currency.
+#[derive(Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct AnnualIncome {
+    /// Income received during the last twelve months.
+    pub trailing_twelve_months: Vec<Money>,
+    /// Income expected during the next twelve months from the current holdings,
+    /// assuming each instrument keeps paying its last dividend with the same frequency.
+    pub forward: Vec<Money>,
+    /// Income received during the last twelve months, in a single currency.
+    pub trailing_twelve_months_total: Money,
+    /// Income expected during the next twelve months, in a single currency.
+    pub forward_total: Money,
+    /// Day of the oldest exchange rate used for the totals that was published more than a week
+    /// before the conversion, because no newer one is known. Totals are approximate then.
+    pub stale_fx_rate_date: Option<NaiveDate>,
+}
+
+/// Cash of a portfolio, which includes funding, proceeds of sells, dividends, taxes and
+/// commissions, less purchases and withdrawals.
+#[derive(Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct CashBalances {
+    /// Cash in every currency, negative when more was spent than received.
+    pub balances: Vec<Money>,
+    /// Sum of balances converted to a single currency at current exchange rates.
+    pub total: Money,
+    /// Day of the oldest exchange rate used for the total that was published more than a week
+    /// before the conversion, because no newer one is known. The total is approximate then.
+    pub stale_fx_rate_date: Option<NaiveDate>,
+}
+
+/// Return of a portfolio since its first operation, in a single currency.
+#[derive(Serialize, SimpleObject, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TotalReturn {
+    /// Market value of open positions plus every cash flow except funding and withdrawals.
+    pub value: Money,
+    /// Return value relative to net funding in percents, or to the cost of purchases when no funding is known.
+    pub percentage: Decimal,
+    /// Day of the oldest exchange rate used for the return that was published more than a week
+    /// before the conversion, because no newer one is known. The return is approximate then.
+    pub stale_fx_rate_date: Option<NaiveDate>,
 }
 
 #[derive(Deserialize, InputObject)]
@@ -41,7 +261,14 @@ pub struct CreatePortfolio {
 
 impl From<super::model::SelectPortfolio> for Portfolio {
     fn from(value: super::model::SelectPortfolio) -> Self {
-        Portfolio { id: value.id, title: value.label }
+        Portfolio {
+            id: value.id,
+            title: value.label,
+            cost_basis_method: value.cost_basis_method,
+            base_currency: value.base_currency,
+            total_returns: Mutex::default(),
+            performances: Mutex::default(),
+        }
     }
 }
 
@@ -72,6 +299,21 @@ impl PortfolioMutation {
         Ok(created.into())
     }
 
+    /// Change the currency in which aggregates of a portfolio are calculated by default
+    async fn set_portfolio_base_currency(&self, ctx: &Context<'_>, portfolio_id: Uuid, currency: String) -> async_graphql::Result<Portfolio> {
+        let claims = get_claims(ctx)?;
+        let state = get_state(ctx)?;
+
+        if currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_uppercase()) {
+            return Err(DescriptiveError::BadRequest("currency must be a three letter ISO 4217 code".to_string()).into());
+        }
+        is_portfolio_owner(state, claims.sub, portfolio_id)?;
+        state.repository.update_portfolio_base_currency(portfolio_id, &currency)?;
+        let updated = state.repository.find_portfolio_by_id(portfolio_id)?
+            .ok_or(DescriptiveError::NotFound { resource: "portfolio".to_string() })?;
+        Ok(updated.into())
+    }
+
     /// Delete portfolio
     async fn delete_portfolio(&self, ctx: &Context<'_>, id: Uuid) -> async_graphql::Result<Uuid> {
         let claims = get_claims(ctx)?;
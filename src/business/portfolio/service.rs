@@ -0,0 +1,652 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+use crate::{business::{currency_conversion::service::list_fiscal_transactions_with_conversions, fiscal_transaction::model::{FiscalTransaction, FiscalTransactionType}, fx_rate::{model::MissingFxRateError, service::FxRateBook}, model::{BrokerType, Money, OperationSource}, performance::service::{fold_transferred_value, MarketData, Valuation}, price::service::PriceBook, security_transfer::model::{SecurityTransfer, TransferDirection}, trade_operation::model::{TradeOperation, TradeOperationSide}}, web::errors::DescriptiveError, ApplicationState};
+
+use super::resource::{AnnualIncome, CashBalances, Holding, TotalReturn};
+
+
+pub fn calculate_holdings(state: &ApplicationState, portfolio_id: Uuid) -> Result<Vec<Holding>, DescriptiveError> {
+    let trade_operations = state.repository.list_trade_operations(portfolio_id)?;
+    let security_transfers = state.repository.list_security_transfers(portfolio_id)?;
+    let now = chrono::Utc::now().naive_utc();
+    let market_data = MarketData {
+        prices: PriceBook::load(state, &trade_operations.iter().map(|o| o.i.instrument_symbol.clone())
+            .chain(security_transfers.iter().map(|t| t.i.instrument_symbol.clone()))
+            .collect::<Vec<_>>(), now.date())?,
+        ..Default::default()
+    };
+    let valuation = Valuation::replay(&market_data, trade_operations.iter().map(|o| &o.i), [].iter(), security_transfers.iter().map(|t| &t.i), now);
+
+    let mut holdings = fold_holdings(trade_operations.iter().map(|o| &o.i), security_transfers.iter().map(|t| &t.i));
+    for holding in holdings.iter_mut() {
+        holding.market_value = valuation.price(&holding.ticker, &holding.currency, now)
+            .map(|price| Money::new((price * Decimal::from(holding.quantity)).normalize(), holding.currency.clone()));
+    }
+    Ok(holdings)
+}
+
+/// Market value of open positions converted to `currency` at the current exchange rates
+pub fn calculate_holdings_value(state: &ApplicationState, portfolio_id: Uuid, currency: &str) -> Result<Money, DescriptiveError> {
+    let trade_operations = state.repository.list_trade_operations(portfolio_id)?;
+    let security_transfers = state.repository.list_security_transfers(portfolio_id)?;
+    let now = chrono::Utc::now().naive_utc();
+    let market_data = MarketData::load(state, &trade_operations, &[], &security_transfers, currency, now.date())?;
+    let (_, securities) = Valuation::replay(&market_data, trade_operations.iter().map(|o| &o.i), [].iter(), security_transfers.iter().map(|t| &t.i), now)
+        .value_parts(currency, now)?;
+    Ok(Money::new(securities.round_dp(2).normalize(), currency.to_string()))
+}
+
+/// Current cash of a portfolio in every currency, and their sum converted to `currency`
+pub fn calculate_cash_balances(state: &ApplicationState, portfolio_id: Uuid, currency: &str) -> Result<CashBalances, DescriptiveError> {
+    let trade_operations = state.repository.list_trade_operations(portfolio_id)?;
+    let fiscal_transactions = list_fiscal_transactions_with_conversions(state, portfolio_id)?;
+    let now = chrono::Utc::now().naive_utc();
+    let market_data = MarketData::load(state, &trade_operations, &fiscal_transactions, &[], currency, now.date())?;
+    let valuation = Valuation::replay(&market_data, trade_operations.iter().map(|o| &o.i), fiscal_transactions.iter().map(|t| &t.i), [].iter(), now);
+    let (total, _) = valuation.value_parts(currency, now)?;
+    Ok(CashBalances {
+        balances: valuation.cash_balances(),
+        total: Money::new(total.round_dp(2).normalize(), currency.to_string()),
+        stale_fx_rate_date: market_data.fx_rates.stale_since(),
+    })
+}
+
+#[derive(Default)]
+struct PositionAccumulator {
+    isin: Option<String>,
+    quantity: i32,
+    total_invested: Decimal,
+    brokerages: BTreeSet<BrokerType>,
+}
+
+impl PositionAccumulator {
+    fn track(&mut self, isin: &Option<String>, broker: Option<BrokerType>) {
+        if self.isin.is_none() {
+            self.isin = isin.clone();
+        }
+        if let Some(broker) = broker {
+            self.brokerages.insert(broker);
+        }
+    }
+
+    fn add(&mut self, quantity: i32, cost: Decimal) {
+        self.quantity += quantity;
+        self.total_invested += cost;
+        self.reset_without_securities();
+    }
+
+    /// Changes amount of securities without changing the invested amount, as a split does.
+    /// The position may go through zero while old securities are replaced with new ones.
+    fn split(&mut self, quantity: i32) {
+        self.quantity += quantity;
+    }
+
+    /// Releases the invested amount of removed securities at the current average price
+    fn remove(&mut self, quantity: i32) {
+        if self.quantity > 0 {
+            let removed = quantity.min(self.quantity);
+            let average_cost = self.total_invested / Decimal::from(self.quantity);
+            self.total_invested -= average_cost * Decimal::from(removed);
+        }
+        self.quantity -= quantity;
+        self.reset_without_securities();
+    }
+
+    fn reset_without_securities(&mut self) {
+        if self.quantity <= 0 {
+            // a short position or a sell without known buys carries no cost basis
+            self.total_invested = Decimal::ZERO;
+        }
+    }
+}
+
+enum PositionChange<'a> {
+    Trade(&'a TradeOperation),
+    Transfer(&'a SecurityTransfer),
+}
+
+impl PositionChange<'_> {
+    fn date_time(&self) -> NaiveDateTime {
+        match self {
+            PositionChange::Trade(operation) => operation.date_time,
+            PositionChange::Transfer(transfer) => transfer.date_time,
+        }
+    }
+}
+
+/// Replays trade operations and security transfers in chronological order and returns
+/// positions which are still open. Positions are tracked per instrument and trade currency,
+/// using weighted average cost: buys and incoming transfers increase the invested amount,
+/// sells and outgoing transfers release it at the current average price. A transfer without
+/// a carried-over cost basis joins a position of the same instrument, or is skipped when
+/// its currency can't be told.
+pub fn fold_holdings<'a>(
+    trade_operations: impl Iterator<Item = &'a TradeOperation>,
+    security_transfers: impl Iterator<Item = &'a SecurityTransfer>,
+) -> Vec<Holding> {
+    let mut changes: Vec<PositionChange> = trade_operations.map(PositionChange::Trade)
+        .chain(security_transfers.map(PositionChange::Transfer))
+        .collect();
+    changes.sort_by_key(|c| c.date_time());
+
+    let mut positions: BTreeMap<(String, String), PositionAccumulator> = BTreeMap::new();
+    for change in changes {
+        match change {
+            PositionChange::Trade(operation) => {
+                let key = (operation.instrument_symbol.clone(), operation.summ.currency.clone());
+                let position = positions.entry(key).or_default();
+                position.track(&operation.isin, operation.broker);
+                match operation.side {
+                    TradeOperationSide::Buy => position.add(operation.quantity, operation.summ.amount),
+                    TradeOperationSide::Sell => position.remove(operation.quantity),
+                }
+            },
+            PositionChange::Transfer(transfer) => {
+                let currency = transfer.cost_basis.as_ref()
+                    .map(|cost_basis| cost_basis.currency.clone())
+                    .or_else(|| positions.keys()
+                        .find(|(ticker, _)| *ticker == transfer.instrument_symbol)
+                        .map(|(_, currency)| currency.clone()));
+                let Some(currency) = currency else { continue };
+                let position = positions.entry((transfer.instrument_symbol.clone(), currency)).or_default();
+                position.track(&transfer.isin, transfer.broker);
+                match transfer.direction {
+                    TransferDirection::In if transfer.is_split => position.split(transfer.quantity),
+                    TransferDirection::Out if transfer.is_split => position.split(-transfer.quantity),
+                    TransferDirection::In => position.add(
+                        transfer.quantity,
+                        transfer.cost_basis.as_ref().map(|c| c.amount).unwrap_or_default(),
+                    ),
+                    TransferDirection::Out => position.remove(transfer.quantity),
+                }
+            },
+        }
+    }
+
+    positions.into_iter()
+        .filter(|(_, position)| position.quantity != 0)
+        .map(|((ticker, currency), position)| {
+            let average_cost = if position.quantity > 0 {
+                position.total_invested / Decimal::from(position.quantity)
+            } else {
+                Decimal::ZERO
+            };
+            Holding {
+                ticker,
+                isin: position.isin,
+                quantity: position.quantity,
+                average_cost: Money::new(average_cost.normalize(), currency.clone()),
+                total_invested: Money::new(position.total_invested.normalize(), currency.clone()),
+                brokerages: position.brokerages.into_iter().collect(),
+                currency,
+                market_value: None,
+            }
+        })
+        .collect()
+}
+
+pub fn calculate_total_return(state: &ApplicationState, portfolio_id: Uuid, currency: &str) -> Result<TotalReturn, DescriptiveError> {
+    let trade_operations = state.repository.list_trade_operations(portfolio_id)?;
+    let fiscal_transactions = list_fiscal_transactions_with_conversions(state, portfolio_id)?;
+    let security_transfers = state.repository.list_security_transfers(portfolio_id)?;
+    let now = chrono::Utc::now().naive_utc();
+    let market_data = MarketData::load(state, &trade_operations, &fiscal_transactions, &security_transfers, currency, now.date())?;
+    Ok(fold_total_return(
+        trade_operations.iter().map(|o| &o.i),
+        fiscal_transactions.iter().map(|t| &t.i),
+        security_transfers.iter().map(|t| &t.i),
+        currency,
+        &market_data,
+        now,
+    )?)
+}
+
+/// External ids of Freedom Finance trades which commissions are imported from the commissions
+/// ledger of a report, as separate commission transactions linked to the trade
+pub fn trades_with_booked_commission<'a>(fiscal_transactions: impl Iterator<Item = &'a FiscalTransaction>) -> HashSet<String> {
+    fiscal_transactions
+        .filter(|t| t.operation_source == OperationSource::FreedomfinanceReport)
+        .filter(|t| matches!(t.operation_type, FiscalTransactionType::Commission))
+        .filter_map(|t| t.metadata["trade_external_id"].as_str().map(|id| id.to_string()))
+        .collect()
+}
+
+/// Exante books every trade commission as a separate commission transaction as well, Freedom
+/// Finance does so only for trades of `trades_with_booked_commission`. Counting the commission
+/// of such a trade itself would account for it twice.
+pub fn is_trade_commission_booked_separately(operation: &TradeOperation, trades_with_booked_commission: &HashSet<String>) -> bool {
+    match operation.operation_source {
+        OperationSource::ExanteReport => true,
+        OperationSource::FreedomfinanceReport => operation.external_id.as_ref()
+            .is_some_and(|id| trades_with_booked_commission.contains(id)),
+        _ => false,
+    }
+}
+
+/// Calculates total return of a portfolio valued in `currency` at the moment `now`. Funding,
+/// withdrawals and purchases are converted at exchange rates of their days, the current
+/// value of the portfolio at the rates of today. Transferred securities count as funding
+/// worth their value at the moment of the transfer.
+pub fn fold_total_return<'a>(
+    trade_operations: impl Iterator<Item = &'a TradeOperation>,
+    fiscal_transactions: impl Iterator<Item = &'a FiscalTransaction>,
+    security_transfers: impl Iterator<Item = &'a SecurityTransfer>,
+    currency: &str,
+    market_data: &MarketData,
+    now: NaiveDateTime,
+) -> Result<TotalReturn, MissingFxRateError> {
+    let trade_operations: Vec<&TradeOperation> = trade_operations.collect();
+    let fiscal_transactions: Vec<&FiscalTransaction> = fiscal_transactions.collect();
+    let security_transfers: Vec<&SecurityTransfer> = security_transfers.collect();
+
+    let mut purchases = Decimal::ZERO;
+    for operation in trade_operations.iter().filter(|o| o.side == TradeOperationSide::Buy) {
+        purchases += market_data.fx_rates.try_convert(&operation.summ, currency, operation.date_time.date())?;
+    }
+    let mut net_funding = Decimal::ZERO;
+    for transaction in fiscal_transactions.iter().filter(|t| matches!(t.operation_type, FiscalTransactionType::FundingWithdrawal)) {
+        net_funding += market_data.fx_rates.try_convert(&transaction.amount, currency, transaction.date_time.date())?;
+    }
+    net_funding += fold_transferred_value(
+        trade_operations.iter().copied(),
+        fiscal_transactions.iter().copied(),
+        security_transfers.iter().copied(),
+        currency,
+        market_data,
+        now,
+    )?;
+
+    let current_value = Valuation::replay(market_data, trade_operations.into_iter(), fiscal_transactions.into_iter(), security_transfers.into_iter(), now)
+        .value(currency, now)?;
+    let value = current_value - net_funding;
+    let base = if net_funding > Decimal::ZERO { net_funding } else { purchases };
+    let percentage = if base.is_zero() {
+        Decimal::ZERO
+    } else {
+        (value / base * Decimal::ONE_HUNDRED).round_dp(2)
+    };
+
+    Ok(TotalReturn {
+        value: Money::new(value.round_dp(2).normalize(), currency.to_string()),
+        percentage,
+        stale_fx_rate_date: market_data.fx_rates.stale_since(),
+    })
+}
+
+pub fn calculate_annual_income(state: &ApplicationState, portfolio_id: Uuid, currency: &str) -> Result<AnnualIncome, DescriptiveError> {
+    let trade_operations = state.repository.list_trade_operations(portfolio_id)?;
+    let fiscal_transactions = state.repository.list_fiscal_transactions(portfolio_id)?;
+    let security_transfers = state.repository.list_security_transfers(portfolio_id)?;
+    let now = chrono::Utc::now().naive_utc();
+    let market_data = MarketData::load(state, &trade_operations, &fiscal_transactions, &[], currency, now.date())?;
+    Ok(fold_annual_income(
+        trade_operations.iter().map(|o| &o.i),
+        fiscal_transactions.iter().map(|t| &t.i),
+        security_transfers.iter().map(|t| &t.i),
+        currency,
+        &market_data.fx_rates,
+        now,
+    )?)
+}
+
+/// Instrument, day and currency of a dividend payment
+type PaymentKey = (String, NaiveDate, String);
+
+fn payment_key(transaction: &FiscalTransaction) -> Option<PaymentKey> {
+    let ticker = transaction.symbol_id.clone()?;
+    Some((ticker, transaction.date_time.date(), transaction.amount.currency.clone()))
+}
+
+/// Dividends, their reversals and the taxes withheld from them are the parts of dividend income,
+/// each with the payment it belongs to. A tax is linked to its dividend by the id of the income it
+/// is withheld from when the broker reports one, as Freedom Finance does, or else by the instrument,
+/// the day and the currency of the payment. Taxes which are not linked to a dividend are left out.
+fn link_dividend_income<'a>(transactions: impl Iterator<Item = &'a FiscalTransaction>) -> Vec<(&'a FiscalTransaction, Option<PaymentKey>)> {
+    let (dividends, taxes): (Vec<&FiscalTransaction>, Vec<&FiscalTransaction>) = transactions
+        .filter(|t| matches!(t.operation_type,
+            FiscalTransactionType::Dividend | FiscalTransactionType::RevertedDividend | FiscalTransactionType::Tax))
+        .partition(|t| t.operation_type != FiscalTransactionType::Tax);
+    let metadata_id = |transaction: &'a FiscalTransaction, key: &str| transaction.metadata.get(key).and_then(|id| id.as_str());
+    let dividends_by_id: HashMap<&str, &FiscalTransaction> = dividends.iter()
+        .filter_map(|d| metadata_id(d, "transaction_id").map(|id| (id, *d)))
+        .collect();
+    let payments: HashSet<PaymentKey> = dividends.iter().filter_map(|d| payment_key(d)).collect();
+
+    let linked_taxes = taxes.into_iter().filter_map(|tax| {
+        let payment = match metadata_id(tax, "income_transaction_id") {
+            Some(income_id) => dividends_by_id.get(income_id).and_then(|d| payment_key(d)),
+            None => payment_key(tax).filter(|key| payments.contains(key)),
+        };
+        payment.map(|payment| (tax, Some(payment)))
+    });
+    dividends.into_iter()
+        .map(|dividend| (dividend, payment_key(dividend)))
+        .chain(linked_taxes)
+        .collect()
+}
+
+/// Net dividend income of a single instrument received on a single day
+struct DividendPayment {
+    date: NaiveDate,
+    date_time: NaiveDateTime,
+    net_amount: Money,
+}
+
+const MAX_PAYMENTS_FOR_CADENCE: usize = 5;
+
+/// Sums up dividend income received during the twelve months before `now`, and estimates
+/// income of the next twelve months. The estimate assumes every held instrument keeps paying
+/// the same net amount per security as its last dividend, with the same frequency as before.
+/// When frequency can't be inferred from a single payment, dividends are assumed to be annual.
+/// Totals are converted to `currency`: received income at exchange rates of the payment days,
+/// expected income at the rates of `now`.
+pub fn fold_annual_income<'a, 'b>(
+    trade_operations: impl Iterator<Item = &'a TradeOperation>,
+    fiscal_transactions: impl Iterator<Item = &'b FiscalTransaction>,
+    security_transfers: impl Iterator<Item = &'a SecurityTransfer>,
+    currency: &str,
+    fx_rates: &FxRateBook,
+    now: NaiveDateTime,
+) -> Result<AnnualIncome, MissingFxRateError> {
+    let trade_operations: Vec<&TradeOperation> = trade_operations.collect();
+    let security_transfers: Vec<&SecurityTransfer> = security_transfers.collect();
+    let income_transactions = link_dividend_income(fiscal_transactions);
+
+    let year_ago = now - Duration::days(365);
+    let mut trailing: BTreeMap<String, Decimal> = BTreeMap::new();
+    let mut trailing_total = Decimal::ZERO;
+    for (transaction, _) in income_transactions.iter().filter(|(t, _)| t.date_time > year_ago && t.date_time <= now) {
+        *trailing.entry(transaction.amount.currency.clone()).or_default() += transaction.amount.amount;
+        trailing_total += fx_rates.try_convert(&transaction.amount, currency, transaction.date_time.date())?;
+    }
+
+    let mut payments: BTreeMap<PaymentKey, (NaiveDateTime, Decimal, bool)> = BTreeMap::new();
+    for (transaction, key) in income_transactions.iter() {
+        let Some(key) = key.clone() else { continue };
+        let payment = payments.entry(key).or_insert((transaction.date_time, Decimal::ZERO, false));
+        payment.0 = payment.0.max(transaction.date_time);
+        payment.1 += transaction.amount.amount;
+        payment.2 |= matches!(transaction.operation_type, FiscalTransactionType::Dividend);
+    }
+    let mut payments_by_ticker: HashMap<String, Vec<DividendPayment>> = HashMap::new();
+    for ((ticker, date, currency), (date_time, net_amount, is_dividend)) in payments {
+        if is_dividend {
+            payments_by_ticker.entry(ticker).or_default().push(DividendPayment {
+                date, date_time, net_amount: Money::new(net_amount, currency),
+            });
+        }
+    }
+
+    let mut forward: BTreeMap<String, Decimal> = BTreeMap::new();
+    for holding in fold_holdings(trade_operations.iter().copied(), security_transfers.iter().copied()).iter().filter(|h| h.quantity > 0) {
+        let Some(ticker_payments) = payments_by_ticker.get(&holding.ticker) else { continue };
+        let Some(last_payment) = ticker_payments.last() else { continue };
+
+        let quantity_at_payment: i32 = fold_holdings(
+                trade_operations.iter()
+                    .copied()
+                    .filter(|o| o.instrument_symbol == holding.ticker && o.date_time <= last_payment.date_time),
+                security_transfers.iter()
+                    .copied()
+                    .filter(|t| t.instrument_symbol == holding.ticker && t.date_time <= last_payment.date_time))
+            .iter()
+            .map(|h| h.quantity)
+            .sum();
+        if quantity_at_payment <= 0 {
+            continue;
+        }
+
+        let recent_dates: Vec<NaiveDate> = ticker_payments.iter()
+            .rev()
+            .take(MAX_PAYMENTS_FOR_CADENCE)
+            .map(|p| p.date)
+            .collect();
+        let payments_per_year = if recent_dates.len() < 2 {
+            Decimal::ONE
+        } else {
+            let average_interval = (recent_dates[0] - recent_dates[recent_dates.len() - 1]).num_days()
+                / (recent_dates.len() as i64 - 1);
+            Decimal::from((365 + average_interval / 2) / average_interval.max(1)).clamp(Decimal::ONE, Decimal::from(12))
+        };
+
+        let per_security = last_payment.net_amount.amount / Decimal::from(quantity_at_payment);
+        *forward.entry(last_payment.net_amount.currency.clone()).or_default() +=
+            per_security * Decimal::from(holding.quantity) * payments_per_year;
+    }
+
+    let mut forward_total = Decimal::ZERO;
+    for (forward_currency, amount) in forward.iter() {
+        forward_total += fx_rates.try_convert(&Money::new(*amount, forward_currency.clone()), currency, now.date())?;
+    }
+
+    let into_money = |totals: BTreeMap<String, Decimal>| totals.into_iter()
+        .map(|(currency, amount)| Money::new(amount.round_dp(2).normalize(), currency))
+        .collect();
+    let total = |amount: Decimal| Money::new(amount.round_dp(2).normalize(), currency.to_string());
+    Ok(AnnualIncome {
+        trailing_twelve_months: into_money(trailing),
+        forward: into_money(forward),
+        trailing_twelve_months_total: total(trailing_total),
+        forward_total: total(forward_total),
+        stale_fx_rate_date: fx_rates.stale_since(),
+    })
+}
+
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDateTime;
+
+    use crate::business::{fx_rate::model::FxRate, model::OperationSource};
+
+    use super::*;
+
+    fn trade(date_time: &str, side: TradeOperationSide, ticker: &str, quantity: i32, price: i64, broker: BrokerType) -> TradeOperation {
+        TradeOperation {
+            operation_source: OperationSource::Manual,
+            broker: Some(broker),
+            external_id: None,
+            date_time: NaiveDateTime::parse_from_str(date_time, "%Y-%m-%d %H:%M:%S").unwrap(),
+            side,
+            instrument_symbol: ticker.to_string(),
+            isin: None,
+            price: Money::new(Decimal::from(price), "USD".to_string()),
+            quantity,
+            commission: None,
+            order_id: None,
+            summ: Money::new(Decimal::from(price * i64::from(quantity)), "USD".to_string()),
+            metadata: serde_json::Value::Null,
+            reported_pnl: None,
+        }
+    }
+
+    #[test]
+    fn folds_buys_and_sells_into_average_cost_positions() {
+        let operations = [
+            trade("2023-01-03 10:00:00", TradeOperationSide::Sell, "VOO", 2, 130, BrokerType::Exante),
+            trade("2023-01-01 10:00:00", TradeOperationSide::Buy, "VOO", 2, 100, BrokerType::Exante),
+            trade("2023-01-02 10:00:00", TradeOperationSide::Buy, "VOO", 2, 120, BrokerType::Freedomfinance),
+            trade("2023-01-02 10:00:00", TradeOperationSide::Buy, "SCHD", 3, 70, BrokerType::Exante),
+            trade("2023-01-04 10:00:00", TradeOperationSide::Sell, "SCHD", 3, 75, BrokerType::Exante),
+        ];
+
+        let holdings = fold_holdings(operations.iter(), [].iter());
+        assert_eq!(holdings.len(), 1);
+        let voo = &holdings[0];
+        assert_eq!(voo.ticker, "VOO");
+        assert_eq!(voo.quantity, 2);
+        assert_eq!(voo.average_cost.amount, Decimal::from(110));
+        assert_eq!(voo.total_invested.amount, Decimal::from(220));
+        assert_eq!(voo.brokerages, vec![BrokerType::Exante, BrokerType::Freedomfinance]);
+    }
+
+    fn transfer(date_time: &str, direction: TransferDirection, ticker: &str, quantity: i32, cost_basis: Option<i64>, broker: BrokerType) -> SecurityTransfer {
+        SecurityTransfer {
+            operation_source: OperationSource::Manual,
+            broker: Some(broker),
+            external_id: None,
+            date_time: NaiveDateTime::parse_from_str(date_time, "%Y-%m-%d %H:%M:%S").unwrap(),
+            direction,
+            instrument_symbol: ticker.to_string(),
+            isin: None,
+            quantity,
+            cost_basis: cost_basis.map(|amount| Money::new(Decimal::from(amount), "USD".to_string())),
+            metadata: serde_json::Value::Null,
+            is_split: false,
+        }
+    }
+
+    #[test]
+    fn moves_positions_between_brokers_with_transfers() {
+        let operations = [
+            trade("2023-01-01 10:00:00", TradeOperationSide::Buy, "VOO", 4, 100, BrokerType::Exante),
+            trade("2023-01-05 10:00:00", TradeOperationSide::Sell, "VOO", 1, 150, BrokerType::Freedomfinance),
+        ];
+        let transfers = [
+            transfer("2023-01-02 10:00:00", TransferDirection::Out, "VOO", 2, None, BrokerType::Exante),
+            transfer("2023-01-03 10:00:00", TransferDirection::In, "VOO", 2, Some(200), BrokerType::Freedomfinance),
+            transfer("2023-01-04 10:00:00", TransferDirection::In, "SCHD", 3, Some(210), BrokerType::Freedomfinance),
+            // currency of a new position isn't known without a cost basis
+            transfer("2023-01-04 10:00:00", TransferDirection::In, "IAU", 3, None, BrokerType::Freedomfinance),
+        ];
+
+        let holdings = fold_holdings(operations.iter(), transfers.iter());
+        assert_eq!(holdings.len(), 2);
+        let (schd, voo) = (&holdings[0], &holdings[1]);
+        assert_eq!(schd.quantity, 3);
+        assert_eq!(schd.average_cost.amount, Decimal::from(70));
+        assert_eq!(voo.quantity, 3);
+        assert_eq!(voo.average_cost.amount, Decimal::from(100));
+        assert_eq!(voo.total_invested.amount, Decimal::from(300));
+    }
+
+    #[test]
+    fn keeps_invested_amount_through_splits() {
+        let operations = [
+            trade("2023-01-01 10:00:00", TradeOperationSide::Buy, "VOO", 10, 100, BrokerType::Exante),
+        ];
+        let split = |direction: TransferDirection, quantity: i32| SecurityTransfer {
+            is_split: true,
+            ..transfer("2023-01-02 10:00:00", direction, "VOO", quantity, None, BrokerType::Exante)
+        };
+        let transfers = [split(TransferDirection::Out, 10), split(TransferDirection::In, 40)];
+
+        let holdings = fold_holdings(operations.iter(), transfers.iter());
+        assert_eq!(holdings.len(), 1);
+        assert_eq!(holdings[0].quantity, 40);
+        assert_eq!(holdings[0].average_cost.amount, Decimal::from(25));
+        assert_eq!(holdings[0].total_invested.amount, Decimal::from(1000));
+    }
+
+    fn transaction(operation_type: FiscalTransactionType, amount: i64) -> FiscalTransaction {
+        FiscalTransaction {
+            operation_source: OperationSource::Manual,
+            broker: None,
+            external_id: None,
+            date_time: NaiveDateTime::parse_from_str("2023-01-05 10:00:00", "%Y-%m-%d %H:%M:%S").unwrap(),
+            symbol_id: None,
+            amount: Money::new(Decimal::from(amount), "USD".to_string()),
+            operation_type,
+            commission: None,
+            metadata: serde_json::Value::Null,
+            ex_date: None,
+            amount_per_share: None,
+        }
+    }
+
+    fn dividend(date_time: &str, operation_type: FiscalTransactionType, ticker: &str, amount: Decimal) -> FiscalTransaction {
+        FiscalTransaction {
+            date_time: NaiveDateTime::parse_from_str(date_time, "%Y-%m-%d %H:%M:%S").unwrap(),
+            symbol_id: Some(ticker.to_string()),
+            amount: Money::new(amount, "USD".to_string()),
+            ..transaction(operation_type, 0)
+        }
+    }
+
+    #[test]
+    fn annual_income_is_net_of_taxes_and_extrapolated_by_cadence() {
+        let operations = [
+            trade("2022-01-10 10:00:00", TradeOperationSide::Buy, "VOO", 10, 100, BrokerType::Exante),
+            trade("2022-12-01 10:00:00", TradeOperationSide::Buy, "VOO", 10, 100, BrokerType::Exante),
+        ];
+        let transactions = [
+            dividend("2022-03-25 10:00:00", FiscalTransactionType::Dividend, "VOO", Decimal::from(10)),
+            dividend("2022-03-25 10:00:00", FiscalTransactionType::Tax, "VOO", Decimal::from(-3)),
+            dividend("2022-06-25 10:00:00", FiscalTransactionType::Dividend, "VOO", Decimal::from(10)),
+            dividend("2022-06-25 10:00:00", FiscalTransactionType::Tax, "VOO", Decimal::from(-3)),
+            dividend("2022-09-25 10:00:00", FiscalTransactionType::Dividend, "VOO", Decimal::from(10)),
+            dividend("2022-09-25 10:00:00", FiscalTransactionType::Tax, "VOO", Decimal::from(-3)),
+            dividend("2022-12-25 10:00:00", FiscalTransactionType::Dividend, "VOO", Decimal::from(20)),
+            dividend("2022-12-25 10:00:00", FiscalTransactionType::Tax, "VOO", Decimal::from(-6)),
+            transaction(FiscalTransactionType::Tax, -100),
+        ];
+        let now = NaiveDateTime::parse_from_str("2023-01-15 00:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+
+        let income = fold_annual_income(operations.iter(), transactions.iter(), [].iter(), "USD", &FxRateBook::default(), now).unwrap();
+        assert_eq!(income.trailing_twelve_months, vec![Money::new(Decimal::from(35), "USD".to_string())]);
+        // 0.7 net per security, 20 securities, 4 payments a year
+        assert_eq!(income.forward, vec![Money::new(Decimal::from(56), "USD".to_string())]);
+        assert_eq!(income.forward_total, Money::new(Decimal::from(56), "USD".to_string()));
+    }
+
+    #[test]
+    fn annual_income_counts_only_taxes_linked_to_dividends() {
+        let operations = [trade("2022-01-10 10:00:00", TradeOperationSide::Buy, "VOO", 10, 100, BrokerType::Freedomfinance)];
+        let with_ids = |transaction: FiscalTransaction, metadata: serde_json::Value| FiscalTransaction { metadata, ..transaction };
+        let transactions = [
+            with_ids(dividend("2022-12-23 10:00:00", FiscalTransactionType::Dividend, "VOO", Decimal::from(10)),
+                serde_json::json!({ "transaction_id": "1" })),
+            // withheld from the dividend a day later
+            with_ids(dividend("2022-12-24 10:00:00", FiscalTransactionType::Tax, "VOO", Decimal::from(-3)),
+                serde_json::json!({ "income_transaction_id": "1" })),
+            // withheld from some other income of the instrument
+            with_ids(dividend("2022-12-23 10:00:00", FiscalTransactionType::Tax, "VOO", Decimal::from(-1)),
+                serde_json::json!({ "income_transaction_id": "2" })),
+            // a tax of the instrument without a dividend on its day
+            dividend("2022-11-01 10:00:00", FiscalTransactionType::Tax, "VOO", Decimal::from(-5)),
+        ];
+        let now = NaiveDateTime::parse_from_str("2023-01-15 00:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+
+        let income = fold_annual_income(operations.iter(), transactions.iter(), [].iter(), "USD", &FxRateBook::default(), now).unwrap();
+        assert_eq!(income.trailing_twelve_months, vec![Money::new(Decimal::from(7), "USD".to_string())]);
+        // a single annual payment of 0.7 net per security
+        assert_eq!(income.forward, vec![Money::new(Decimal::from(7), "USD".to_string())]);
+    }
+
+    #[test]
+    fn total_return_accounts_for_market_value_and_cash_flows() {
+        let operations = [
+            trade("2023-01-01 10:00:00", TradeOperationSide::Buy, "VOO", 10, 100, BrokerType::Exante),
+            trade("2023-01-02 10:00:00", TradeOperationSide::Sell, "VOO", 5, 110, BrokerType::Exante),
+            trade("2023-01-03 10:00:00", TradeOperationSide::Buy, "VOO", 1, 120, BrokerType::Exante),
+        ];
+        let transactions = [
+            transaction(FiscalTransactionType::FundingWithdrawal, 2000),
+            transaction(FiscalTransactionType::Dividend, 10),
+            transaction(FiscalTransactionType::Tax, -3),
+            transaction(FiscalTransactionType::Commission, -2),
+        ];
+
+        let now = NaiveDateTime::parse_from_str("2023-01-06 00:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+
+        let total_return = fold_total_return(operations.iter(), transactions.iter(), [].iter(), "USD", &MarketData::default(), now).unwrap();
+        // 6 * 120 (market value) - 1000 + 550 - 120 (trades) + 10 - 3 - 2 (fiscal)
+        assert_eq!(total_return.value.amount, Decimal::from(155));
+        assert_eq!(total_return.percentage, Decimal::new(775, 2));
+
+        let fx_rates = [FxRate {
+            date: NaiveDate::from_ymd_opt(2023, 1, 1).unwrap(),
+            base_currency: "EUR".to_string(),
+            quote_currency: "USD".to_string(),
+            rate: Decimal::from(2),
+        }].into_iter().collect();
+        let market_data = MarketData { fx_rates, ..Default::default() };
+        let in_euro = fold_total_return(operations.iter(), transactions.iter(), [].iter(), "EUR", &market_data, now).unwrap();
+        assert_eq!(in_euro.value, Money::new(Decimal::new(775, 1), "EUR".to_string()));
+        assert_eq!(in_euro.percentage, Decimal::new(775, 2));
+    }
+}
@@ -0,0 +1,43 @@
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Selectable, Queryable};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{business::model::BrokerType, database::schema};
+
+/// A position or a cash balance as reported by the broker at the moment `taken_at`
+#[derive(Serialize,Deserialize,Insertable,Selectable,Queryable)]
+#[diesel(table_name = crate::database::schema::position_snapshot)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct SnapshotPosition {
+    pub taken_at: NaiveDateTime,
+    pub instrument_symbol: Option<String>, // none for a cash balance
+    pub isin: Option<String>,
+    pub quantity: Decimal, // amount of money for a cash balance
+    pub currency: String,
+}
+
+// --- orm model
+
+#[derive(Deserialize, Insertable)]
+#[diesel(table_name = schema::position_snapshot )]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct InsertSnapshotPosition {
+    pub portfolio_id: Uuid,
+    pub report_upload_id: Uuid,
+    pub broker: BrokerType,
+    #[diesel(embed)]
+    pub position: SnapshotPosition
+}
+
+#[derive(Deserialize, Queryable, Selectable)]
+#[diesel(table_name = schema::position_snapshot )]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct SelectSnapshotPosition {
+    pub id: Uuid,
+    pub report_upload_id: Uuid,
+    pub broker: BrokerType,
+    #[diesel(embed)]
+    pub i: SnapshotPosition
+}
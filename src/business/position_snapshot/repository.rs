@@ -0,0 +1,21 @@
+use diesel::{insert_into, prelude::*};
+use uuid::Uuid;
+
+use crate::database::{schema::position_snapshot::dsl, CommonRepository, RepositoryError};
+
+use super::model::{InsertSnapshotPosition, SelectSnapshotPosition};
+
+impl CommonRepository {
+    pub fn list_snapshot_positions(&self, portfolio_id: Uuid) -> Result<Vec<SelectSnapshotPosition>, RepositoryError> {
+        Ok(dsl::position_snapshot
+            .filter(dsl::portfolio_id.eq(portfolio_id))
+            .select(SelectSnapshotPosition::as_select())
+            .load(&mut self.pool.get()?)?)
+    }
+
+    pub fn create_snapshot_positions(&self, positions: Vec<InsertSnapshotPosition>) -> Result<usize, RepositoryError> {
+        Ok(insert_into(dsl::position_snapshot)
+            .values(positions)
+            .execute(&mut self.pool.get()?)?)
+    }
+}
@@ -0,0 +1,44 @@
+use async_graphql::SimpleObject;
+use chrono::NaiveDateTime;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::business::model::{BrokerType, Money};
+
+// --- model
+
+/// Comparison of the latest position snapshot reported by a broker with the positions and
+/// cash derived from operations of the broker
+#[derive(Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct BrokerReconciliation {
+    pub brokerage: BrokerType,
+    /// Report upload the snapshot comes from
+    pub report_upload_id: Uuid,
+    pub taken_at: NaiveDateTime,
+    pub positions: Vec<ReconciledPosition>,
+    pub cash: Vec<ReconciledCash>,
+    /// True when every position and cash balance matches, otherwise the import is likely incomplete
+    pub reconciled: bool,
+}
+
+#[derive(Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct ReconciledPosition {
+    pub ticker: String,
+    pub currency: String,
+    pub reported_quantity: Decimal,
+    pub calculated_quantity: Decimal,
+    pub matches: bool,
+}
+
+#[derive(Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct ReconciledCash {
+    pub currency: String,
+    pub reported: Money,
+    pub calculated: Money,
+    /// Balances match when they are equal to a cent
+    pub matches: bool,
+}
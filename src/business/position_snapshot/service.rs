@@ -0,0 +1,189 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDateTime;
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+use crate::{business::{currency_conversion::service::list_fiscal_transactions_with_conversions, fiscal_transaction::model::FiscalTransaction, model::{BrokerType, Money}, performance::service::{MarketData, Valuation}, portfolio::service::fold_holdings, security_transfer::model::SecurityTransfer, trade_operation::model::TradeOperation}, web::errors::DescriptiveError, ApplicationState};
+
+use super::{model::SnapshotPosition, resource::{BrokerReconciliation, ReconciledCash, ReconciledPosition}};
+
+
+/// Reconciles the latest position snapshot of every broker of a portfolio with operations
+/// imported from the broker up to the moment of the snapshot
+pub fn calculate_reconciliations(state: &ApplicationState, portfolio_id: Uuid) -> Result<Vec<BrokerReconciliation>, DescriptiveError> {
+    let snapshot_positions = state.repository.list_snapshot_positions(portfolio_id)?;
+    let trade_operations = state.repository.list_trade_operations(portfolio_id)?;
+    let fiscal_transactions = list_fiscal_transactions_with_conversions(state, portfolio_id)?;
+    let security_transfers = state.repository.list_security_transfers(portfolio_id)?;
+
+    // the latest snapshot of every broker, identified by its report upload
+    let mut latest: BTreeMap<BrokerType, (NaiveDateTime, Uuid)> = BTreeMap::new();
+    for position in snapshot_positions.iter() {
+        let snapshot = latest.entry(position.broker).or_insert((position.i.taken_at, position.report_upload_id));
+        if position.i.taken_at > snapshot.0 {
+            *snapshot = (position.i.taken_at, position.report_upload_id);
+        }
+    }
+
+    Ok(latest.into_iter()
+        .map(|(broker, (taken_at, report_upload_id))| {
+            let positions: Vec<&SnapshotPosition> = snapshot_positions.iter()
+                .filter(|p| p.report_upload_id == report_upload_id)
+                .map(|p| &p.i)
+                .collect();
+            let (positions, cash) = fold_reconciliation(
+                &positions,
+                taken_at,
+                trade_operations.iter().map(|o| &o.i).filter(|o| o.broker == Some(broker)),
+                fiscal_transactions.iter().map(|t| &t.i).filter(|t| t.broker == Some(broker)),
+                security_transfers.iter().map(|t| &t.i).filter(|t| t.broker == Some(broker)),
+            );
+            BrokerReconciliation {
+                brokerage: broker,
+                report_upload_id,
+                taken_at,
+                reconciled: positions.iter().all(|p| p.matches) && cash.iter().all(|c| c.matches),
+                positions,
+                cash,
+            }
+        })
+        .collect())
+}
+
+/// Compares positions and cash balances of a snapshot with the ones derived from operations
+/// made up to the moment `taken_at`
+pub fn fold_reconciliation<'a>(
+    snapshot: &[&SnapshotPosition],
+    taken_at: NaiveDateTime,
+    trade_operations: impl Iterator<Item = &'a TradeOperation>,
+    fiscal_transactions: impl Iterator<Item = &'a FiscalTransaction>,
+    security_transfers: impl Iterator<Item = &'a SecurityTransfer>,
+) -> (Vec<ReconciledPosition>, Vec<ReconciledCash>) {
+    let trade_operations: Vec<&TradeOperation> = trade_operations.filter(|o| o.date_time <= taken_at).collect();
+
+    // (reported, calculated) of every security and currency
+    let mut quantities: BTreeMap<(String, String), (Decimal, Decimal)> = BTreeMap::new();
+    let mut balances: BTreeMap<String, (Decimal, Decimal)> = BTreeMap::new();
+    for position in snapshot {
+        match &position.instrument_symbol {
+            Some(ticker) => quantities.entry((ticker.clone(), position.currency.clone())).or_default().0 += position.quantity,
+            None => balances.entry(position.currency.clone()).or_default().0 += position.quantity,
+        }
+    }
+    let security_transfers: Vec<&SecurityTransfer> = security_transfers.filter(|t| t.date_time <= taken_at).collect();
+    for holding in fold_holdings(trade_operations.iter().copied(), security_transfers.iter().copied()) {
+        quantities.entry((holding.ticker, holding.currency)).or_default().1 += Decimal::from(holding.quantity);
+    }
+    let market_data = MarketData::default();
+    let valuation = Valuation::replay(
+        &market_data,
+        trade_operations.iter().copied(),
+        fiscal_transactions.filter(|t| t.date_time <= taken_at),
+        security_transfers.iter().copied(),
+        NaiveDateTime::MAX,
+    );
+    for balance in valuation.cash_balances() {
+        balances.entry(balance.currency).or_default().1 += balance.amount;
+    }
+
+    let positions = quantities.into_iter()
+        .map(|((ticker, currency), (reported, calculated))| ReconciledPosition {
+            ticker,
+            currency,
+            reported_quantity: reported.normalize(),
+            calculated_quantity: calculated.normalize(),
+            matches: reported == calculated,
+        })
+        .collect();
+    let cash = balances.into_iter()
+        .map(|(currency, (reported, calculated))| {
+            let money = |amount: Decimal| Money::new(amount.round_dp(2).normalize(), currency.clone());
+            ReconciledCash {
+                matches: reported.round_dp(2) == calculated.round_dp(2),
+                reported: money(reported),
+                calculated: money(calculated),
+                currency,
+            }
+        })
+        .collect();
+    (positions, cash)
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::business::{fiscal_transaction::model::FiscalTransactionType, model::OperationSource, trade_operation::model::TradeOperationSide};
+
+    use super::*;
+
+    fn date_time(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn position(ticker: Option<&str>, quantity: i64) -> SnapshotPosition {
+        SnapshotPosition {
+            taken_at: date_time("2023-01-31 23:59:59"),
+            instrument_symbol: ticker.map(|t| t.to_string()),
+            isin: None,
+            quantity: Decimal::from(quantity),
+            currency: "USD".to_string(),
+        }
+    }
+
+    #[test]
+    fn flags_positions_and_cash_missing_from_imports() {
+        let buy = |at: &str, ticker: &str, quantity: i32| TradeOperation {
+            operation_source: OperationSource::Manual,
+            broker: None,
+            external_id: None,
+            date_time: date_time(at),
+            side: TradeOperationSide::Buy,
+            instrument_symbol: ticker.to_string(),
+            isin: None,
+            price: Money::new(Decimal::from(10), "USD".to_string()),
+            quantity,
+            commission: None,
+            order_id: None,
+            summ: Money::new(Decimal::from(10 * quantity), "USD".to_string()),
+            metadata: serde_json::Value::Null,
+            reported_pnl: None,
+        };
+        let operations = [
+            buy("2023-01-10 10:00:00", "VOO", 2),
+            buy("2023-01-11 10:00:00", "SCHD", 1),
+            // made after the snapshot
+            buy("2023-02-01 10:00:00", "SCHD", 5),
+        ];
+        let funding = FiscalTransaction {
+            operation_source: OperationSource::Manual,
+            broker: None,
+            external_id: None,
+            date_time: date_time("2023-01-02 10:00:00"),
+            symbol_id: None,
+            amount: Money::new(Decimal::from(100), "USD".to_string()),
+            operation_type: FiscalTransactionType::FundingWithdrawal,
+            commission: None,
+            metadata: serde_json::Value::Null,
+            ex_date: None,
+            amount_per_share: None,
+        };
+        let snapshot = [position(None, 60), position(Some("VOO"), 2), position(Some("SCHD"), 2)];
+
+        let (positions, cash) = fold_reconciliation(
+            &snapshot.iter().collect::<Vec<_>>(),
+            date_time("2023-01-31 23:59:59"),
+            operations.iter(),
+            [funding].iter(),
+            [].iter(),
+        );
+
+        assert_eq!(positions.len(), 2);
+        let (schd, voo) = (&positions[0], &positions[1]);
+        assert_eq!((schd.reported_quantity, schd.calculated_quantity, schd.matches), (Decimal::from(2), Decimal::from(1), false));
+        assert!(voo.matches);
+        assert_eq!(cash.len(), 1);
+        assert_eq!(cash[0].calculated.amount, Decimal::from(70));
+        assert!(!cash[0].matches);
+    }
+}
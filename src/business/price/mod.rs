@@ -0,0 +1,5 @@
+pub mod model;
+pub mod provider;
+pub mod repository;
+pub mod resource;
+pub mod service;
@@ -0,0 +1,52 @@
+use chrono::NaiveDate;
+use diesel::{Insertable, Selectable, Queryable};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{business::model::Money, database::schema};
+
+/// Price at which an instrument closed a trading day
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DailyPrice {
+    pub ticker: String,
+    pub date: NaiveDate,
+    pub close: Money,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum MarketDataError {
+    #[error("Market data provider is not configured")]
+    NotConfigured,
+    #[error("Ticker \"{0}\" can't be used to look up prices")]
+    InvalidTicker(String),
+    #[error("No prices are known for ticker \"{0}\"")]
+    UnknownTicker(String),
+    #[error(transparent)]
+    IO { #[from] source: std::io::Error },
+    #[error(transparent)]
+    Csv { #[from] source: csv::Error },
+}
+
+// --- orm model
+
+#[derive(Deserialize, Insertable)]
+#[diesel(table_name = schema::price_history )]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct InsertPrice {
+    pub ticker: String,
+    pub date: NaiveDate,
+    pub close: Money,
+    /// Name of the market data provider the price was imported from
+    pub source: String,
+}
+
+#[derive(Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = schema::price_history )]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct SelectPrice {
+    pub id: Uuid,
+    pub ticker: String,
+    pub date: NaiveDate,
+    pub close: Money,
+    pub source: String,
+}
@@ -0,0 +1,110 @@
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::{business::model::Money, settings::MarketDataSettings};
+
+use super::model::{DailyPrice, MarketDataError};
+
+/// Source of daily close prices of instruments
+pub trait MarketDataProvider: Send + Sync {
+    /// Name that is stored along with imported prices
+    fn name(&self) -> &'static str;
+
+    /// Close prices of `ticker` for trading days between `from` and `to` inclusive, in ascending order
+    fn daily_close_prices(&self, ticker: &str, from: NaiveDate, to: NaiveDate) -> Result<Vec<DailyPrice>, MarketDataError>;
+}
+
+/// Returns the provider configured in settings, if any
+pub fn configured_provider(settings: &MarketDataSettings) -> Option<Box<dyn MarketDataProvider>> {
+    settings.csv_directory
+        .as_ref()
+        .map(|directory| Box::new(CsvMarketDataProvider::new(directory)) as Box<dyn MarketDataProvider>)
+}
+
+
+/// Reads prices from `<directory>/<ticker>.csv` files with `date,close,currency` columns,
+/// dates formatted as `YYYY-MM-DD`.
+pub struct CsvMarketDataProvider {
+    directory: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct CsvPriceRecord {
+    date: NaiveDate,
+    close: Decimal,
+    currency: String,
+}
+
+impl CsvMarketDataProvider {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self { directory: directory.into() }
+    }
+
+    fn file_of(&self, ticker: &str) -> Result<PathBuf, MarketDataError> {
+        let is_plain_name = !ticker.is_empty()
+            && !ticker.starts_with('.')
+            && !ticker.contains(['/', '\\']);
+        if !is_plain_name {
+            return Err(MarketDataError::InvalidTicker(ticker.to_string()));
+        }
+        Ok(self.directory.join(format!("{ticker}.csv")))
+    }
+}
+
+impl MarketDataProvider for CsvMarketDataProvider {
+    fn name(&self) -> &'static str {
+        "csv"
+    }
+
+    fn daily_close_prices(&self, ticker: &str, from: NaiveDate, to: NaiveDate) -> Result<Vec<DailyPrice>, MarketDataError> {
+        let path = self.file_of(ticker)?;
+        if !path.is_file() {
+            return Err(MarketDataError::UnknownTicker(ticker.to_string()));
+        }
+
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_path(path)?;
+        let mut prices = Vec::new();
+        for record in reader.deserialize::<CsvPriceRecord>() {
+            let record = record?;
+            if record.date >= from && record.date <= to {
+                prices.push(DailyPrice {
+                    ticker: ticker.to_string(),
+                    date: record.date,
+                    close: Money::new(record.close, record.currency),
+                });
+            }
+        }
+        prices.sort_by_key(|p| p.date);
+        Ok(prices)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_prices_within_period_from_csv_file() {
+        let directory = std::env::temp_dir().join(format!("prices-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("VOO.ARCA.csv"), "date,close,currency\n2023-01-04, 352.5, USD\n2023-01-03,350.1,USD\n2023-01-02,349,USD\n").unwrap();
+        let provider = CsvMarketDataProvider::new(&directory);
+
+        let date = |day| NaiveDate::from_ymd_opt(2023, 1, day).unwrap();
+        let prices = provider.daily_close_prices("VOO.ARCA", date(3), date(10)).unwrap();
+        assert_eq!(prices, vec![
+            DailyPrice { ticker: "VOO.ARCA".to_string(), date: date(3), close: Money::new(Decimal::new(3501, 1), "USD".to_string()) },
+            DailyPrice { ticker: "VOO.ARCA".to_string(), date: date(4), close: Money::new(Decimal::new(3525, 1), "USD".to_string()) },
+        ]);
+
+        assert!(matches!(provider.daily_close_prices("SCHD", date(1), date(10)), Err(MarketDataError::UnknownTicker(_))));
+        assert!(matches!(provider.daily_close_prices("../VOO.ARCA", date(1), date(10)), Err(MarketDataError::InvalidTicker(_))));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
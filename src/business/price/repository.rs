@@ -0,0 +1,34 @@
+use chrono::NaiveDate;
+use diesel::{insert_into, prelude::*, upsert::excluded};
+
+use crate::database::{schema::price_history::dsl, CommonRepository, RepositoryError};
+
+use super::model::{InsertPrice, SelectPrice};
+
+impl CommonRepository {
+    pub fn list_prices(&self, tickers: &[String], from: Option<NaiveDate>, to: Option<NaiveDate>) -> Result<Vec<SelectPrice>, RepositoryError> {
+        let mut query = dsl::price_history
+            .filter(dsl::ticker.eq_any(tickers))
+            .into_boxed();
+        if let Some(from) = from {
+            query = query.filter(dsl::date.ge(from));
+        }
+        if let Some(to) = to {
+            query = query.filter(dsl::date.le(to));
+        }
+        Ok(query
+            .order((dsl::ticker, dsl::date))
+            .select(SelectPrice::as_select())
+            .load(&mut self.pool.get()?)?)
+    }
+
+    /// Inserts prices, replacing previously stored prices of the same ticker and day
+    pub fn upsert_prices(&self, prices: Vec<InsertPrice>) -> Result<usize, RepositoryError> {
+        Ok(insert_into(dsl::price_history)
+            .values(prices)
+            .on_conflict((dsl::ticker, dsl::date))
+            .do_update()
+            .set((dsl::close.eq(excluded(dsl::close)), dsl::source.eq(excluded(dsl::source))))
+            .execute(&mut self.pool.get()?)?)
+    }
+}
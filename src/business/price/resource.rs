@@ -0,0 +1,79 @@
+use async_graphql::{Context, Object, SimpleObject};
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::business::model::Money;
+use crate::web::graphql::{get_admin_claims, get_claims, get_state};
+
+use super::model::SelectPrice;
+use super::service::import_prices;
+
+
+#[derive(Default)]
+pub struct PriceQuery;
+#[Object(rename_fields="camelCase", rename_args="camelCase")]
+impl PriceQuery {
+    /// Stored daily close prices of an instrument
+    async fn price_history<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        ticker: String,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> async_graphql::Result<Vec<Price>> {
+        get_claims(ctx)?;
+        let state = get_state(ctx)?;
+        Ok(state.repository.list_prices(&[ticker], from, to)?
+            .into_iter()
+            .map(Price::from)
+            .collect())
+    }
+}
+
+
+#[derive(Default)]
+pub struct PriceMutation;
+#[Object(rename_fields="camelCase", rename_args="camelCase")]
+impl PriceMutation {
+    /// Import daily close prices of instruments from the configured market data provider.
+    /// Previously stored prices of the same days are replaced. Prices are shared by every user,
+    /// so only admins may import them.
+    async fn import_prices(
+        &self,
+        ctx: &Context<'_>,
+        tickers: Vec<String>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> async_graphql::Result<Vec<PriceImportResult>> {
+        get_admin_claims(ctx)?;
+        let state = get_state(ctx)?;
+        Ok(import_prices(state, &tickers, from, to)?
+            .into_iter()
+            .map(|(ticker, imported)| PriceImportResult { ticker, imported })
+            .collect())
+    }
+}
+
+// --- model
+
+#[derive(Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct Price {
+    pub ticker: String,
+    pub date: NaiveDate,
+    pub close: Money,
+}
+
+impl From<SelectPrice> for Price {
+    fn from(value: SelectPrice) -> Self {
+        Price { ticker: value.ticker, date: value.date, close: value.close }
+    }
+}
+
+#[derive(Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct PriceImportResult {
+    pub ticker: String,
+    /// Amount of imported daily prices.
+    pub imported: usize,
+}
@@ -0,0 +1,68 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+use crate::{web::errors::DescriptiveError, ApplicationState};
+
+use super::{model::{DailyPrice, InsertPrice, MarketDataError}, provider::configured_provider};
+
+
+/// Loads daily close prices of `tickers` from the configured market data provider into
+/// the price history. Returns amount of imported prices per ticker.
+pub fn import_prices(
+    state: &ApplicationState,
+    tickers: &[String],
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> Result<Vec<(String, usize)>, DescriptiveError> {
+    let provider = configured_provider(&state.settings.market_data)
+        .ok_or(MarketDataError::NotConfigured)?;
+
+    let mut imported = Vec::with_capacity(tickers.len());
+    for ticker in tickers {
+        let prices = provider.daily_close_prices(ticker, from.unwrap_or(NaiveDate::MIN), to.unwrap_or(NaiveDate::MAX))?;
+        let amount = state.repository.upsert_prices(prices.into_iter()
+            .map(|p| InsertPrice { ticker: p.ticker, date: p.date, close: p.close, source: provider.name().to_string() })
+            .collect())?;
+        imported.push((ticker.clone(), amount));
+    }
+    Ok(imported)
+}
+
+/// Stored close prices of instruments, looked up by ticker, currency and day
+#[derive(Default)]
+pub struct PriceBook {
+    closes: HashMap<(String, String), BTreeMap<NaiveDate, Decimal>>,
+}
+
+impl PriceBook {
+    /// Loads every stored price of `tickers` up to `to`
+    pub fn load(state: &ApplicationState, tickers: &[String], to: NaiveDate) -> Result<Self, DescriptiveError> {
+        let prices = state.repository.list_prices(tickers, None, Some(to))?;
+        Ok(prices.into_iter()
+            .map(|p| DailyPrice { ticker: p.ticker, date: p.date, close: p.close })
+            .collect())
+    }
+
+    /// The last close price of `ticker` in `currency` at or before `date`
+    pub fn close_at(&self, ticker: &str, currency: &str, date: NaiveDate) -> Option<(NaiveDate, Decimal)> {
+        self.closes.get(&(ticker.to_string(), currency.to_string()))?
+            .range(..=date)
+            .next_back()
+            .map(|(date, close)| (*date, *close))
+    }
+}
+
+impl FromIterator<DailyPrice> for PriceBook {
+    fn from_iter<T: IntoIterator<Item = DailyPrice>>(prices: T) -> Self {
+        let mut book = PriceBook::default();
+        for price in prices {
+            book.closes
+                .entry((price.ticker, price.close.currency))
+                .or_default()
+                .insert(price.date, price.close.amount);
+        }
+        book
+    }
+}
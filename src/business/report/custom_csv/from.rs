@@ -0,0 +1,249 @@
+use rust_decimal::prelude::ToPrimitive;
+use serde_json::json;
+
+use super::super::model::{AbstractReport, ExternalIds, RowDiagnostic};
+use super::model::{Report, Row};
+
+use crate::business::{csv_mapping::model::{CsvColumns, CsvOperation}, fiscal_transaction::model::{FiscalTransaction, FiscalTransactionType}, model::{BrokerType, Money, OperationSource}, trade_operation::model::{TradeOperation, TradeOperationSide}};
+
+impl From<Report> for AbstractReport {
+    fn from(value: Report) -> Self {
+        let mut report = AbstractReport {
+            broker: BrokerType::Other,
+            trade_operations: vec![],
+            fiscal_transactions: vec![],
+            security_transfers: vec![],
+            currency_conversions: vec![],
+            position_snapshot: vec![],
+            diagnostics: vec![],
+        };
+        // identifiers are only unique within files of the same profile
+        let mut external_ids = ExternalIds::default();
+        for row in value.rows {
+            let key = row.external_id.clone().unwrap_or_else(|| format!(
+                "{}/{}/{}/{}/{}",
+                row.date_time.format("%Y%m%d%H%M%S"),
+                row.operation_value,
+                row.ticker.as_deref().unwrap_or_default(),
+                row.quantity.unwrap_or_default(),
+                row.amount.unwrap_or_default(),
+            ));
+            let external_id = external_ids.next(format!("{}/{}", value.profile_id, key));
+            let imported = match row.operation {
+                Some(CsvOperation::Buy) | Some(CsvOperation::Sell) => trade_operation(row, external_id, &value.columns)
+                    .map(|operation| report.trade_operations.push(operation)),
+                _ => fiscal_transaction(row, external_id, &value.columns)
+                    .map(|transaction| report.fiscal_transactions.push(transaction)),
+            };
+            if let Err(diagnostic) = imported {
+                report.diagnostics.push(diagnostic);
+            }
+        }
+        report
+    }
+}
+
+/// A row which can't be imported, reported in the mapped `column`
+fn diagnostic(value: &Row, section: &str, column: &Option<String>, raw: Option<String>, message: String) -> RowDiagnostic {
+    RowDiagnostic {
+        line: value.number,
+        section: section.to_string(),
+        column: column.clone(),
+        value: raw,
+        message,
+    }
+}
+
+/// Rows which don't make a whole trade, like ones with a fraction of a security, are refused with a diagnostic
+fn trade_operation(value: Row, external_id: String, columns: &CsvColumns) -> Result<TradeOperation, RowDiagnostic> {
+    let Some(ticker) = value.ticker.clone() else {
+        return Err(diagnostic(&value, "Trades", &columns.ticker, None, "trade has no ticker".to_string()));
+    };
+    let Some(shares) = value.quantity else {
+        return Err(diagnostic(&value, "Trades", &columns.quantity, None, format!("trade of {ticker} has no quantity")));
+    };
+    if !shares.fract().is_zero() {
+        return Err(diagnostic(&value, "Trades", &columns.quantity, Some(shares.to_string()),
+            format!("{ticker} has a fraction of a security, which can't be stored yet")));
+    }
+    let Some(quantity) = shares.abs().to_i32().filter(|quantity| *quantity > 0) else {
+        return Err(diagnostic(&value, "Trades", &columns.quantity, Some(shares.to_string()),
+            format!("quantity of {ticker} is zero or too large")));
+    };
+    // either of the price and the traded volume is enough to know the other
+    let Some(summ) = value.amount.map(|amount| amount.abs())
+        .or(value.price.map(|price| price.abs() * shares.abs())) else {
+        let column = if columns.price.is_some() { &columns.price } else { &columns.amount };
+        return Err(diagnostic(&value, "Trades", column, None, format!("trade of {ticker} has neither a price nor an amount")));
+    };
+    let price = value.price.map(|price| price.abs())
+        .unwrap_or_else(|| summ / shares.abs());
+    Ok(TradeOperation {
+        operation_source: OperationSource::CustomCsvReport,
+        broker: Some(BrokerType::Other),
+        external_id: Some(external_id),
+        date_time: value.date_time,
+        side: if value.operation == Some(CsvOperation::Sell) { TradeOperationSide::Sell } else { TradeOperationSide::Buy },
+        instrument_symbol: ticker,
+        isin: value.isin.clone(),
+        price: Money::new(price, value.currency.clone()),
+        quantity,
+        commission: value.commission
+            .filter(|commission| !commission.is_zero())
+            .map(|commission| Money::new(commission.abs(), value.currency.clone())),
+        order_id: None,
+        summ: Money::new(summ, value.currency.clone()),
+        reported_pnl: None,
+        metadata: metadata(&value),
+    })
+}
+
+fn fiscal_transaction(value: Row, external_id: String, columns: &CsvColumns) -> Result<FiscalTransaction, RowDiagnostic> {
+    let Some(amount) = value.amount else {
+        return Err(diagnostic(&value, "Transactions", &columns.amount, None, format!("'{}' has no amount", value.operation_value)));
+    };
+    let (operation_type, amount) = match value.operation {
+        Some(CsvOperation::Dividend) => (FiscalTransactionType::Dividend, amount),
+        Some(CsvOperation::Interest) => (FiscalTransactionType::Interest, amount),
+        Some(CsvOperation::FundingWithdrawal) => (FiscalTransactionType::FundingWithdrawal, amount),
+        // paid money, whatever its sign in the file
+        Some(CsvOperation::Tax) => (FiscalTransactionType::Tax, -amount.abs()),
+        Some(CsvOperation::Commission) => (FiscalTransactionType::Commission, -amount.abs()),
+        _ => (FiscalTransactionType::Unrecognized(value.operation_value.clone()), amount),
+    };
+    Ok(FiscalTransaction {
+        operation_source: OperationSource::CustomCsvReport,
+        broker: Some(BrokerType::Other),
+        external_id: Some(external_id),
+        date_time: value.date_time,
+        symbol_id: value.ticker.clone(),
+        amount: Money::new(amount, value.currency.clone()),
+        operation_type,
+        commission: value.commission
+            .filter(|commission| !commission.is_zero())
+            .map(|commission| Money::new(commission.abs(), value.currency.clone())),
+        metadata: metadata(&value),
+        ex_date: None,
+        amount_per_share: None,
+    })
+}
+
+fn metadata(value: &Row) -> serde_json::Value {
+    json!({
+        "row": value.number,
+        "type": value.operation_value,
+        "comment": value.comment,
+    })
+}
+
+
+#[cfg(test)]
+mod test {
+    use rust_decimal::Decimal;
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn row(operation: Option<CsvOperation>, quantity: Option<i64>, price: Option<i64>, amount: Option<i64>) -> Row {
+        Row {
+            number: 2,
+            date_time: chrono::NaiveDateTime::parse_from_str("2024-01-08 16:45:00", "%Y-%m-%d %H:%M:%S").unwrap(),
+            operation,
+            operation_value: "value".to_string(),
+            ticker: Some("VWCE".to_string()),
+            isin: None,
+            quantity: quantity.map(Decimal::from),
+            price: price.map(Decimal::from),
+            amount: amount.map(Decimal::from),
+            currency: "EUR".to_string(),
+            commission: None,
+            external_id: None,
+            comment: None,
+        }
+    }
+
+    fn columns() -> CsvColumns {
+        CsvColumns {
+            date: "Date".to_string(),
+            time: None,
+            operation_type: Some("Type".to_string()),
+            side: None,
+            ticker: Some("Symbol".to_string()),
+            isin: None,
+            quantity: Some("Qty".to_string()),
+            price: Some("Price".to_string()),
+            amount: Some("Amount".to_string()),
+            currency: None,
+            commission: None,
+            external_id: None,
+            comment: None,
+        }
+    }
+
+    #[test]
+    fn maps_rows_of_any_operation() {
+        let profile_id = Uuid::new_v4();
+        let report: AbstractReport = Report {
+            profile_id,
+            columns: columns(),
+            rows: vec![
+                row(Some(CsvOperation::Buy), Some(10), None, Some(-1000)),
+                row(Some(CsvOperation::Sell), Some(-4), Some(110), None),
+                row(Some(CsvOperation::Tax), None, None, Some(2)),
+                row(Some(CsvOperation::Tax), None, None, Some(2)),
+                row(None, None, None, Some(5)),
+            ],
+        }.into();
+
+        let trades: Vec<(TradeOperationSide, i32, Decimal, Decimal)> = report.trade_operations.iter()
+            .map(|t| (t.side, t.quantity, t.price.amount, t.summ.amount))
+            .collect();
+        assert_eq!(trades, vec![
+            (TradeOperationSide::Buy, 10, Decimal::from(100), Decimal::from(1000)),
+            (TradeOperationSide::Sell, 4, Decimal::from(110), Decimal::from(440)),
+        ]);
+
+        let types: Vec<String> = report.fiscal_transactions.iter().map(|t| t.operation_type.to_string()).collect();
+        assert_eq!(types, vec!["Tax", "Tax", "value"]);
+        assert_eq!(report.fiscal_transactions[0].amount.amount, Decimal::from(-2));
+        // identical rows are told apart, and scoped by the profile
+        let ids: Vec<&str> = report.fiscal_transactions.iter().filter_map(|t| t.external_id.as_deref()).collect();
+        assert_ne!(ids[0], ids[1]);
+        assert!(ids.iter().all(|id| id.starts_with(&profile_id.to_string())));
+    }
+
+    #[test]
+    fn reports_rows_it_cant_import() {
+        let mut fractional = row(Some(CsvOperation::Buy), None, Some(100), None);
+        fractional.quantity = Some(Decimal::new(15, 1));
+        let mut unnamed = row(Some(CsvOperation::Sell), Some(1), Some(100), None);
+        unnamed.ticker = None;
+        let report: AbstractReport = Report {
+            profile_id: Uuid::new_v4(),
+            columns: columns(),
+            rows: vec![
+                fractional,
+                unnamed,
+                row(Some(CsvOperation::Buy), None, Some(100), None),
+                row(Some(CsvOperation::Buy), Some(0), Some(100), None),
+                row(Some(CsvOperation::Buy), Some(1), None, None),
+                row(Some(CsvOperation::Dividend), None, None, None),
+            ],
+        }.into();
+
+        assert!(report.trade_operations.is_empty());
+        assert!(report.fiscal_transactions.is_empty());
+        let diagnostics: Vec<(&str, Option<&str>, Option<&str>)> = report.diagnostics.iter()
+            .map(|d| (d.section.as_str(), d.column.as_deref(), d.value.as_deref()))
+            .collect();
+        assert_eq!(diagnostics, vec![
+            ("Trades", Some("Qty"), Some("1.5")),
+            ("Trades", Some("Symbol"), None),
+            ("Trades", Some("Qty"), None),
+            ("Trades", Some("Qty"), Some("0")),
+            ("Trades", Some("Price"), None),
+            ("Transactions", Some("Amount"), None),
+        ]);
+        assert!(report.diagnostics.iter().all(|d| d.line == 2));
+    }
+}
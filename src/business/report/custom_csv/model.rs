@@ -0,0 +1,43 @@
+use chrono::NaiveDateTime;
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+use crate::business::csv_mapping::model::{CsvColumns, CsvOperation};
+
+pub struct Report {
+    /// Profile the file was read with, which scopes the identifiers of its rows
+    pub profile_id: Uuid,
+    /// Mapped columns, which rows left out of the import are reported with
+    pub columns: CsvColumns,
+    pub rows: Vec<Row>,
+}
+
+pub struct Row {
+    pub number: usize,
+    pub date_time: NaiveDateTime,
+    /// Operation of the row, which is never a `Trade` as the side is already known.
+    /// None when the value of the operation type column is not mapped.
+    pub operation: Option<CsvOperation>,
+    pub operation_value: String,
+    pub ticker: Option<String>,
+    pub isin: Option<String>,
+    pub quantity: Option<Decimal>,
+    pub price: Option<Decimal>,
+    pub amount: Option<Decimal>,
+    pub currency: String,
+    pub commission: Option<Decimal>,
+    pub external_id: Option<String>,
+    pub comment: Option<String>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CustomCsvReportParsingError {
+    #[error(transparent)]
+    IO { #[from] source: std::io::Error },
+    #[error(transparent)]
+    Csv { #[from] source: csv::Error },
+    #[error("Column '{0}' of the mapping profile is not in the header of this file")]
+    MissingColumn(String),
+    #[error("Malformed row {row}: {reason}")]
+    MalformedRecord { row: usize, reason: String },
+}
@@ -0,0 +1,289 @@
+use std::str::FromStr;
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use rust_decimal::Decimal;
+use futures_util::future::BoxFuture;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::business::csv_mapping::model::{CsvMapping, CsvMappingProfile, CsvOperation};
+use crate::business::model::BrokerType;
+use crate::business::report::{model::{AbstractReport, ReportProcessingError}, parser::ReportParser};
+
+use super::model::{CustomCsvReportParsingError, Report, Row};
+
+pub struct CustomCsvReportParser;
+
+impl ReportParser for CustomCsvReportParser {
+    fn broker(&self) -> BrokerType {
+        BrokerType::Other
+    }
+
+    fn name(&self) -> &'static str {
+        "Other broker"
+    }
+
+    fn description(&self) -> &'static str {
+        "CSV file of any broker, read with a mapping profile of its columns saved in the portfolio"
+    }
+
+    fn is_suitable(&self, _content: &[u8]) -> bool {
+        false
+    }
+
+    fn requires_csv_mapping_profile(&self) -> bool {
+        true
+    }
+
+    fn parse<'a>(&'a self, content: &'a [u8], profile: Option<&'a CsvMappingProfile>) -> BoxFuture<'a, Result<AbstractReport, ReportProcessingError>> {
+        Box::pin(async move {
+            let profile = profile.ok_or(ReportProcessingError::MissingCsvMappingProfile)?;
+            parse_report(content, profile).await
+                .map(|report| report.into())
+                .map_err(|err| self.parsing_error(err))
+        })
+    }
+}
+
+pub async fn parse_report<R: AsyncRead + Unpin>(
+    mut reader: R,
+    profile: &CsvMappingProfile,
+) -> Result<Report, CustomCsvReportParsingError> {
+    let mut content = String::new();
+    reader.read_to_string(&mut content).await?;
+    Ok(Report {
+        profile_id: profile.id,
+        columns: profile.mapping.columns.clone(),
+        rows: parse_rows(content.trim_start_matches('\u{feff}'), &profile.mapping)?,
+    })
+}
+
+fn parse_rows(content: &str, mapping: &CsvMapping) -> Result<Vec<Row>, CustomCsvReportParsingError> {
+    let skipped_lines = mapping.skip_lines.max(0) as usize;
+    let content = content.splitn(skipped_lines + 1, '\n').last().unwrap_or_default();
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(mapping.delimiter.as_bytes().first().copied().unwrap_or(b','))
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    let columns = Columns::find(reader.headers()?, mapping)?;
+
+    let mut rows = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let record = record?;
+        if record.iter().all(|v| v.trim().is_empty()) {
+            continue;
+        }
+        let line = RowReader { mapping, columns: &columns, record: &record, number: skipped_lines + index + 2 };
+        if let Some(row) = line.row()? {
+            rows.push(row);
+        }
+    }
+    Ok(rows)
+}
+
+/// Positions of the mapped columns in the header
+struct Columns {
+    date: usize,
+    time: Option<usize>,
+    operation_type: Option<usize>,
+    side: Option<usize>,
+    ticker: Option<usize>,
+    isin: Option<usize>,
+    quantity: Option<usize>,
+    price: Option<usize>,
+    amount: Option<usize>,
+    currency: Option<usize>,
+    commission: Option<usize>,
+    external_id: Option<usize>,
+    comment: Option<usize>,
+}
+
+impl Columns {
+    fn find(header: &csv::StringRecord, mapping: &CsvMapping) -> Result<Self, CustomCsvReportParsingError> {
+        let position = |name: &Option<String>| -> Result<Option<usize>, CustomCsvReportParsingError> {
+            let Some(name) = name else { return Ok(None) };
+            header.iter()
+                .position(|h| h.trim().eq_ignore_ascii_case(name.trim()))
+                .map(Some)
+                .ok_or_else(|| CustomCsvReportParsingError::MissingColumn(name.clone()))
+        };
+        let columns = &mapping.columns;
+        Ok(Columns {
+            date: position(&Some(columns.date.clone()))?.unwrap_or_default(),
+            time: position(&columns.time)?,
+            operation_type: position(&columns.operation_type)?,
+            side: position(&columns.side)?,
+            ticker: position(&columns.ticker)?,
+            isin: position(&columns.isin)?,
+            quantity: position(&columns.quantity)?,
+            price: position(&columns.price)?,
+            amount: position(&columns.amount)?,
+            currency: position(&columns.currency)?,
+            commission: position(&columns.commission)?,
+            external_id: position(&columns.external_id)?,
+            comment: position(&columns.comment)?,
+        })
+    }
+}
+
+struct RowReader<'a> {
+    mapping: &'a CsvMapping,
+    columns: &'a Columns,
+    record: &'a csv::StringRecord,
+    number: usize,
+}
+
+impl RowReader<'_> {
+    fn malformed(&self, reason: String) -> CustomCsvReportParsingError {
+        CustomCsvReportParsingError::MalformedRecord { row: self.number, reason }
+    }
+
+    fn text(&self, position: Option<usize>) -> Option<String> {
+        self.record.get(position?)
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .map(|v| v.to_string())
+    }
+
+    fn number(&self, position: Option<usize>) -> Result<Option<Decimal>, CustomCsvReportParsingError> {
+        let Some(value) = self.text(position) else { return Ok(None) };
+        let normalized = if self.mapping.decimal_comma {
+            value.replace(['.', ' ', '\u{a0}'], "").replace(',', ".")
+        } else {
+            value.replace([',', ' ', '\u{a0}'], "")
+        };
+        Decimal::from_str(&normalized)
+            .map(Some)
+            .map_err(|_| self.malformed(format!("'{value}' is not a number")))
+    }
+
+    fn date_time(&self) -> Result<NaiveDateTime, CustomCsvReportParsingError> {
+        let date = self.text(Some(self.columns.date))
+            .ok_or_else(|| self.malformed("date is missing".to_string()))?;
+        let value = match self.text(self.columns.time) {
+            Some(time) => format!("{date} {time}"),
+            None => date,
+        };
+        let format = self.mapping.date_format.as_str();
+        NaiveDateTime::parse_from_str(&value, format)
+            .or_else(|_| NaiveDate::parse_from_str(&value, format).map(|date| date.and_time(NaiveTime::MIN)))
+            .map_err(|_| self.malformed(format!("'{value}' is not a date in the format '{format}'")))
+    }
+
+    /// The operation of a row, where a trade takes its side from the side column
+    fn operation(&self) -> Result<(Option<CsvOperation>, String), CustomCsvReportParsingError> {
+        let type_value = self.text(self.columns.operation_type);
+        let operation = match type_value.as_ref() {
+            Some(value) => self.mapping.operation_of(value),
+            None if self.columns.operation_type.is_some() => return Err(self.malformed("operation type is missing".to_string())),
+            None => Some(CsvOperation::Trade),
+        };
+        if operation != Some(CsvOperation::Trade) {
+            return Ok((operation, type_value.unwrap_or_default()));
+        }
+
+        let side = self.text(self.columns.side)
+            .ok_or_else(|| self.malformed("side of the trade is missing".to_string()))?;
+        match self.mapping.operation_of(&side) {
+            Some(side @ (CsvOperation::Buy | CsvOperation::Sell)) => Ok((Some(side), type_value.unwrap_or(side_value(side)))),
+            _ => Err(self.malformed(format!("side '{side}' is mapped to neither BUY nor SELL"))),
+        }
+    }
+
+    fn row(&self) -> Result<Option<Row>, CustomCsvReportParsingError> {
+        let (operation, operation_value) = self.operation()?;
+        if operation == Some(CsvOperation::Ignore) {
+            return Ok(None);
+        }
+        let currency = self.text(self.columns.currency)
+            .or_else(|| self.mapping.default_currency.clone())
+            .ok_or_else(|| self.malformed("currency is missing".to_string()))?;
+        Ok(Some(Row {
+            number: self.number,
+            date_time: self.date_time()?,
+            operation,
+            operation_value,
+            ticker: self.text(self.columns.ticker),
+            isin: self.text(self.columns.isin),
+            quantity: self.number(self.columns.quantity)?,
+            price: self.number(self.columns.price)?,
+            amount: self.number(self.columns.amount)?,
+            currency,
+            commission: self.number(self.columns.commission)?,
+            external_id: self.text(self.columns.external_id),
+            comment: self.text(self.columns.comment),
+        }))
+    }
+}
+
+fn side_value(side: CsvOperation) -> String {
+    if side == CsvOperation::Sell { "sell" } else { "buy" }.to_string()
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::business::csv_mapping::model::{CsvColumns, CsvValueMapping};
+
+    use super::*;
+
+    fn mapping() -> CsvMapping {
+        CsvMapping {
+            delimiter: ";".to_string(),
+            date_format: "%d.%m.%Y %H:%M".to_string(),
+            decimal_comma: true,
+            skip_lines: 1,
+            default_currency: Some("EUR".to_string()),
+            columns: CsvColumns {
+                date: "Datum".to_string(),
+                time: Some("Zeit".to_string()),
+                operation_type: Some("Art".to_string()),
+                side: Some("Richtung".to_string()),
+                ticker: Some("Symbol".to_string()),
+                isin: None,
+                quantity: Some("Stück".to_string()),
+                price: Some("Kurs".to_string()),
+                amount: Some("Betrag".to_string()),
+                currency: None,
+                commission: Some("Gebühr".to_string()),
+                external_id: None,
+                comment: None,
+            },
+            value_mappings: vec![
+                CsvValueMapping { value: "Wertpapier".to_string(), operation: CsvOperation::Trade },
+                CsvValueMapping { value: "K".to_string(), operation: CsvOperation::Buy },
+                CsvValueMapping { value: "V".to_string(), operation: CsvOperation::Sell },
+                CsvValueMapping { value: "Ausschüttung".to_string(), operation: CsvOperation::Dividend },
+                CsvValueMapping { value: "Info".to_string(), operation: CsvOperation::Ignore },
+            ],
+        }
+    }
+
+    #[test]
+    fn parses_rows_with_mapping() {
+        let content = "Depotumsätze 2024\n\
+            Datum;Zeit;Art;Richtung;Symbol;Stück;Kurs;Betrag;Gebühr\n\
+            08.01.2024;16:45;Wertpapier;K;VWCE;10;108,30;1.083,00;3,90\n\
+            28.03.2024;08:11;Ausschüttung;;VWCE;;;6,16;\n\
+            29.03.2024;08:00;Info;;;;;;\n\
+            02.04.2024;06:00;Interest;;;;;0,85;\n\
+            03.04.2024;06:00;Zinsgutschrift;;;;;1,00;\n";
+        let rows = parse_rows(content, &mapping()).unwrap();
+
+        let operations: Vec<Option<CsvOperation>> = rows.iter().map(|r| r.operation).collect();
+        assert_eq!(operations, vec![Some(CsvOperation::Buy), Some(CsvOperation::Dividend), Some(CsvOperation::Interest), None]);
+        assert_eq!(rows[0].date_time.to_string(), "2024-01-08 16:45:00");
+        assert_eq!(rows[0].amount, Some(Decimal::new(108300, 2)));
+        assert_eq!(rows[0].currency, "EUR");
+        assert_eq!(rows[0].number, 3);
+        assert_eq!(rows[3].operation_value, "Zinsgutschrift");
+    }
+
+    #[test]
+    fn reports_missing_columns_and_malformed_rows() {
+        let content = "title\nDatum;Zeit;Art;Richtung;Symbol;Stück;Kurs;Betrag\n";
+        assert!(matches!(parse_rows(content, &mapping()), Err(CustomCsvReportParsingError::MissingColumn(column)) if column == "Gebühr"));
+
+        let content = "title\nDatum;Zeit;Art;Richtung;Symbol;Stück;Kurs;Betrag;Gebühr\n08.01.2024;16:45;Wertpapier;X;VWCE;10;108,30;1.083,00;3,90\n";
+        assert!(matches!(parse_rows(content, &mapping()), Err(CustomCsvReportParsingError::MalformedRecord { row: 3, .. })));
+    }
+}
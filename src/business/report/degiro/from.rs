@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+
+use rust_decimal::prelude::ToPrimitive;
+use serde_json::json;
+
+use super::super::model::{AbstractReport, ExternalIds, RowDiagnostic};
+use super::model::{AccountMovement, AccountMovementKind, Report};
+
+use crate::business::{currency_conversion::model::CurrencyConversion, fiscal_transaction::model::{FiscalTransaction, FiscalTransactionType}, model::{BrokerType, Money, OperationSource}, trade_operation::model::{TradeOperation, TradeOperationSide}};
+
+impl From<Report> for AbstractReport {
+    fn from(value: Report) -> Self {
+        // Account.csv repeats the money of trades and their fees, which are taken from Transactions.csv
+        let movements: Vec<AccountMovement> = value.account_movements.into_iter()
+            .filter(|v| match v.kind() {
+                AccountMovementKind::Trade => false,
+                AccountMovementKind::Fee => v.order_id.is_none(),
+                _ => true,
+            })
+            .collect();
+        let (currency_conversions, movements) = pair_currency_conversions(movements);
+
+        // Degiro has no ids of rows
+        let mut movement_ids = ExternalIds::default();
+        let mut trade_ids = ExternalIds::default();
+        let mut trade_operations = Vec::new();
+        let mut diagnostics = Vec::new();
+        for trade in value.trades {
+            let key = trade.order_id.clone().unwrap_or_else(|| format!("{}/{}", trade.date_time.format("%Y%m%d%H%M"), trade.isin));
+            match trade_operation(trade, trade_ids.next(key)) {
+                Ok(operation) => trade_operations.extend(operation),
+                Err(diagnostic) => diagnostics.push(diagnostic),
+            }
+        }
+        Self {
+            trade_operations,
+            fiscal_transactions: movements.into_iter()
+                .map(|v| {
+                    let key = format!("{}/{}/{}{}", v.date_time.format("%Y%m%d%H%M"), v.description, v.change, v.currency);
+                    fiscal_transaction(v, movement_ids.next(key))
+                })
+                .collect(),
+            security_transfers: vec![],
+            currency_conversions,
+            position_snapshot: vec![],
+            diagnostics,
+            broker: BrokerType::Degiro,
+        }
+    }
+}
+
+/// Degiro reports no tickers, so instruments are identified by their ISIN.
+/// Trades of fractions of a security can't be stored, and are left out with a diagnostic.
+fn trade_operation(value: super::model::Trade, external_id: String) -> Result<Option<TradeOperation>, RowDiagnostic> {
+    if !value.quantity.fract().is_zero() {
+        return Err(RowDiagnostic {
+            line: value.line,
+            section: "Transactions".to_string(),
+            column: Some("Quantity".to_string()),
+            value: Some(value.quantity.to_string()),
+            message: format!("{} has a fraction of a security, which can't be stored yet", value.isin),
+        });
+    }
+    let Some(quantity) = value.quantity.abs().to_i32().filter(|quantity| *quantity > 0) else { return Ok(None) };
+    Ok(Some(TradeOperation {
+        operation_source: OperationSource::DegiroReport,
+        broker: Some(BrokerType::Degiro),
+        external_id: Some(external_id),
+        date_time: value.date_time,
+        side: if value.quantity.is_sign_negative() { TradeOperationSide::Sell } else { TradeOperationSide::Buy },
+        instrument_symbol: value.isin.clone(),
+        isin: Some(value.isin),
+        price: Money::new(value.price, value.price_currency),
+        quantity,
+        commission: value.fees_currency
+            .map(|currency| Money::new(value.fees.abs(), currency))
+            .filter(|commission| !commission.amount.is_zero()),
+        order_id: value.order_id,
+        summ: Money::new(value.local_value.abs(), value.local_currency),
+        reported_pnl: None,
+        metadata: json!({
+            "product": value.product,
+            "reference_exchange": value.reference_exchange,
+            "venue": value.venue,
+            "value": value.value,
+            "exchange_rate": value.exchange_rate,
+            "total": value.total,
+        }),
+    }))
+}
+
+fn fiscal_transaction(value: AccountMovement, external_id: String) -> FiscalTransaction {
+    FiscalTransaction {
+        operation_source: OperationSource::DegiroReport,
+        broker: Some(BrokerType::Degiro),
+        external_id: Some(external_id),
+        date_time: value.date_time,
+        operation_type: match value.kind() {
+            AccountMovementKind::Dividend => FiscalTransactionType::Dividend,
+            AccountMovementKind::DividendTax => FiscalTransactionType::Tax,
+            AccountMovementKind::FundingWithdrawal => FiscalTransactionType::FundingWithdrawal,
+            AccountMovementKind::FxDebit | AccountMovementKind::FxCredit => FiscalTransactionType::CurrencyExchange,
+            AccountMovementKind::Interest => FiscalTransactionType::Interest,
+            AccountMovementKind::Fee => FiscalTransactionType::Commission,
+            AccountMovementKind::Trade | AccountMovementKind::Other => FiscalTransactionType::Unrecognized(value.description.clone()),
+        },
+        symbol_id: value.isin.clone(),
+        amount: Money::new(value.change, value.currency),
+        commission: None,
+        metadata: json!({
+            "description": value.description,
+            "product": value.product,
+            "value_date": value.value_date,
+            "fx_rate": value.fx_rate,
+            "order_id": value.order_id,
+        }),
+        ex_date: None,
+        amount_per_share: None,
+    }
+}
+
+/// Degiro books a conversion as an FX debit of the sold currency and an FX credit of the
+/// bought one, sharing the order they were made for, or the time when made on their own.
+/// Rows which don't make up such a pair are returned as they are.
+fn pair_currency_conversions(movements: Vec<AccountMovement>) -> (Vec<CurrencyConversion>, Vec<AccountMovement>) {
+    let is_conversion = |v: &AccountMovement| matches!(v.kind(), AccountMovementKind::FxDebit | AccountMovementKind::FxCredit);
+    let (conversion_rows, mut rest): (Vec<_>, Vec<_>) = movements.into_iter().partition(is_conversion);
+
+    let mut groups: BTreeMap<String, Vec<AccountMovement>> = BTreeMap::new();
+    for row in conversion_rows {
+        let key = row.order_id.clone().unwrap_or_else(|| row.date_time.to_string());
+        groups.entry(key).or_default().push(row);
+    }
+
+    let mut conversions = Vec::new();
+    for (key, mut rows) in groups {
+        rows.sort_by_key(|v| v.change.is_sign_positive());
+        match rows.as_slice() {
+            [sold, bought] if sold.change.is_sign_negative() && bought.change.is_sign_positive() && sold.currency != bought.currency => {
+                let sold_money = Money::new(-sold.change, sold.currency.clone());
+                let bought_money = Money::new(bought.change, bought.currency.clone());
+                conversions.push(CurrencyConversion {
+                    operation_source: OperationSource::DegiroReport,
+                    broker: Some(BrokerType::Degiro),
+                    external_id: Some(format!("fx/{key}")),
+                    date_time: sold.date_time.max(bought.date_time),
+                    rate: CurrencyConversion::implied_rate(&sold_money, &bought_money),
+                    sold: sold_money,
+                    bought: bought_money,
+                    metadata: json!({
+                        "fx_rate": sold.fx_rate.or(bought.fx_rate),
+                        "order_id": sold.order_id,
+                        "descriptions": [sold.description, bought.description],
+                    }),
+                });
+            },
+            _ => rest.append(&mut rows),
+        }
+    }
+    (conversions, rest)
+}
+
+
+#[cfg(test)]
+mod test {
+    use rust_decimal::Decimal;
+    use tokio::fs::File;
+
+    use super::*;
+    use super::super::parse::parse_report;
+
+    async fn report_of(name: &str) -> AbstractReport {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("testdata");
+        d.push(name);
+        parse_report(File::open(d).await.unwrap()).await.unwrap().into()
+    }
+
+    #[tokio::test]
+    async fn maps_transactions() {
+        let report = report_of("degiro_transactions.csv").await;
+        assert_eq!(report.trade_operations.len(), 3);
+        let sale = &report.trade_operations[0];
+        assert!(sale.side == TradeOperationSide::Sell && sale.quantity == 2);
+        assert_eq!(sale.instrument_symbol, "US9229083632");
+        assert_eq!(sale.summ, Money::new(Decimal::new(90024, 2), "USD".to_string()));
+        assert_eq!(sale.commission, Some(Money::new(Decimal::from(1), "EUR".to_string())));
+        assert!(report.diagnostics.is_empty());
+    }
+
+    #[tokio::test]
+    async fn leaves_out_fractional_trades_with_diagnostics() {
+        let content = "Date,Time,Product,ISIN,Reference exchange,Venue,Quantity,Price,,Local value,,Order ID\n\
+            15-02-2024,15:31,VANGUARD S&P500,US9229083632,NSY,ARCA,0.5,450.12,USD,-225.06,USD,b1c2d3e4\n\
+            16-02-2024,15:31,VANGUARD S&P500,US9229083632,NSY,ARCA,\"1,000\",450.12,USD,\"-450,120.00\",USD,b1c2d3e5\n";
+        let report: AbstractReport = parse_report(content.as_bytes()).await.unwrap().into();
+
+        assert_eq!(report.trade_operations.len(), 1);
+        assert_eq!(report.trade_operations[0].quantity, 1000);
+        let diagnostic = &report.diagnostics[0];
+        assert_eq!((diagnostic.line, diagnostic.value.as_deref()), (2, Some("0.5")));
+    }
+
+    #[tokio::test]
+    async fn maps_account_without_trades() {
+        let report = report_of("degiro_account_de.csv").await;
+        let types: Vec<String> = report.fiscal_transactions.iter().map(|t| t.operation_type.to_string()).collect();
+        assert_eq!(types, vec!["FundingWithdrawal", "Dividend", "Tax", "Interest", "FundingWithdrawal"]);
+
+        assert_eq!(report.currency_conversions.len(), 1);
+        let conversion = &report.currency_conversions[0];
+        assert_eq!(conversion.sold, Money::new(Decimal::new(185472, 2), "EUR".to_string()));
+        assert_eq!(conversion.bought, Money::new(Decimal::new(203110, 2), "USD".to_string()));
+    }
+}
@@ -0,0 +1,99 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use rust_decimal::Decimal;
+
+/// Degiro exports trades and the movements of money as separate files, so a report holds
+/// the records of one of them only
+pub struct Report {
+    pub trades: Vec<Trade>,
+    pub account_movements: Vec<AccountMovement>,
+}
+
+/// A row of `Transactions.csv`
+pub struct Trade {
+    /// Line of the file, counted from 1
+    pub line: usize,
+    pub date_time: NaiveDateTime,
+    pub product: String,
+    pub isin: String,
+    pub reference_exchange: Option<String>,
+    pub venue: Option<String>,
+    pub quantity: Decimal, // negative when sold
+    pub price: Decimal,
+    pub price_currency: String,
+    pub local_value: Decimal, // negative when bought
+    pub local_currency: String,
+    pub value: Option<Decimal>, // in the currency of the account
+    pub exchange_rate: Option<Decimal>,
+    pub fees: Decimal, // negative
+    pub fees_currency: Option<String>,
+    pub total: Option<Decimal>,
+    pub order_id: Option<String>,
+}
+
+/// A row of `Account.csv`
+pub struct AccountMovement {
+    pub date_time: NaiveDateTime,
+    pub value_date: Option<NaiveDate>,
+    pub product: Option<String>,
+    pub isin: Option<String>,
+    pub description: String,
+    pub fx_rate: Option<Decimal>,
+    pub change: Decimal,
+    pub currency: String,
+    pub order_id: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AccountMovementKind {
+    Dividend,
+    DividendTax,
+    FundingWithdrawal,
+    FxDebit,
+    FxCredit,
+    Interest,
+    Fee,
+    /// Money paid or received for a trade, which `Transactions.csv` already has
+    Trade,
+    Other,
+}
+
+impl AccountMovement {
+    /// Descriptions are written in the language of the account, English and German are known
+    pub fn kind(&self) -> AccountMovementKind {
+        let description = self.description.to_lowercase();
+        let starts_with_any = |prefixes: &[&str]| prefixes.iter().any(|p| description.starts_with(p));
+        let contains_any = |parts: &[&str]| parts.iter().any(|p| description.contains(p));
+
+        if starts_with_any(&["dividend tax", "dividendensteuer"]) {
+            AccountMovementKind::DividendTax
+        } else if starts_with_any(&["dividend", "dividende"]) {
+            AccountMovementKind::Dividend
+        } else if starts_with_any(&["fx debit", "fx withdrawal", "währungswechsel (ausbuchung)"]) {
+            AccountMovementKind::FxDebit
+        } else if starts_with_any(&["fx credit", "währungswechsel (einbuchung)"]) {
+            AccountMovementKind::FxCredit
+        } else if contains_any(&["deposit", "withdrawal", "einzahlung", "auszahlung"]) {
+            AccountMovementKind::FundingWithdrawal
+        } else if starts_with_any(&["buy ", "sell ", "kauf ", "verkauf "]) {
+            AccountMovementKind::Trade
+        } else if contains_any(&["interest", "zinsen"]) {
+            AccountMovementKind::Interest
+        } else if contains_any(&["fee", "gebühr", "kosten"]) {
+            AccountMovementKind::Fee
+        } else {
+            AccountMovementKind::Other
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum DegiroReportParsingError {
+    #[error(transparent)]
+    IO { #[from] source: std::io::Error },
+    #[error(transparent)]
+    Csv { #[from] source: csv::Error },
+    #[error("This file starts with an unknown header, expected the one of Transactions.csv or Account.csv")]
+    UnknownHeader,
+    #[error("Malformed row {row}: {reason}")]
+    MalformedRecord { row: usize, reason: String },
+}
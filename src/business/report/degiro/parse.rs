@@ -0,0 +1,364 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use futures_util::future::BoxFuture;
+use rust_decimal::Decimal;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::business::{csv_mapping::model::CsvMappingProfile, model::BrokerType};
+use crate::business::report::{model::{AbstractReport, ReportProcessingError}, parser::ReportParser};
+use super::model::{AccountMovement, DegiroReportParsingError, Report, Trade};
+
+/// English and German header names of every known column. Money columns are followed by an
+/// unnamed column, which holds either the currency or the amount.
+const COLUMNS: [(Column, &[&str], &[&str]); 18] = [
+    (Column::Date, &["Date"], &["Datum"]),
+    (Column::Time, &["Time"], &["Uhrzeit"]),
+    (Column::ValueDate, &["Value date"], &["Valutadatum"]),
+    (Column::Product, &["Product"], &["Produkt"]),
+    (Column::Isin, &["ISIN"], &["ISIN"]),
+    (Column::ReferenceExchange, &["Reference exchange"], &["Referenzbörse"]),
+    (Column::Venue, &["Venue"], &["Ausführungsort"]),
+    (Column::Quantity, &["Quantity"], &["Anzahl"]),
+    (Column::Price, &["Price"], &["Kurs"]),
+    (Column::LocalValue, &["Local value"], &["Wert in Lokalwährung"]),
+    (Column::Value, &["Value"], &["Wert"]),
+    (Column::ExchangeRate, &["Exchange rate"], &["Wechselkurs"]),
+    (Column::Fees, &["Transaction and/or third party fees", "Transaction and/or third", "Transaction costs"], &["Transaktionskosten und/oder Gebühren Dritter", "Transaktionskosten und/oder", "Transaktionsgebühren"]),
+    (Column::Total, &["Total"], &["Gesamt"]),
+    (Column::Description, &["Description"], &["Beschreibung"]),
+    (Column::Fx, &["FX"], &["FX"]),
+    (Column::Change, &["Change"], &["Änderung"]),
+    (Column::OrderId, &["Order ID", "Order Id"], &["Order-ID", "Order-Id"]),
+];
+
+/// Language of the header, which numbers of the file are written in
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Language {
+    English,
+    German,
+}
+
+impl Language {
+    /// English numbers are written as `1,234.56`, German ones as `1.234,56`
+    fn parse_number(&self, value: &str) -> Option<Decimal> {
+        let value = value.trim().replace(' ', "");
+        if value.is_empty() {
+            return None;
+        }
+        let normalized = match self {
+            Language::English => value.replace(',', ""),
+            Language::German => value.replace('.', "").replace(',', "."),
+        };
+        Decimal::from_str(&normalized).ok()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Column {
+    Date, Time, ValueDate, Product, Isin, ReferenceExchange, Venue, Quantity, Price, LocalValue,
+    Value, ExchangeRate, Fees, Total, Description, Fx, Change, OrderId,
+}
+
+enum ReportFileType {
+    Transactions,
+    Account,
+}
+
+impl ReportFileType {
+    fn of(header: &Header) -> Option<Self> {
+        if header.has(Column::Quantity) && header.has(Column::Price) {
+            Some(ReportFileType::Transactions)
+        } else if header.has(Column::Description) && header.has(Column::Change) {
+            Some(ReportFileType::Account)
+        } else {
+            None
+        }
+    }
+}
+
+pub struct DegiroReportParser;
+
+impl ReportParser for DegiroReportParser {
+    fn broker(&self) -> BrokerType {
+        BrokerType::Degiro
+    }
+
+    fn name(&self) -> &'static str {
+        "Degiro"
+    }
+
+    fn description(&self) -> &'static str {
+        "Transactions.csv or Account.csv from the inbox of the Degiro web trader, in English or German"
+    }
+
+    /// The header is one of Transactions.csv or Account.csv in a known language
+    fn is_suitable(&self, content: &[u8]) -> bool {
+        let content = String::from_utf8_lossy(content);
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(content.trim_start_matches('\u{feff}').as_bytes());
+        reader.records().next()
+            .and_then(|header| header.ok())
+            .is_some_and(|header| ReportFileType::of(&Header::read(&header)).is_some())
+    }
+
+    fn parse<'a>(&'a self, content: &'a [u8], _profile: Option<&'a CsvMappingProfile>) -> BoxFuture<'a, Result<AbstractReport, ReportProcessingError>> {
+        Box::pin(async move {
+            parse_report(content).await
+                .map(|report| report.into())
+                .map_err(|err| self.parsing_error(err))
+        })
+    }
+}
+
+pub async fn parse_report<R: AsyncRead + Unpin>(
+    mut reader: R,
+) -> Result<Report, DegiroReportParsingError> {
+    let mut content = String::new();
+    reader.read_to_string(&mut content).await?;
+    parse_report_csv(content.trim_start_matches('\u{feff}'))
+}
+
+fn parse_report_csv(content: &str) -> Result<Report, DegiroReportParsingError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    let mut records = reader.records();
+    let header = records.next().ok_or(DegiroReportParsingError::UnknownHeader)??;
+    let header = Header::read(&header);
+
+    let file_type = ReportFileType::of(&header).ok_or(DegiroReportParsingError::UnknownHeader)?;
+
+    let mut report = Report { trades: Vec::new(), account_movements: Vec::new() };
+    for (index, record) in records.enumerate() {
+        let record = record?;
+        if record.iter().all(|v| v.trim().is_empty()) {
+            continue;
+        }
+        let row = Row { header: &header, record: &record, number: index + 2 };
+        match file_type {
+            ReportFileType::Transactions => report.trades.push(row.trade()?),
+            ReportFileType::Account => if let Some(movement) = row.account_movement()? {
+                report.account_movements.push(movement);
+            },
+        }
+    }
+    Ok(report)
+}
+
+/// Positions of known columns, a money column takes two of them
+struct Header {
+    positions: HashMap<Column, Vec<usize>>,
+    language: Language,
+}
+
+impl Header {
+    fn read(record: &csv::StringRecord) -> Self {
+        let mut positions: HashMap<Column, Vec<usize>> = HashMap::new();
+        let mut language = Language::English;
+        let mut previous = None;
+        for (index, name) in record.iter().enumerate() {
+            let name = name.trim();
+            let column = if name.is_empty() {
+                previous
+            } else {
+                let is_any = |names: &[&str]| names.iter().any(|n| n.eq_ignore_ascii_case(name));
+                COLUMNS.iter()
+                    .find(|(_, english, german)| is_any(english) || is_any(german))
+                    .map(|(column, english, _)| {
+                        if !is_any(english) {
+                            language = Language::German;
+                        }
+                        *column
+                    })
+            };
+            if let Some(column) = column {
+                positions.entry(column).or_default().push(index);
+            }
+            previous = if name.is_empty() { None } else { column };
+        }
+        Header { positions, language }
+    }
+
+    fn has(&self, column: Column) -> bool {
+        self.positions.contains_key(&column)
+    }
+}
+
+struct Row<'a> {
+    header: &'a Header,
+    record: &'a csv::StringRecord,
+    number: usize,
+}
+
+impl Row<'_> {
+    fn malformed(&self, reason: String) -> DegiroReportParsingError {
+        DegiroReportParsingError::MalformedRecord { row: self.number, reason }
+    }
+
+    fn parse_number(&self, value: &str) -> Option<Decimal> {
+        self.header.language.parse_number(value)
+    }
+
+    fn values(&self, column: Column) -> Vec<&str> {
+        self.header.positions.get(&column)
+            .map(|positions| positions.iter()
+                .filter_map(|p| self.record.get(*p))
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty())
+                .collect())
+            .unwrap_or_default()
+    }
+
+    fn optional(&self, column: Column) -> Option<String> {
+        self.values(column).first().map(|v| v.to_string())
+    }
+
+    fn number(&self, column: Column) -> Result<Option<Decimal>, DegiroReportParsingError> {
+        self.values(column).first()
+            .map(|v| self.parse_number(v).ok_or_else(|| self.malformed(format!("'{v}' is not a number"))))
+            .transpose()
+    }
+
+    /// Amount and currency of a money column, which come in either order
+    fn money(&self, column: Column) -> Result<Option<(Decimal, Option<String>)>, DegiroReportParsingError> {
+        let values = self.values(column);
+        let amount = values.iter().find_map(|v| self.parse_number(v));
+        let currency = values.iter().find(|v| self.parse_number(v).is_none()).map(|v| v.to_string());
+        match (amount, values.is_empty()) {
+            (Some(amount), _) => Ok(Some((amount, currency))),
+            (None, true) => Ok(None),
+            (None, false) => Err(self.malformed(format!("no amount among {values:?}"))),
+        }
+    }
+
+    fn date_time(&self) -> Result<NaiveDateTime, DegiroReportParsingError> {
+        let date = self.optional(Column::Date).ok_or_else(|| self.malformed("date is missing".to_string()))?;
+        let date = parse_date(&date).ok_or_else(|| self.malformed(format!("'{date}' is not a date")))?;
+        let time = self.optional(Column::Time)
+            .and_then(|v| NaiveTime::parse_from_str(&v, "%H:%M").ok())
+            .unwrap_or(NaiveTime::MIN);
+        Ok(date.and_time(time))
+    }
+
+    fn trade(&self) -> Result<Trade, DegiroReportParsingError> {
+        let (price, price_currency) = self.money(Column::Price)?
+            .ok_or_else(|| self.malformed("price is missing".to_string()))?;
+        let (local_value, local_currency) = self.money(Column::LocalValue)?
+            .ok_or_else(|| self.malformed("local value is missing".to_string()))?;
+        let price_currency = price_currency.or(local_currency.clone())
+            .ok_or_else(|| self.malformed("currency of the price is missing".to_string()))?;
+        let (fees, fees_currency) = self.money(Column::Fees)?.unwrap_or((Decimal::ZERO, None));
+        Ok(Trade {
+            line: self.number,
+            date_time: self.date_time()?,
+            product: self.optional(Column::Product).unwrap_or_default(),
+            isin: self.optional(Column::Isin).ok_or_else(|| self.malformed("ISIN is missing".to_string()))?,
+            reference_exchange: self.optional(Column::ReferenceExchange),
+            venue: self.optional(Column::Venue),
+            quantity: self.number(Column::Quantity)?.ok_or_else(|| self.malformed("quantity is missing".to_string()))?,
+            price,
+            local_currency: local_currency.unwrap_or_else(|| price_currency.clone()),
+            price_currency,
+            local_value,
+            value: self.money(Column::Value)?.map(|(amount, _)| amount),
+            exchange_rate: self.number(Column::ExchangeRate)?,
+            fees,
+            fees_currency,
+            total: self.money(Column::Total)?.map(|(amount, _)| amount),
+            order_id: self.optional(Column::OrderId),
+        })
+    }
+
+    /// Rows without a change of money, like notes about money market funds, are skipped
+    fn account_movement(&self) -> Result<Option<AccountMovement>, DegiroReportParsingError> {
+        let Some((change, Some(currency))) = self.money(Column::Change)? else { return Ok(None) };
+        Ok(Some(AccountMovement {
+            date_time: self.date_time()?,
+            value_date: self.optional(Column::ValueDate).and_then(|v| parse_date(&v)),
+            product: self.optional(Column::Product),
+            isin: self.optional(Column::Isin),
+            description: self.optional(Column::Description).unwrap_or_default(),
+            fx_rate: self.number(Column::Fx)?,
+            change,
+            currency,
+            order_id: self.optional(Column::OrderId),
+        }))
+    }
+}
+
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    ["%d-%m-%Y", "%d.%m.%Y", "%Y-%m-%d"].iter()
+        .find_map(|format| NaiveDate::parse_from_str(value, format).ok())
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::fs::File;
+
+    use super::*;
+    use super::super::model::AccountMovementKind;
+
+    async fn parse_testdata(name: &str) -> Report {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("testdata");
+        d.push(name);
+        parse_report(File::open(d).await.unwrap()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn parses_english_transactions() {
+        let report = parse_testdata("degiro_transactions.csv").await;
+        assert!(report.account_movements.is_empty());
+        assert_eq!(report.trades.len(), 3);
+
+        let trade = &report.trades[0];
+        assert_eq!(trade.date_time.to_string(), "2024-02-15 15:31:00");
+        assert_eq!(trade.isin, "US9229083632");
+        assert_eq!(trade.quantity, Decimal::from(-2));
+        assert_eq!((trade.price, trade.price_currency.as_str()), (Decimal::new(45012, 2), "USD"));
+        assert_eq!((trade.fees, trade.fees_currency.as_deref()), (Decimal::new(-100, 2), Some("EUR")));
+        assert_eq!(trade.exchange_rate, Some(Decimal::new(10783, 4)));
+    }
+
+    #[tokio::test]
+    async fn parses_german_account() {
+        let report = parse_testdata("degiro_account_de.csv").await;
+        assert!(report.trades.is_empty());
+        assert_eq!(report.account_movements.len(), 9); // the note without an amount is skipped
+
+        let kinds: Vec<AccountMovementKind> = report.account_movements.iter().map(|m| m.kind()).collect();
+        assert_eq!(kinds, vec![
+            AccountMovementKind::FundingWithdrawal,
+            AccountMovementKind::FxDebit,
+            AccountMovementKind::FxCredit,
+            AccountMovementKind::Trade,
+            AccountMovementKind::Fee,
+            AccountMovementKind::Dividend,
+            AccountMovementKind::DividendTax,
+            AccountMovementKind::Interest,
+            AccountMovementKind::FundingWithdrawal,
+        ]);
+        assert_eq!(report.account_movements[0].change, Decimal::from(5000));
+        assert_eq!(report.account_movements[1].change, Decimal::new(-185472, 2));
+    }
+
+    #[test]
+    fn parses_numbers_in_the_language_of_the_header() {
+        let header = |names: &[&str]| Header::read(&csv::StringRecord::from(names.to_vec())).language;
+        assert_eq!(header(&["Date", "Time", "ISIN", "Quantity", "Price"]), Language::English);
+        assert_eq!(header(&["Datum", "Uhrzeit", "ISIN", "Anzahl", "Kurs"]), Language::German);
+
+        assert_eq!(Language::German.parse_number("1.234,56"), Some(Decimal::new(123456, 2)));
+        assert_eq!(Language::German.parse_number("-0,5"), Some(Decimal::new(-5, 1)));
+        assert_eq!(Language::English.parse_number("1,234.56"), Some(Decimal::new(123456, 2)));
+        // a thousands separator, not a decimal comma
+        assert_eq!(Language::English.parse_number("1,234"), Some(Decimal::from(1234)));
+        assert_eq!(Language::German.parse_number("1.234"), Some(Decimal::from(1234)));
+        assert_eq!(Language::English.parse_number("EUR"), None);
+    }
+}
@@ -1,6 +1,9 @@
+use std::collections::BTreeMap;
+
+use rust_decimal::prelude::ToPrimitive;
 use serde_json::json;
 
-use crate::business::{fiscal_transaction::model::FiscalTransactionType, model::{BrokerType, Money, OperationSource}, report::model::AbstractReport, trade_operation::model::TradeOperationSide};
+use crate::business::{currency_conversion::model::CurrencyConversion, fiscal_transaction::model::FiscalTransactionType, model::{BrokerType, Money, OperationSource}, report::model::{AbstractReport, RowDiagnostic}, security_transfer::model::{SecurityTransfer, TransferDirection}, trade_operation::model::TradeOperationSide};
 
 use super::model::TransactionOperationType;
 
@@ -8,9 +11,24 @@ use crate::business::{fiscal_transaction::model::FiscalTransaction, trade_operat
 
 impl From<super::model::Report> for AbstractReport {
     fn from(value: super::model::Report) -> Self {
+        let (security_movements, transactions): (Vec<_>, Vec<_>) = value.transactions.into_iter()
+            .partition(|v| v.is_security_movement());
+        let (currency_conversions, transactions) = pair_currency_conversions(transactions);
+        let mut diagnostics = value.diagnostics;
+        let mut security_transfers = Vec::new();
+        for movement in security_movements {
+            match security_transfer(movement) {
+                Ok(transfer) => security_transfers.extend(transfer),
+                Err(diagnostic) => diagnostics.push(diagnostic),
+            }
+        }
         Self {
             trade_operations: value.trade_operations.into_iter().map(|v| v.into()).collect(),
-            fiscal_transactions: value.transactions.into_iter().map(|v| v.into()).collect(),
+            fiscal_transactions: transactions.into_iter().map(|v| v.into()).collect(),
+            security_transfers,
+            currency_conversions,
+            position_snapshot: vec![],
+            diagnostics,
             broker: BrokerType::Exante
         }
     }
@@ -18,6 +36,11 @@ impl From<super::model::Report> for AbstractReport {
 
 impl From<super::model::TradeOperation> for TradeOperation {
     fn from(value: super::model::TradeOperation) -> Self {
+        // Exante reports a zero P&L of every purchase, it's only a result of a sale
+        let reported_pnl = match value.side {
+            super::model::TradeOperationSide::Sell => Some(Money::new(value.pnl, value.currency.clone())),
+            super::model::TradeOperationSide::Buy => None,
+        };
         Self { 
             operation_source: OperationSource::ExanteReport,
             broker: Some(BrokerType::Exante),
@@ -33,6 +56,7 @@ impl From<super::model::TradeOperation> for TradeOperation {
             quantity: value.quantity,
             commission: Some(Money::new(value.commission, value.commission_currency)),
             order_id: Some(value.order_id.to_string()),
+            reported_pnl,
             summ: Money::new(value.traded_volume, value.currency),
             metadata: json!({
                 "uti": value.uti,
@@ -63,6 +87,14 @@ impl From<super::model::Transaction> for FiscalTransaction {
                 TransactionOperationType::Trade => FiscalTransactionType::Unrecognized("Trade".to_owned()),
                 TransactionOperationType::Commission => FiscalTransactionType::Commission,
                 TransactionOperationType::FundingWithdrawal => FiscalTransactionType::FundingWithdrawal,
+                TransactionOperationType::Interest => FiscalTransactionType::Interest,
+                TransactionOperationType::Autoconversion => FiscalTransactionType::CurrencyExchange,
+                TransactionOperationType::Exchange => FiscalTransactionType::CurrencyExchange,
+                TransactionOperationType::Rollover => FiscalTransactionType::Rollover,
+                // money of corporate actions, splits and transfers, like a payment for fractional securities
+                TransactionOperationType::CorporateAction => FiscalTransactionType::CorporateAction,
+                TransactionOperationType::StockSplit => FiscalTransactionType::CorporateAction,
+                TransactionOperationType::SecurityTransfer => FiscalTransactionType::CorporateAction,
                 TransactionOperationType::Unrecognized(a) => FiscalTransactionType::Unrecognized(a),
             },
             amount: Money::new(value.sum, value.asset),
@@ -75,6 +107,173 @@ impl From<super::model::Transaction> for FiscalTransaction {
                 "uuid": value.uuid,
                 "parent_uuid": value.parent_uuid
             }),
+            ex_date: None,
+            amount_per_share: None,
+        }
+    }
+}
+
+/// Exante books a conversion as a withdrawal of the sold currency and a deposit of the bought
+/// one, both sharing the parent operation. Rows which don't make up such a pair are returned
+/// as they are.
+fn pair_currency_conversions(
+    transactions: Vec<super::model::Transaction>,
+) -> (Vec<CurrencyConversion>, Vec<super::model::Transaction>) {
+    let is_conversion = |v: &super::model::Transaction| matches!(v.operation_type,
+        TransactionOperationType::Autoconversion | TransactionOperationType::Exchange);
+    let (conversion_rows, mut rest): (Vec<_>, Vec<_>) = transactions.into_iter().partition(is_conversion);
+
+    let mut groups: BTreeMap<String, Vec<super::model::Transaction>> = BTreeMap::new();
+    for row in conversion_rows {
+        let key = if row.parent_uuid == "None" { row.timestamp.to_string() } else { row.parent_uuid.clone() };
+        groups.entry(key).or_default().push(row);
+    }
+
+    let mut conversions = Vec::new();
+    for (_, mut rows) in groups {
+        rows.sort_by_key(|v| v.sum.is_sign_positive());
+        match rows.as_slice() {
+            [sold, bought] if sold.sum.is_sign_negative() && bought.sum.is_sign_positive() && sold.asset != bought.asset => {
+                let sold_money = Money::new(-sold.sum, sold.asset.clone());
+                let bought_money = Money::new(bought.sum, bought.asset.clone());
+                conversions.push(CurrencyConversion {
+                    operation_source: OperationSource::ExanteReport,
+                    broker: Some(BrokerType::Exante),
+                    external_id: Some(format!("{}/{}", sold.id, bought.id)),
+                    date_time: sold.timestamp.max(bought.timestamp),
+                    rate: CurrencyConversion::implied_rate(&sold_money, &bought_money),
+                    sold: sold_money,
+                    bought: bought_money,
+                    metadata: json!({
+                        "account_id": sold.account_id,
+                        "type": sold.operation_type.to_string(),
+                        "comment": sold.comment,
+                        "uuids": [sold.uuid, bought.uuid],
+                        "parent_uuid": sold.parent_uuid,
+                    }),
+                });
+            },
+            _ => rest.append(&mut rows),
+        }
+    }
+    (conversions, rest)
+}
+
+/// Securities of the `asset` added to or removed from the account, the sum is their amount.
+/// Fractions of securities aren't supported, and are left out with a diagnostic.
+fn security_transfer(value: super::model::Transaction) -> Result<Option<SecurityTransfer>, RowDiagnostic> {
+    if !value.sum.fract().is_zero() {
+        return Err(RowDiagnostic {
+            line: value.line,
+            section: "transactions".to_string(),
+            column: Some("Sum".to_string()),
+            value: Some(value.sum.to_string()),
+            message: format!("{} has a fraction of a security, which can't be stored yet", value.asset),
+        });
+    }
+    let Some(quantity) = value.sum.abs().to_i32().filter(|quantity| *quantity > 0) else { return Ok(None) };
+    Ok(Some(SecurityTransfer {
+        operation_source: OperationSource::ExanteReport,
+        broker: Some(BrokerType::Exante),
+        external_id: Some(value.id),
+        date_time: value.timestamp,
+        direction: if value.sum.is_sign_negative() { TransferDirection::Out } else { TransferDirection::In },
+        is_split: value.operation_type == TransactionOperationType::StockSplit,
+        instrument_symbol: value.asset,
+        isin: Some(value.isin),
+        quantity,
+        cost_basis: None,
+        metadata: json!({
+            "account_id": value.account_id,
+            "type": value.operation_type.to_string(),
+            "comment": value.comment,
+            "uuid": value.uuid,
+            "parent_uuid": value.parent_uuid
+        }),
+    }))
+}
+
+
+#[cfg(test)]
+mod test {
+    use rust_decimal::Decimal;
+
+    use super::*;
+    use super::super::model::{Report, Transaction};
+
+    fn transaction(id: &str, operation_type: TransactionOperationType, sum: i64, asset: &str, isin: &str) -> Transaction {
+        Transaction {
+            line: 2,
+            id: id.to_string(),
+            account_id: "AMD0000.001".to_string(),
+            symbol_id: if isin == "None" { "None".to_string() } else { asset.to_string() },
+            isin: isin.to_string(),
+            operation_type,
+            timestamp: chrono::NaiveDateTime::default(),
+            sum: Decimal::from(sum),
+            asset: asset.to_string(),
+            eur_equivalent: Decimal::ZERO,
+            comment: String::new(),
+            uuid: id.to_string(),
+            parent_uuid: "None".to_string(),
         }
     }
+
+    #[test]
+    fn maps_money_and_securities_movements() {
+        let report: AbstractReport = Report {
+            trade_operations: vec![],
+            transactions: vec![
+                transaction("1", TransactionOperationType::Autoconversion, -100, "EUR", "None"),
+                transaction("2", TransactionOperationType::Autoconversion, 108, "USD", "None"),
+                transaction("3", TransactionOperationType::Interest, -2, "USD", "None"),
+                transaction("4", TransactionOperationType::StockSplit, -10, "VOO.ARCA", "US9229083632"),
+                transaction("5", TransactionOperationType::StockSplit, 40, "VOO.ARCA", "US9229083632"),
+                transaction("6", TransactionOperationType::SecurityTransfer, 3, "SCHD.ARCA", "US8085247976"),
+            ],
+            diagnostics: vec![],
+        }.into();
+
+        let types: Vec<String> = report.fiscal_transactions.iter().map(|t| t.operation_type.to_string()).collect();
+        assert_eq!(types, vec!["Interest"]);
+        assert_eq!(report.currency_conversions.len(), 1);
+        let conversion = &report.currency_conversions[0];
+        assert_eq!(conversion.sold, Money::new(Decimal::from(100), "EUR".to_string()));
+        assert_eq!(conversion.bought, Money::new(Decimal::from(108), "USD".to_string()));
+        assert_eq!(conversion.rate, Decimal::new(108, 2));
+
+        assert_eq!(report.security_transfers.len(), 3);
+        let (split_out, split_in, transfer) = (&report.security_transfers[0], &report.security_transfers[1], &report.security_transfers[2]);
+        assert!(split_out.is_split && split_out.direction == TransferDirection::Out && split_out.quantity == 10);
+        assert!(split_in.is_split && split_in.direction == TransferDirection::In && split_in.quantity == 40);
+        assert!(!transfer.is_split && transfer.direction == TransferDirection::In);
+        assert_eq!(transfer.instrument_symbol, "SCHD.ARCA");
+    }
+
+    #[test]
+    fn leaves_out_fractional_security_movements_with_diagnostics() {
+        let mut fractional = transaction("1", TransactionOperationType::CorporateAction, 0, "VOO.ARCA", "US9229083632");
+        fractional.sum = Decimal::new(5, 1);
+        let report: AbstractReport = Report {
+            trade_operations: vec![],
+            transactions: vec![fractional],
+            diagnostics: vec![],
+        }.into();
+
+        assert!(report.security_transfers.is_empty());
+        let diagnostics: Vec<(usize, &str, Option<&str>, Option<&str>)> = report.diagnostics.iter()
+            .map(|d| (d.line, d.section.as_str(), d.column.as_deref(), d.value.as_deref()))
+            .collect();
+        assert_eq!(diagnostics, vec![(2, "transactions", Some("Sum"), Some("0.5"))]);
+    }
+
+    #[tokio::test]
+    async fn keeps_reported_pnl_of_sales_only() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("testdata/exante_small_report.csv");
+        let report: AbstractReport = super::super::parse::parse_report(tokio::fs::File::open(d).await.unwrap()).await.unwrap().into();
+
+        assert!(report.trade_operations.iter()
+            .all(|t| t.reported_pnl.is_some() == (t.side == TradeOperationSide::Sell)));
+    }
 }
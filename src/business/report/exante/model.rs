@@ -1,9 +1,10 @@
 use chrono::NaiveDateTime;
 use rust_decimal::Decimal;
 use serde::Deserialize;
-use serde_enum_str::Deserialize_enum_str;
+use serde_enum_str::{Deserialize_enum_str, Serialize_enum_str};
 use uuid::Uuid;
 
+use crate::business::report::model::RowDiagnostic;
 #[allow(unused_imports)]
 use crate::util::serde::date_time_format;
 
@@ -55,7 +56,7 @@ pub struct TradeOperation {
     pub trade_type: String, // like "TRADE"
 }
 
-#[derive(Deserialize_enum_str,PartialEq)]
+#[derive(Deserialize_enum_str,Serialize_enum_str,PartialEq)]
 pub enum TransactionOperationType {
     #[serde(rename = "US TAX")]
     UsTax,
@@ -69,12 +70,36 @@ pub enum TransactionOperationType {
     Commission,
     #[serde(rename = "FUNDING/WITHDRAWAL")]
     FundingWithdrawal,
+    #[serde(rename = "INTEREST")]
+    Interest,
+    #[serde(rename = "AUTOCONVERSION")]
+    Autoconversion,
+    #[serde(rename = "EXCHANGE")]
+    Exchange,
+    #[serde(rename = "ROLLOVER")]
+    Rollover,
+    #[serde(rename = "CORPORATE ACTION")]
+    CorporateAction,
+    #[serde(rename = "STOCK SPLIT")]
+    StockSplit,
+    #[serde(rename = "SECURITY TRANSFER")]
+    SecurityTransfer,
     #[serde(other)]
     Unrecognized(String),
 }
 
+impl TransactionOperationType {
+    /// Operations which may move securities rather than money
+    pub fn may_move_securities(&self) -> bool {
+        matches!(self, Self::CorporateAction | Self::StockSplit | Self::SecurityTransfer)
+    }
+}
+
 #[derive(Deserialize)]
 pub struct Transaction {
+    /// Line of the row in the file
+    #[serde(skip)]
+    pub line: usize,
     #[serde(rename = "Transaction ID")]
     pub id: String,
     #[serde(rename = "Account ID")]
@@ -101,9 +126,18 @@ pub struct Transaction {
     pub parent_uuid: String,
 }
 
+impl Transaction {
+    /// The sum is an amount of securities of the `asset`, not of money
+    pub fn is_security_movement(&self) -> bool {
+        self.isin != "None" && self.operation_type.may_move_securities()
+    }
+}
+
 pub struct Report {
     pub trade_operations: Vec<TradeOperation>,
     pub transactions: Vec<Transaction>,
+    /// Rows of trades and transactions which can't be read
+    pub diagnostics: Vec<RowDiagnostic>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -112,6 +146,4 @@ pub enum ExanteReportParsingError {
     IO { #[from] source: std::io::Error },
     #[error("This file starts with an unknown header")]
     UnknownHeader,
-    #[error(transparent)]
-    Csv { #[from] source: csv::Error }
 }
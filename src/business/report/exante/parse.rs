@@ -1,8 +1,12 @@
 use super::model::{Report, TradeOperation, Transaction, ExanteReportParsingError};
 use super::model::TransactionOperationType;
 use serde::de::DeserializeOwned;
+use futures_util::future::BoxFuture;
 use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
 
+use crate::business::{csv_mapping::model::CsvMappingProfile, model::BrokerType};
+use crate::business::report::{model::{AbstractReport, ReportProcessingError, RowDiagnostic}, parser::ReportParser};
+
 const EXANTE_REPORT_DELIMETER: char = '\t';
 
 fn split_csv_line_to_fields(line: &str) -> Vec<String> {
@@ -68,6 +72,37 @@ impl ReportRecordType {
 }
 
 
+pub struct ExanteReportParser;
+
+impl ReportParser for ExanteReportParser {
+    fn broker(&self) -> BrokerType {
+        BrokerType::Exante
+    }
+
+    fn name(&self) -> &'static str {
+        "Exante"
+    }
+
+    fn description(&self) -> &'static str {
+        "Tab-separated trades and transactions from the reports section of the Exante client area"
+    }
+
+    /// The report starts with a header of trades or transactions
+    fn is_suitable(&self, content: &[u8]) -> bool {
+        let first_line = String::from_utf8_lossy(content).lines().next().unwrap_or_default().to_string();
+        ReportRecordType::get_suitable_type_for_header(&split_csv_line_to_fields(&first_line)).is_some()
+    }
+
+    fn parse<'a>(&'a self, content: &'a [u8], _profile: Option<&'a CsvMappingProfile>) -> BoxFuture<'a, Result<AbstractReport, ReportProcessingError>> {
+        Box::pin(async move {
+            parse_report(content).await
+                .map(|report| report.into())
+                .map_err(|err| self.parsing_error(err))
+        })
+    }
+}
+
+/// Reads the report, where rows which can't be read are left out with a diagnostic of each
 pub async fn parse_report<R: AsyncRead + Unpin>(
     reader: R,
 ) -> Result<Report, ExanteReportParsingError> {
@@ -77,8 +112,11 @@ pub async fn parse_report<R: AsyncRead + Unpin>(
 
     let mut trade_operations = Vec::new();
     let mut transactions = Vec::new();
+    let mut diagnostics = Vec::new();
+    let mut line_number = 0;
 
     while let Some(line) = lines.next_line().await? {
+        line_number += 1;
         let split_line = split_csv_line_to_fields(&line);
         if let Some(record_type) = ReportRecordType::get_suitable_type_for_header(&split_line) {
             let header = csv::StringRecord::from(split_line);
@@ -88,17 +126,25 @@ pub async fn parse_report<R: AsyncRead + Unpin>(
                 .as_ref()
                 .ok_or(ExanteReportParsingError::UnknownHeader)?;
             let record = csv::StringRecord::from(split_line);
+            let diagnostic = |err: csv::Error| row_diagnostic(line_number, &current_record_type.0, &current_record_type.1, &record, err);
             match current_record_type.0 {
                 ReportRecordType::TradeOperation => {
-                    let report_item: TradeOperation =
-                        record.deserialize(Some(&current_record_type.1))?;
-                    trade_operations.push(report_item);
+                    match record.deserialize::<TradeOperation>(Some(&current_record_type.1)) {
+                        Ok(report_item) => trade_operations.push(report_item),
+                        Err(err) => diagnostics.push(diagnostic(err)),
+                    }
                 }
                 ReportRecordType::Transaction => {
-                    let report_item: Transaction =
-                        record.deserialize(Some(&current_record_type.1))?;
-                    if report_item.isin == "None" && report_item.operation_type != TransactionOperationType::Trade {
-                        transactions.push(report_item); // only save fiscal tranactions
+                    let report_item = match record.deserialize::<Transaction>(Some(&current_record_type.1)) {
+                        Ok(report_item) => Transaction { line: line_number, ..report_item },
+                        Err(err) => {
+                            diagnostics.push(diagnostic(err));
+                            continue;
+                        }
+                    };
+                    let is_fiscal = report_item.isin == "None" || report_item.is_security_movement();
+                    if is_fiscal && report_item.operation_type != TransactionOperationType::Trade {
+                        transactions.push(report_item); // only save fiscal tranactions and moved securities
                     }
                 }
             }
@@ -108,9 +154,33 @@ pub async fn parse_report<R: AsyncRead + Unpin>(
     Ok(Report {
         trade_operations,
         transactions,
+        diagnostics,
     })
 }
 
+fn row_diagnostic(line: usize, record_type: &ReportRecordType, header: &csv::StringRecord, record: &csv::StringRecord, err: csv::Error) -> RowDiagnostic {
+    let section = match record_type {
+        ReportRecordType::TradeOperation => "trades",
+        ReportRecordType::Transaction => "transactions",
+    }.to_string();
+    match err.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => {
+            let message = err.kind().to_string();
+            // errors of types like Decimal don't know their field, but quote the value which failed
+            let field = err.field().map(|field| field as usize).or_else(|| record.iter()
+                .position(|value| !value.is_empty() && message.contains(&format!("\"{value}\""))));
+            RowDiagnostic {
+                line,
+                section,
+                column: field.and_then(|field| header.get(field)).map(|column| column.to_string()),
+                value: field.and_then(|field| record.get(field)).map(|value| value.to_string()),
+                message,
+            }
+        },
+        _ => RowDiagnostic { line, section, column: None, value: None, message: err.to_string() },
+    }
+}
+
 #[cfg(test)]
 mod test {
     use tokio::fs::File;
@@ -145,4 +215,23 @@ mod test {
         let that_one_trade = report.trade_operations.iter().find(|o| o.order_id == required_id).unwrap();
         assert_eq!(that_one_trade.price.to_string(), "76.49");
     }
+
+    #[tokio::test]
+    async fn leaves_out_malformed_rows_with_diagnostics() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("testdata/exante_malformed_report.csv");
+        let file = File::open(d).await.unwrap();
+
+        let report = parse_report(file).await.unwrap();
+        assert_eq!(report.trade_operations.len(), 24);
+        assert_eq!(report.transactions.len(), 80);
+
+        let diagnostics: Vec<(usize, &str, Option<&str>, Option<&str>)> = report.diagnostics.iter()
+            .map(|d| (d.line, d.section.as_str(), d.column.as_deref(), d.value.as_deref()))
+            .collect();
+        assert_eq!(diagnostics, vec![
+            (3, "trades", Some("Price"), Some("x363.85")),
+            (29, "transactions", Some("Sum"), Some("12,5 USD")),
+        ]);
+    }
 }
@@ -1,15 +1,63 @@
+use std::collections::HashMap;
+
+use rust_decimal::{prelude::ToPrimitive, Decimal};
 use serde_json::json;
 
-use super::super::model::AbstractReport;
+use super::super::model::{AbstractReport, ExternalIds};
 use super::model::CashInOutType;
 
-use crate::{business::{fiscal_transaction::model::{FiscalTransaction, FiscalTransactionType}, model::{BrokerType, Money, OperationSource}, trade_operation::model::{TradeOperation, TradeOperationSide}}};
+use crate::{business::{fiscal_transaction::model::{FiscalTransaction, FiscalTransactionType}, model::{BrokerType, Money, OperationSource}, position_snapshot::model::SnapshotPosition, security_transfer::model::{SecurityTransfer, TransferDirection}, trade_operation::model::{TradeOperation, TradeOperationSide}}};
 
 impl From<super::model::Report> for AbstractReport {
     fn from(value: super::model::Report) -> Self {
+        let security_transfers: Vec<SecurityTransfer> = value.security_in_outs().filter_map(security_transfer).collect();
+
+        // taxes carry no ticker, it's taken from the income they are withheld from
+        let income_tickers: HashMap<u64, String> = value.cash_in_outs.iter()
+            .filter_map(|v| v.ticker.clone().map(|ticker| (v.transaction_id, ticker)))
+            .collect();
+        let taxes: Vec<FiscalTransaction> = value.taxes.into_iter()
+            .map(|v| {
+                let ticker = income_tickers.get(&v.income_transaction_id).cloned();
+                tax_transaction(v, ticker)
+            })
+            .collect();
+
+        let mut corporate_actions: HashMap<String, super::model::CorporateAction> = HashMap::new();
+        for action in value.corporate_actions.into_iter().flat_map(|v| v.detailed) {
+            corporate_actions.entry(action.corporate_action_id.clone()).or_insert(action);
+        }
+        let cash_in_outs = value.cash_in_outs.into_iter()
+            .map(|v| {
+                let action = corporate_action_id(&v.details).and_then(|id| corporate_actions.get(&id));
+                let mut transaction: FiscalTransaction = v.into();
+                if let Some(action) = action {
+                    add_corporate_action(&mut transaction, action);
+                }
+                transaction
+            });
+
+        // commissions of trades are linked by the trade id to the traded instrument
+        let traded_instruments: HashMap<u64, (String, String)> = value.trades.detailed.iter()
+            .map(|v| (v.trade_id, (v.id.clone(), v.instr_nm.clone())))
+            .collect();
+        let mut external_ids = ExternalIds::default();
+        let commissions: Vec<FiscalTransaction> = value.commissions.into_iter()
+            .flat_map(|v| v.detailed)
+            .filter(|v| !v.sum.is_zero())
+            .map(|v| {
+                let trade = v.trade_id().and_then(|id| traded_instruments.get(&id).cloned());
+                commission_transaction(v, trade, &mut external_ids)
+            })
+            .collect();
+
         Self {
             trade_operations: value.trades.detailed.into_iter().map(|v| v.into()).collect(),
-            fiscal_transactions: value.cash_in_outs.into_iter().map(|v| v.into()).collect(),
+            fiscal_transactions: cash_in_outs.chain(taxes).chain(commissions).collect(),
+            security_transfers,
+            currency_conversions: vec![],
+            position_snapshot: value.account_at_end.map(position_snapshot).unwrap_or_default(),
+            diagnostics: vec![],
             broker: BrokerType::Freedomfinance
         }
     }
@@ -33,6 +81,7 @@ impl From<super::model::DetailedTrade> for TradeOperation {
             commission: Some(Money::new(value.commission, value.commission_currency)),
             order_id: Some(value.order_id.to_string()),
             summ: Money::new(value.summ, value.curr_c),
+            reported_pnl: None,
             metadata: json!({
                 "comment": value.comment,
                 "market": value.mkt_name,
@@ -46,7 +95,7 @@ impl From<super::model::DetailedTrade> for TradeOperation {
 impl From<super::model::CashInOut> for FiscalTransaction {
     fn from(value: super::model::CashInOut) -> Self {
         let commission = if let Some(currency) = value.commission_currency {
-            if rust_decimal::prelude::Zero::is_zero(&value.commission) {
+            if value.commission.is_zero() {
                 None
             } else {
                 Some(Money::new(value.commission, currency))
@@ -75,6 +124,223 @@ impl From<super::model::CashInOut> for FiscalTransaction {
                 "value_usd_details": value.value_usd_details,
                 "reverted": value.reverted.to_string(),
             }),
+            ex_date: None,
+            amount_per_share: None,
         }
     }
 }
+
+fn tax_transaction(value: super::model::Tax, ticker: Option<String>) -> FiscalTransaction {
+    FiscalTransaction {
+        operation_source: OperationSource::FreedomfinanceReport,
+        broker: Some(BrokerType::Freedomfinance),
+        // ids of taxes and cash in/outs are sequenced separately
+        external_id: Some(format!("tax-{}", value.id)),
+        date_time: value.datetime,
+        symbol_id: ticker,
+        amount: Money::new(value.amount, value.currency),
+        operation_type: FiscalTransactionType::Tax,
+        commission: None,
+        metadata: json!({
+            "transaction_id": value.transaction_id.to_string(),
+            "income_transaction_id": value.income_transaction_id.to_string(),
+            "tax_rate": value.tax_rate.to_string(),
+            "citizenship_code": value.citizenship_code,
+            "comment": value.comment,
+        }),
+        ex_date: None,
+        amount_per_share: None,
+    }
+}
+
+/// `trade` is the external id and the instrument of the trade the commission is charged for
+fn commission_transaction(value: super::model::Commission, trade: Option<(String, String)>, external_ids: &mut ExternalIds) -> FiscalTransaction {
+    let trade_id = value.trade_id().map(|v| v.to_string());
+    let (trade_external_id, symbol_id) = trade.unzip();
+    FiscalTransaction {
+        operation_source: OperationSource::FreedomfinanceReport,
+        broker: Some(BrokerType::Freedomfinance),
+        // the ledger has no ids, a trade is charged once and other commissions are told apart
+        // by their contents and a counter of identical ones
+        external_id: Some(match &trade_id {
+            Some(trade_id) => format!("commission-trade-{}", trade_id),
+            None => external_ids.next(format!(
+                "commission-{}-{}-{}{}",
+                value.datetime,
+                value.commission_type.trim(),
+                value.sum.normalize(),
+                value.currency,
+            )),
+        }),
+        date_time: value.datetime,
+        symbol_id,
+        amount: Money::new(-value.sum.abs(), value.currency),
+        operation_type: FiscalTransactionType::Commission,
+        commission: None,
+        metadata: json!({
+            "type": value.commission_type.trim(),
+            "comment": value.comment.trim(),
+            "trade_id": trade_id,
+            "trade_external_id": trade_external_id,
+        }),
+        ex_date: None,
+        amount_per_share: None,
+    }
+}
+
+fn security_transfer(value: &super::model::SecurityInOut) -> Option<SecurityTransfer> {
+    let quantity = value.quantity.abs().to_i32().filter(|quantity| *quantity > 0)?;
+    let is_withdrawn = value.quantity.is_sign_negative()
+        || value.transfer_type.as_deref().is_some_and(|t| t.to_lowercase().contains("out"));
+    let direction = if is_withdrawn { TransferDirection::Out } else { TransferDirection::In };
+    let cost_basis = value.price.zip(value.currency.clone())
+        .map(|(price, currency)| Money::new(price * Decimal::from(quantity), currency));
+    Some(SecurityTransfer {
+        operation_source: OperationSource::FreedomfinanceReport,
+        broker: Some(BrokerType::Freedomfinance),
+        external_id: Some(value.id.to_string()),
+        date_time: value.datetime,
+        direction,
+        instrument_symbol: value.ticker.clone(),
+        isin: value.isin.clone(),
+        quantity,
+        cost_basis,
+        metadata: json!({
+            "type": value.transfer_type,
+            "comment": value.comment,
+        }),
+        is_split: false,
+    })
+}
+
+fn position_snapshot(value: super::model::AccountAtEnd) -> Vec<SnapshotPosition> {
+    let taken_at = value.date;
+    let super::model::PositionsAndAccounts { acc, pos } = value.account.positions_from_ts.ps;
+    let balances = acc.into_iter().map(|v| SnapshotPosition {
+        taken_at,
+        instrument_symbol: None,
+        isin: None,
+        quantity: v.sum,
+        currency: v.curr,
+    });
+    let positions = pos.into_iter().map(|v| SnapshotPosition {
+        taken_at,
+        instrument_symbol: Some(v.ticker),
+        isin: v.isin,
+        quantity: v.quantity,
+        currency: v.curr,
+    });
+    balances.chain(positions).collect()
+}
+
+/// Id of the corporate action a cash in/out originates from, found in its `details` JSON
+fn corporate_action_id(details: &str) -> Option<String> {
+    let details: serde_json::Value = serde_json::from_str(details).ok()?;
+    details.get("corporate_action_id")?.as_str().map(|v| v.to_string())
+}
+
+fn add_corporate_action(transaction: &mut FiscalTransaction, action: &super::model::CorporateAction) {
+    transaction.ex_date = Some(action.ex_date);
+    transaction.amount_per_share = Some(action.amount_per_one);
+    let Some(metadata) = transaction.metadata.as_object_mut() else { return };
+    metadata.insert("corporate_action_id".to_string(), json!(action.corporate_action_id));
+    metadata.insert("isin".to_string(), json!(action.isin));
+    metadata.insert("quantity_on_ex_date".to_string(), json!(action.q_on_ex_date.to_string()));
+    metadata.insert("tax_amount".to_string(), json!(action.tax_amount.to_string()));
+    metadata.insert("tax_currency".to_string(), json!(action.tax_currency));
+    metadata.insert("external_tax".to_string(), json!(action.external_tax.to_string()));
+    metadata.insert("external_tax_currency".to_string(), json!(action.external_tax_currency));
+}
+
+
+#[cfg(test)]
+mod test {
+    use tokio::fs::File;
+
+    use super::*;
+    use crate::business::report::freedomfinance::parse::parse_report;
+
+    #[tokio::test]
+    async fn links_taxes_to_dividends() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("testdata/freedomfinance_report.json");
+        let report: AbstractReport = parse_report(File::open(d).await.unwrap()).await.unwrap().into();
+
+        let dividend = report.fiscal_transactions.iter()
+            .find(|t| t.external_id.as_deref() == Some("42515439"))
+            .unwrap();
+        let tax = report.fiscal_transactions.iter()
+            .find(|t| t.external_id.as_deref() == Some("tax-629702"))
+            .unwrap();
+
+        assert!(matches!(tax.operation_type, FiscalTransactionType::Tax));
+        assert_eq!(tax.symbol_id.as_deref(), Some("RING.US"));
+        assert_eq!(tax.date_time, dividend.date_time);
+        assert_eq!(tax.amount, Money::new(rust_decimal::Decimal::new(-17, 2), "USD".to_string()));
+        assert_eq!(tax.metadata["income_transaction_id"], dividend.metadata["transaction_id"]);
+    }
+
+    #[tokio::test]
+    async fn adds_corporate_actions_to_dividends() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("testdata/freedomfinance_report.json");
+        let report: AbstractReport = parse_report(File::open(d).await.unwrap()).await.unwrap().into();
+
+        let dividend = report.fiscal_transactions.iter()
+            .find(|t| t.external_id.as_deref() == Some("42515439"))
+            .unwrap();
+
+        assert_eq!(dividend.metadata["corporate_action_id"], "2022-12-14_35_RING.US_0.28231");
+        assert_eq!(dividend.ex_date, chrono::NaiveDate::from_ymd_opt(2022, 12, 14));
+        assert_eq!(dividend.amount_per_share, Some(rust_decimal::Decimal::new(28231, 5)));
+        assert_eq!(dividend.metadata["quantity_on_ex_date"], "2.00");
+        assert_eq!(dividend.metadata["tax_amount"], "-0.17");
+    }
+
+    #[test]
+    fn finds_trade_of_commission() {
+        let commission = |commission_type: &str| super::super::model::Commission {
+            datetime: chrono::NaiveDateTime::default(),
+            sum: rust_decimal::Decimal::ONE,
+            currency: "EUR".to_string(),
+            commission_type: commission_type.to_string(),
+            comment: String::new(),
+        };
+
+        assert_eq!(commission(" За сделку:  290828464").trade_id(), Some(290828464));
+        assert_eq!(commission(" Прочие комиссии ").trade_id(), None);
+
+        let mut external_ids = ExternalIds::default();
+        let transaction = commission_transaction(commission(" За сделку:  290828464"), Some(("123".to_string(), "VOO.US".to_string())), &mut external_ids);
+        assert_eq!(transaction.external_id.as_deref(), Some("commission-trade-290828464"));
+        assert_eq!(transaction.symbol_id.as_deref(), Some("VOO.US"));
+        assert_eq!(transaction.amount, Money::new(-rust_decimal::Decimal::ONE, "EUR".to_string()));
+        assert_eq!(transaction.metadata["trade_external_id"], "123");
+
+        // identical fees of the same moment are told apart
+        let first = commission_transaction(commission(" Прочие комиссии "), None, &mut external_ids);
+        let second = commission_transaction(commission(" Прочие комиссии "), None, &mut external_ids);
+        assert_ne!(first.external_id, second.external_id);
+    }
+
+    #[tokio::test]
+    async fn imports_security_transfers_of_both_sections() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("testdata/freedomfinance_report.json");
+        let mut report: serde_json::Value = serde_json::from_slice(&tokio::fs::read(d).await.unwrap()).unwrap();
+        let transfer = |id: u64, quantity: &str| json!({
+            "id": id, "datetime": "2023-03-01 10:00:00", "ticker": "VOO.US", "isin": "US9229083632",
+            "quantity": quantity, "type": "in", "price": "350.5", "currency": "USD", "comment": null,
+        });
+        report["securities_in_outs"] = json!([transfer(1, "2")]);
+        report["in_outs_securities"]["detailed"] = json!([transfer(1, "2"), transfer(2, "-1")]);
+        let report: AbstractReport = parse_report(serde_json::to_vec(&report).unwrap().as_slice()).await.unwrap().into();
+
+        assert_eq!(report.security_transfers.len(), 2);
+        let incoming = &report.security_transfers[0];
+        assert_eq!(incoming.direction, TransferDirection::In);
+        assert_eq!(incoming.quantity, 2);
+        assert_eq!(incoming.cost_basis, Some(Money::new(rust_decimal::Decimal::new(7010, 1), "USD".to_string())));
+        assert_eq!(report.security_transfers[1].direction, TransferDirection::Out);
+    }
+}
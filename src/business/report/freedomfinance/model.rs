@@ -8,6 +8,9 @@ use serde_enum_str::Deserialize_enum_str;
 use crate::util::serde::date_time_format;
 
 
+/// Trades of the report period. Its `securities` are the net quantities traded per ticker within
+/// the period rather than holdings, they repeat `detailed` and are not imported. Holdings of the
+/// broker are taken from `account_at_end`.
 #[derive(Deserialize)]
 pub struct Trades {
     pub detailed: Vec<DetailedTrade>,
@@ -93,11 +96,161 @@ pub struct CashInOut {
     pub reverted: u64,
 }
 
+#[derive(Deserialize)]
+pub struct Tax {
+    pub id: u64,
+    #[serde(with = "date_time_format")]
+    pub datetime: NaiveDateTime,
+    pub amount: Decimal,
+    pub currency: String,
+    pub tax_rate: Decimal,
+    pub citizenship_code: Option<String>,
+    /// `transaction_id` of the cash in/out the tax is withheld from
+    pub income_transaction_id: u64,
+    // for metadata
+    pub transaction_id: u64,
+    pub comment: String,
+}
+
+#[derive(Deserialize)]
+pub struct CorporateActions {
+    pub detailed: Vec<CorporateAction>,
+}
+
+#[derive(Deserialize)]
+pub struct CorporateAction {
+    pub corporate_action_id: String,
+    pub isin: Option<String>,
+    pub ex_date: NaiveDate,
+    pub amount_per_one: Decimal,
+    pub q_on_ex_date: Decimal,
+    pub tax_amount: Decimal,
+    pub tax_currency: Option<String>,
+    pub external_tax: Decimal,
+    pub external_tax_currency: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct Commissions {
+    pub detailed: Vec<Commission>,
+}
+
+#[derive(Deserialize)]
+pub struct Commission {
+    #[serde(with = "date_time_format")]
+    pub datetime: NaiveDateTime,
+    pub sum: Decimal,
+    pub currency: String,
+    /// Kind of the commission, e.g. " За сделку:  290828464" for a commission of the trade
+    #[serde(rename = "type")]
+    pub commission_type: String,
+    pub comment: String,
+}
+
+impl Commission {
+    /// Id of the trade the commission is charged for
+    pub fn trade_id(&self) -> Option<u64> {
+        let (_, trade_id) = self.commission_type.split_once("За сделку:")?;
+        trade_id.trim().parse().ok()
+    }
+}
+
+/// Securities deposited to or withdrawn from the account, e.g. moved from an other broker
+#[derive(Deserialize)]
+pub struct SecurityInOut {
+    pub id: u64,
+    #[serde(with = "date_time_format")]
+    pub datetime: NaiveDateTime,
+    pub ticker: String,
+    pub isin: Option<String>,
+    /// Negative when securities leave the account
+    #[serde(alias = "q")]
+    pub quantity: Decimal,
+    #[serde(rename = "type")]
+    pub transfer_type: Option<String>,
+    /// Price the securities are accounted at, when carried over from the other broker
+    pub price: Option<Decimal>,
+    pub currency: Option<String>,
+    pub comment: Option<String>,
+}
+
+/// Transfers of securities as the report lists them next to trades, in the layout of the
+/// other sections. Totals per ticker only repeat the detailed rows.
+#[derive(Deserialize)]
+pub struct SecuritiesInOuts {
+    #[serde(default)]
+    pub detailed: Vec<SecurityInOut>,
+}
+
+/// Holdings and cash of the account at the end of the report period, stored as the snapshot
+/// of the broker
+#[derive(Deserialize)]
+pub struct AccountAtEnd {
+    #[serde(with = "date_time_format")]
+    pub date: NaiveDateTime,
+    pub account: Account,
+}
+
+#[derive(Deserialize)]
+pub struct Account {
+    pub positions_from_ts: PositionsFromTs,
+}
+
+#[derive(Deserialize)]
+pub struct PositionsFromTs {
+    pub ps: PositionsAndAccounts,
+}
+
+#[derive(Deserialize)]
+pub struct PositionsAndAccounts {
+    #[serde(default)]
+    pub acc: Vec<AccountBalance>,
+    #[serde(default)]
+    pub pos: Vec<Position>,
+}
+
+/// Cash balance in a single currency
+#[derive(Deserialize)]
+pub struct AccountBalance {
+    #[serde(rename = "s")]
+    pub sum: Decimal,
+    pub curr: String,
+}
+
+#[derive(Deserialize)]
+pub struct Position {
+    #[serde(rename = "i")]
+    pub ticker: String,
+    #[serde(rename = "q")]
+    pub quantity: Decimal,
+    pub curr: String,
+    #[serde(rename = "issue_nb")]
+    pub isin: Option<String>,
+}
+
 #[derive(Deserialize)]
 pub struct Report {
     pub trades: Trades,
     pub cash_flows: CashFlows,
-    pub cash_in_outs: Vec<CashInOut>
+    pub cash_in_outs: Vec<CashInOut>,
+    #[serde(default)]
+    pub taxes: Vec<Tax>,
+    pub corporate_actions: Option<CorporateActions>,
+    pub commissions: Option<Commissions>,
+    #[serde(default)]
+    pub securities_in_outs: Vec<SecurityInOut>,
+    pub in_outs_securities: Option<SecuritiesInOuts>,
+    pub account_at_end: Option<AccountAtEnd>,
+}
+
+impl Report {
+    /// Transfers of both sections, a transfer listed in each of them only once
+    pub fn security_in_outs(&self) -> impl Iterator<Item = &SecurityInOut> {
+        let mut ids = std::collections::HashSet::new();
+        self.securities_in_outs.iter()
+            .chain(self.in_outs_securities.iter().flat_map(|v| v.detailed.iter()))
+            .filter(move |v| ids.insert(v.id))
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -106,4 +259,6 @@ pub enum FreedomfinanceReportParsingError {
     IO { #[from] source: std::io::Error },
     #[error(transparent)]
     Serde { #[from] source: serde_json::Error },
+    #[error("Transfer {id} moves {quantity} of {ticker}, fractions of a security can't be stored yet")]
+    FractionalSecurityTransfer { id: u64, ticker: String, quantity: Decimal },
 }
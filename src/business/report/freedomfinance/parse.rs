@@ -1,13 +1,53 @@
+use futures_util::future::BoxFuture;
 use tokio::io::{AsyncRead, AsyncReadExt};
 
+use crate::business::{csv_mapping::model::CsvMappingProfile, model::BrokerType};
+use crate::business::report::{model::{AbstractReport, ReportProcessingError}, parser::ReportParser};
 
 
+pub struct FreedomfinanceReportParser;
+
+impl ReportParser for FreedomfinanceReportParser {
+    fn broker(&self) -> BrokerType {
+        BrokerType::Freedomfinance
+    }
+
+    fn name(&self) -> &'static str {
+        "Freedom Finance"
+    }
+
+    fn description(&self) -> &'static str {
+        "Broker report in JSON from the reports section of the Tradernet client area"
+    }
+
+    /// The report carries the account data as plain text too
+    fn is_suitable(&self, content: &[u8]) -> bool {
+        serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(content)
+            .is_ok_and(|report| report.contains_key("plainAccountInfoData") && report.contains_key("trades"))
+    }
+
+    fn parse<'a>(&'a self, content: &'a [u8], _profile: Option<&'a CsvMappingProfile>) -> BoxFuture<'a, Result<AbstractReport, ReportProcessingError>> {
+        Box::pin(async move {
+            parse_report(content).await
+                .map(|report| report.into())
+                .map_err(|err| self.parsing_error(err))
+        })
+    }
+}
+
 pub async fn parse_report<R: AsyncRead + Unpin>(
     mut reader: R,
 ) -> Result<super::model::Report, super::model::FreedomfinanceReportParsingError> {
     let mut buffer_for_entire_file = Vec::new();
     reader.read_to_end(&mut buffer_for_entire_file).await?;
     let parsed: super::model::Report = serde_json::from_slice(&buffer_for_entire_file)?;
+    if let Some(transfer) = parsed.security_in_outs().find(|v| !v.quantity.fract().is_zero()) {
+        return Err(super::model::FreedomfinanceReportParsingError::FractionalSecurityTransfer {
+            id: transfer.id,
+            ticker: transfer.ticker.clone(),
+            quantity: transfer.quantity,
+        });
+    }
     Ok(parsed)
 }
 
@@ -26,5 +66,19 @@ mod test {
         let report = parse_report(file).await.unwrap();
         assert_eq!(report.trades.detailed.len(), 23);
         assert_eq!(report.cash_flows.detailed.len(), 83);
+        assert_eq!(report.taxes.len(), 39);
+    }
+
+    #[tokio::test]
+    async fn rejects_fractional_security_transfers() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("testdata/freedomfinance_report.json");
+        let mut report: serde_json::Value = serde_json::from_slice(&tokio::fs::read(d).await.unwrap()).unwrap();
+        report["in_outs_securities"]["detailed"] = serde_json::json!([{
+            "id": 7, "datetime": "2023-03-01 10:00:00", "ticker": "VOO.US", "quantity": "0.5",
+        }]);
+
+        let result = parse_report(serde_json::to_vec(&report).unwrap().as_slice()).await;
+        assert!(matches!(result, Err(super::super::model::FreedomfinanceReportParsingError::FractionalSecurityTransfer { id: 7, .. })));
     }
 }
@@ -0,0 +1,331 @@
+use chrono::NaiveTime;
+use rust_decimal::prelude::ToPrimitive;
+use serde_json::json;
+
+use super::super::model::{AbstractReport, RowDiagnostic};
+use super::model::{Report, TradeSide};
+
+use crate::business::{currency_conversion::model::CurrencyConversion, fiscal_transaction::model::{FiscalTransaction, FiscalTransactionType}, model::{BrokerType, Money, OperationSource}, position_snapshot::model::SnapshotPosition, security_transfer::model::{SecurityTransfer, TransferDirection}, trade_operation::model::{TradeOperation, TradeOperationSide}};
+
+/// Corporate actions which only change the amount of securities without a cost
+const SPLIT_ACTION_TYPES: [&str; 2] = ["FS", "RS"];
+
+impl From<Report> for AbstractReport {
+    fn from(value: Report) -> Self {
+        // trades of a currency pair are conversions of money rather than purchases of securities
+        let (conversions, trades): (Vec<_>, Vec<_>) = value.trades.into_iter()
+            .partition(|v| v.asset_category == "CASH");
+        let conversion_commissions: Vec<FiscalTransaction> = conversions.iter()
+            .filter_map(conversion_commission)
+            .collect();
+
+        let corporate_action_proceeds: Vec<FiscalTransaction> = value.corporate_actions.iter()
+            .filter(|v| !v.proceeds.is_zero())
+            .map(corporate_action_proceeds)
+            .collect();
+
+        let mut trade_operations = Vec::new();
+        let mut diagnostics = Vec::new();
+        for trade in trades {
+            match trade_operation(trade) {
+                Ok(operation) => trade_operations.extend(operation),
+                Err(diagnostic) => diagnostics.push(diagnostic),
+            }
+        }
+
+        let mut security_transfers = Vec::new();
+        for action in value.corporate_actions {
+            match security_transfer(action) {
+                Ok(transfer) => security_transfers.extend(transfer),
+                Err(diagnostic) => diagnostics.push(diagnostic),
+            }
+        }
+
+        Self {
+            trade_operations,
+            fiscal_transactions: value.cash_transactions.into_iter()
+                .map(|v| v.into())
+                .chain(conversion_commissions)
+                .chain(corporate_action_proceeds)
+                .collect(),
+            security_transfers,
+            currency_conversions: conversions.into_iter().filter_map(currency_conversion).collect(),
+            position_snapshot: value.open_positions.into_iter().map(|v| v.into()).collect(),
+            diagnostics,
+            broker: BrokerType::Ibkr,
+        }
+    }
+}
+
+/// Only trades of whole stocks and ETFs can be stored, others are left out with a diagnostic
+fn trade_operation(value: super::model::Trade) -> Result<Option<TradeOperation>, RowDiagnostic> {
+    let diagnostic = |column: &str, raw: String, message: String| RowDiagnostic {
+        line: value.line,
+        section: "Trades".to_string(),
+        column: Some(column.to_string()),
+        value: Some(raw),
+        message,
+    };
+    if value.asset_category != "STK" {
+        return Err(diagnostic("assetCategory", value.asset_category.clone(),
+            format!("{} is not a stock or an ETF, which can't be stored yet", value.symbol)));
+    }
+    if !value.quantity.fract().is_zero() {
+        return Err(diagnostic("quantity", value.quantity.to_string(),
+            format!("{} has a fraction of a security, which can't be stored yet", value.symbol)));
+    }
+    let Some(quantity) = value.quantity.abs().to_i32().filter(|quantity| *quantity > 0) else { return Ok(None) };
+    let side = match value.side {
+        TradeSide::Buy => TradeOperationSide::Buy,
+        TradeSide::Sell => TradeOperationSide::Sell,
+    };
+    Ok(Some(TradeOperation {
+        operation_source: OperationSource::IbkrReport,
+        broker: Some(BrokerType::Ibkr),
+        external_id: Some(value.trade_id),
+        date_time: value.date_time,
+        reported_pnl: match side {
+            TradeOperationSide::Sell => Some(Money::new(value.fifo_pnl_realized, value.currency.clone())),
+            TradeOperationSide::Buy => None,
+        },
+        side,
+        instrument_symbol: value.symbol,
+        isin: value.isin,
+        price: Money::new(value.trade_price, value.currency.clone()),
+        quantity,
+        commission: Some(Money::new(value.ib_commission.abs(), value.ib_commission_currency))
+            .filter(|commission| !commission.amount.is_zero()),
+        order_id: value.ib_order_id,
+        summ: Money::new(value.proceeds.abs(), value.currency),
+        metadata: json!({
+            "account_id": value.account_id,
+            "description": value.description,
+            "exchange": value.exchange,
+        }),
+    }))
+}
+
+/// A trade of `EUR.USD` buys or sells the base currency EUR for the quote currency USD
+fn currency_conversion(value: super::model::Trade) -> Option<CurrencyConversion> {
+    let (base_currency, _) = value.symbol.split_once('.')?;
+    let base = Money::new(value.quantity.abs(), base_currency.to_string());
+    let quote = Money::new(value.proceeds.abs(), value.currency.clone());
+    let (sold, bought) = match value.side {
+        TradeSide::Buy => (quote, base),
+        TradeSide::Sell => (base, quote),
+    };
+    if sold.amount.is_zero() || bought.amount.is_zero() {
+        return None;
+    }
+    Some(CurrencyConversion {
+        operation_source: OperationSource::IbkrReport,
+        broker: Some(BrokerType::Ibkr),
+        external_id: Some(value.trade_id),
+        date_time: value.date_time,
+        rate: CurrencyConversion::implied_rate(&sold, &bought),
+        sold,
+        bought,
+        metadata: json!({
+            "account_id": value.account_id,
+            "symbol": value.symbol,
+            "trade_price": value.trade_price,
+            "order_id": value.ib_order_id,
+        }),
+    })
+}
+
+/// Commissions of conversions are not part of the converted money, so they are booked apart
+fn conversion_commission(value: &super::model::Trade) -> Option<FiscalTransaction> {
+    if value.ib_commission.is_zero() {
+        return None;
+    }
+    Some(FiscalTransaction {
+        operation_source: OperationSource::IbkrReport,
+        broker: Some(BrokerType::Ibkr),
+        external_id: Some(format!("commission-{}", value.trade_id)),
+        date_time: value.date_time,
+        symbol_id: None,
+        amount: Money::new(-value.ib_commission.abs(), value.ib_commission_currency.clone()),
+        operation_type: FiscalTransactionType::Commission,
+        commission: None,
+        metadata: json!({
+            "account_id": value.account_id,
+            "trade_id": value.trade_id,
+            "symbol": value.symbol,
+        }),
+        ex_date: None,
+        amount_per_share: None,
+    })
+}
+
+impl From<super::model::CashTransaction> for FiscalTransaction {
+    fn from(value: super::model::CashTransaction) -> Self {
+        Self {
+            operation_source: OperationSource::IbkrReport,
+            broker: Some(BrokerType::Ibkr),
+            external_id: Some(value.transaction_id),
+            date_time: value.date_time,
+            symbol_id: value.symbol,
+            amount: Money::new(value.amount, value.currency),
+            operation_type: match value.transaction_type.as_str() {
+                "Dividends" | "Payment In Lieu Of Dividends" => FiscalTransactionType::Dividend,
+                "Withholding Tax" | "871(m) Withholding" => FiscalTransactionType::Tax,
+                "Other Fees" | "Commission Adjustments" | "Advisor Fees" => FiscalTransactionType::Commission,
+                "Deposits/Withdrawals" | "Deposits & Withdrawals" => FiscalTransactionType::FundingWithdrawal,
+                "Broker Interest Received" | "Broker Interest Paid" | "Bond Interest Received" | "Bond Interest Paid" => FiscalTransactionType::Interest,
+                other => FiscalTransactionType::Unrecognized(other.to_string()),
+            },
+            commission: None,
+            metadata: json!({
+                "account_id": value.account_id,
+                "type": value.transaction_type,
+                "description": value.description,
+                "isin": value.isin,
+                "action_id": value.action_id,
+            }),
+            ex_date: None,
+            amount_per_share: None,
+        }
+    }
+}
+
+/// Corporate actions with a fraction of a security, like cash in lieu of one, are left out with a diagnostic
+fn security_transfer(value: super::model::CorporateAction) -> Result<Option<SecurityTransfer>, RowDiagnostic> {
+    if !value.quantity.fract().is_zero() {
+        return Err(RowDiagnostic {
+            line: value.line,
+            section: "CorporateActions".to_string(),
+            column: Some("quantity".to_string()),
+            value: Some(value.quantity.to_string()),
+            message: format!("{} has a fraction of a security, which can't be stored yet", value.symbol),
+        });
+    }
+    let Some(quantity) = value.quantity.abs().to_i32().filter(|quantity| *quantity > 0) else { return Ok(None) };
+    Ok(Some(SecurityTransfer {
+        operation_source: OperationSource::IbkrReport,
+        broker: Some(BrokerType::Ibkr),
+        external_id: Some(value.transaction_id),
+        date_time: value.date_time,
+        direction: if value.quantity.is_sign_negative() { TransferDirection::Out } else { TransferDirection::In },
+        is_split: SPLIT_ACTION_TYPES.contains(&value.action_type.as_str()),
+        instrument_symbol: value.symbol,
+        isin: value.isin,
+        quantity,
+        cost_basis: None,
+        metadata: json!({
+            "account_id": value.account_id,
+            "type": value.action_type,
+            "description": value.description,
+            "action_id": value.action_id,
+        }),
+    }))
+}
+
+/// Cash paid out by a corporate action, like a merger or a tender offer
+fn corporate_action_proceeds(value: &super::model::CorporateAction) -> FiscalTransaction {
+    FiscalTransaction {
+        operation_source: OperationSource::IbkrReport,
+        broker: Some(BrokerType::Ibkr),
+        external_id: Some(value.transaction_id.clone()),
+        date_time: value.date_time,
+        symbol_id: Some(value.symbol.clone()),
+        amount: Money::new(value.proceeds, value.currency.clone()),
+        operation_type: FiscalTransactionType::CorporateAction,
+        commission: None,
+        metadata: json!({
+            "account_id": value.account_id,
+            "type": value.action_type,
+            "description": value.description,
+            "action_id": value.action_id,
+        }),
+        ex_date: None,
+        amount_per_share: None,
+    }
+}
+
+impl From<super::model::OpenPosition> for SnapshotPosition {
+    fn from(value: super::model::OpenPosition) -> Self {
+        Self {
+            // positions are reported as of the end of the day
+            taken_at: value.report_date.date().and_time(NaiveTime::from_hms_opt(23, 59, 59).unwrap_or_default()),
+            instrument_symbol: Some(value.symbol),
+            isin: value.isin,
+            quantity: value.position,
+            currency: value.currency,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use rust_decimal::Decimal;
+    use tokio::fs::File;
+
+    use super::*;
+    use super::super::parse::parse_report;
+
+    #[tokio::test]
+    async fn maps_flex_query_report() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("testdata/ibkr_flex_report.xml");
+        let report: AbstractReport = parse_report(File::open(d).await.unwrap()).await.unwrap().into();
+
+        assert_eq!(report.trade_operations.len(), 3);
+        let bought: i32 = report.trade_operations.iter()
+            .filter(|t| t.side == TradeOperationSide::Buy)
+            .map(|t| t.quantity)
+            .sum();
+        assert_eq!(bought, 10);
+        let sale = report.trade_operations.iter().find(|t| t.side == TradeOperationSide::Sell).unwrap();
+        assert_eq!(sale.summ, Money::new(Decimal::from(1350), "USD".to_string()));
+        assert_eq!(sale.commission, Some(Money::new(Decimal::from(1), "USD".to_string())));
+
+        let types: Vec<String> = report.fiscal_transactions.iter().map(|t| t.operation_type.to_string()).collect();
+        assert_eq!(types, vec!["FundingWithdrawal", "Dividend", "Tax", "Commission", "Interest", "Commission"]);
+
+        assert_eq!(report.currency_conversions.len(), 1);
+        let conversion = &report.currency_conversions[0];
+        assert_eq!(conversion.sold, Money::new(Decimal::from(1095), "USD".to_string()));
+        assert_eq!(conversion.bought, Money::new(Decimal::from(1000), "EUR".to_string()));
+
+        assert_eq!(report.security_transfers.len(), 1);
+        assert!(report.security_transfers[0].is_split && report.security_transfers[0].quantity == 18);
+
+        assert_eq!(report.position_snapshot.len(), 2);
+        assert_eq!(report.position_snapshot[0].taken_at.to_string(), "2024-03-29 23:59:59");
+    }
+
+    #[tokio::test]
+    async fn leaves_out_unsupported_trades_with_diagnostics() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("testdata/ibkr_flex_report.xml");
+        let content = tokio::fs::read_to_string(d).await.unwrap().replacen("</Trades>", concat!(
+            r#"<Trade accountId="U1234567" currency="USD" assetCategory="OPT" symbol="VOO 240621C00450000" tradeID="611900001" dateTime="20240301;100000" buySell="BUY" quantity="1" tradePrice="5" proceeds="-500" ibCommission="-1" fifoPnlRealized="0" levelOfDetail="EXECUTION" />"#, "\n",
+            r#"<Trade accountId="U1234567" currency="USD" assetCategory="STK" symbol="VOO" tradeID="611900002" dateTime="20240301;100000" buySell="BUY" quantity="0.5" tradePrice="450" proceeds="-225" ibCommission="-1" fifoPnlRealized="0" levelOfDetail="EXECUTION" />"#, "\n",
+            "</Trades>",
+        ), 1);
+        let report: AbstractReport = parse_report(content.as_bytes()).await.unwrap().into();
+
+        assert_eq!(report.trade_operations.len(), 3);
+        let diagnostics: Vec<(usize, Option<String>)> = report.diagnostics.iter().map(|d| (d.line, d.column.clone())).collect();
+        assert_eq!(diagnostics, vec![(11, Some("assetCategory".to_string())), (12, Some("quantity".to_string()))]);
+    }
+
+    #[tokio::test]
+    async fn leaves_out_fractional_corporate_actions_with_diagnostics() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("testdata/ibkr_flex_report.xml");
+        let content = tokio::fs::read_to_string(d).await.unwrap().replacen("</CorporateActions>", concat!(
+            r#"<CorporateAction accountId="U1234567" currency="USD" assetCategory="STK" symbol="VOO" description="VOO SPLIT 3 FOR 2" dateTime="20240607;202500" quantity="1.5" proceeds="0" type="FS" transactionID="800000002" levelOfDetail="DETAIL" />"#, "\n",
+            "</CorporateActions>",
+        ), 1);
+        let report: AbstractReport = parse_report(content.as_bytes()).await.unwrap().into();
+
+        assert_eq!(report.security_transfers.len(), 1);
+        let diagnostics: Vec<(usize, &str, Option<&str>, Option<&str>)> = report.diagnostics.iter()
+            .map(|d| (d.line, d.section.as_str(), d.column.as_deref(), d.value.as_deref()))
+            .collect();
+        assert_eq!(diagnostics, vec![(22, "CorporateActions", Some("quantity"), Some("1.5"))]);
+    }
+}
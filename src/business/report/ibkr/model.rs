@@ -0,0 +1,87 @@
+use chrono::NaiveDateTime;
+use rust_decimal::Decimal;
+
+/// Sections of a Flex Query statement which are imported. Every record of a Flex Query is
+/// an XML element whose fields are attributes, as in `<Trade symbol="VOO" quantity="2" .../>`.
+pub struct Report {
+    pub trades: Vec<Trade>,
+    pub cash_transactions: Vec<CashTransaction>,
+    pub corporate_actions: Vec<CorporateAction>,
+    pub open_positions: Vec<OpenPosition>,
+}
+
+#[derive(PartialEq)]
+pub enum TradeSide {
+    Buy,
+    Sell,
+}
+
+pub struct Trade {
+    pub line: usize,
+    pub account_id: String,
+    pub asset_category: String, // like "STK", or "CASH" for a conversion of currencies
+    pub symbol: String,
+    pub isin: Option<String>,
+    pub description: String,
+    pub currency: String,
+    pub trade_id: String,
+    pub ib_order_id: Option<String>,
+    pub date_time: NaiveDateTime,
+    pub side: TradeSide,
+    pub quantity: Decimal, // negative when sold
+    pub trade_price: Decimal,
+    pub proceeds: Decimal, // negative when bought
+    pub ib_commission: Decimal, // negative
+    pub ib_commission_currency: String,
+    pub fifo_pnl_realized: Decimal,
+    pub exchange: Option<String>,
+}
+
+pub struct CashTransaction {
+    pub account_id: String,
+    pub transaction_type: String, // like "Dividends" or "Withholding Tax"
+    pub transaction_id: String,
+    pub symbol: Option<String>,
+    pub isin: Option<String>,
+    pub description: String,
+    pub currency: String,
+    pub amount: Decimal,
+    pub date_time: NaiveDateTime,
+    pub action_id: Option<String>,
+}
+
+pub struct CorporateAction {
+    pub line: usize,
+    pub account_id: String,
+    pub action_type: String, // like "FS" for a forward split
+    pub transaction_id: String,
+    pub action_id: Option<String>,
+    pub symbol: String,
+    pub isin: Option<String>,
+    pub description: String,
+    pub currency: String,
+    pub quantity: Decimal,
+    pub proceeds: Decimal,
+    pub date_time: NaiveDateTime,
+}
+
+pub struct OpenPosition {
+    pub account_id: String,
+    pub symbol: String,
+    pub isin: Option<String>,
+    pub currency: String,
+    pub position: Decimal,
+    pub report_date: NaiveDateTime,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum IbkrReportParsingError {
+    #[error(transparent)]
+    IO { #[from] source: std::io::Error },
+    #[error(transparent)]
+    Xml { #[from] source: quick_xml::Error },
+    #[error("This file is not a Flex Query statement")]
+    NotFlexQuery,
+    #[error("Malformed {record} record: {reason}")]
+    MalformedRecord { record: String, reason: String },
+}
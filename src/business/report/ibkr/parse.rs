@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use futures_util::future::BoxFuture;
+use quick_xml::events::BytesStart;
+use quick_xml::{events::Event, Reader};
+use rust_decimal::Decimal;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::business::{csv_mapping::model::CsvMappingProfile, model::BrokerType};
+use crate::business::report::{model::{AbstractReport, ReportProcessingError}, parser::ReportParser};
+use super::model::{CashTransaction, CorporateAction, IbkrReportParsingError, OpenPosition, Report, Trade, TradeSide};
+
+/// Records which Flex Query repeats with less detail, like a summary of lots
+const DETAILED_LEVELS: [&str; 2] = ["EXECUTION", "DETAIL"];
+
+pub struct IbkrReportParser;
+
+impl ReportParser for IbkrReportParser {
+    fn broker(&self) -> BrokerType {
+        BrokerType::Ibkr
+    }
+
+    fn name(&self) -> &'static str {
+        "Interactive Brokers"
+    }
+
+    fn description(&self) -> &'static str {
+        "Activity Flex Query in XML with trades, cash transactions, corporate actions and open positions"
+    }
+
+    /// The first element of the document is one of a Flex Query
+    fn is_suitable(&self, content: &[u8]) -> bool {
+        let mut reader = Reader::from_reader(content);
+        let mut buffer = Vec::new();
+        loop {
+            match reader.read_event_into(&mut buffer) {
+                Ok(Event::Start(element)) | Ok(Event::Empty(element)) => {
+                    return matches!(element.local_name().as_ref(), b"FlexQueryResponse" | b"FlexStatements" | b"FlexStatement");
+                },
+                Ok(Event::Eof) | Err(_) => return false,
+                _ => buffer.clear(),
+            }
+        }
+    }
+
+    fn parse<'a>(&'a self, content: &'a [u8], _profile: Option<&'a CsvMappingProfile>) -> BoxFuture<'a, Result<AbstractReport, ReportProcessingError>> {
+        Box::pin(async move {
+            parse_report(content).await
+                .map(|report| report.into())
+                .map_err(|err| self.parsing_error(err))
+        })
+    }
+}
+
+pub async fn parse_report<R: AsyncRead + Unpin>(
+    mut reader: R,
+) -> Result<Report, IbkrReportParsingError> {
+    let mut content = Vec::new();
+    reader.read_to_end(&mut content).await?;
+    parse_report_xml(&content)
+}
+
+fn parse_report_xml(content: &[u8]) -> Result<Report, IbkrReportParsingError> {
+    let mut reader = Reader::from_reader(content);
+    reader.trim_text(true);
+
+    let mut report = Report {
+        trades: Vec::new(),
+        cash_transactions: Vec::new(),
+        corporate_actions: Vec::new(),
+        open_positions: Vec::new(),
+    };
+    let mut is_flex_query = false;
+    let mut buffer = Vec::new();
+    loop {
+        let line = line_at(content, reader.buffer_position());
+        match reader.read_event_into(&mut buffer)? {
+            Event::Start(element) | Event::Empty(element) => match element.local_name().as_ref() {
+                b"FlexQueryResponse" | b"FlexStatement" => is_flex_query = true,
+                b"Trade" => {
+                    let attributes = Attributes::read("Trade", &element, line)?;
+                    if attributes.is_detailed() {
+                        report.trades.push(attributes.trade()?);
+                    }
+                },
+                b"CashTransaction" => {
+                    let attributes = Attributes::read("CashTransaction", &element, line)?;
+                    if attributes.is_detailed() {
+                        report.cash_transactions.push(attributes.cash_transaction()?);
+                    }
+                },
+                b"CorporateAction" => {
+                    let attributes = Attributes::read("CorporateAction", &element, line)?;
+                    if attributes.is_detailed() {
+                        report.corporate_actions.push(attributes.corporate_action()?);
+                    }
+                },
+                b"OpenPosition" => {
+                    let attributes = Attributes::read("OpenPosition", &element, line)?;
+                    // positions are repeated for every lot they were bought in
+                    if attributes.optional("levelOfDetail").is_none_or(|level| level == "SUMMARY") {
+                        report.open_positions.push(attributes.open_position()?);
+                    }
+                },
+                _ => {},
+            },
+            Event::Eof => break,
+            _ => {},
+        }
+        buffer.clear();
+    }
+
+    if !is_flex_query {
+        return Err(IbkrReportParsingError::NotFlexQuery);
+    }
+    Ok(report)
+}
+
+/// Line of the file, counted from 1, of the element which follows the position in `content`
+fn line_at(content: &[u8], position: usize) -> usize {
+    let start = content[position..].iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .map_or(content.len(), |offset| position + offset);
+    content[..start].iter().filter(|b| **b == b'\n').count() + 1
+}
+
+/// Attributes of a single record, where absent and empty values are the same
+struct Attributes {
+    record: &'static str,
+    line: usize,
+    values: HashMap<String, String>,
+}
+
+impl Attributes {
+    fn read(record: &'static str, element: &BytesStart, line: usize) -> Result<Self, IbkrReportParsingError> {
+        let mut values = HashMap::new();
+        for attribute in element.attributes() {
+            let attribute = attribute.map_err(quick_xml::Error::from)?;
+            let key = String::from_utf8_lossy(attribute.key.local_name().as_ref()).into_owned();
+            values.insert(key, attribute.unescape_value()?.into_owned());
+        }
+        Ok(Attributes { record, line, values })
+    }
+
+    fn malformed(&self, reason: String) -> IbkrReportParsingError {
+        IbkrReportParsingError::MalformedRecord { record: self.record.to_string(), reason }
+    }
+
+    fn is_detailed(&self) -> bool {
+        self.optional("levelOfDetail").is_none_or(|level| DETAILED_LEVELS.contains(&level.as_str()))
+    }
+
+    fn optional(&self, name: &str) -> Option<String> {
+        self.values.get(name)
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty() && *v != "--")
+            .map(|v| v.to_string())
+    }
+
+    fn text(&self, name: &str) -> Result<String, IbkrReportParsingError> {
+        self.optional(name).ok_or_else(|| self.malformed(format!("'{name}' is missing")))
+    }
+
+    fn decimal(&self, name: &str) -> Result<Decimal, IbkrReportParsingError> {
+        let Some(value) = self.optional(name) else { return Ok(Decimal::ZERO) };
+        Decimal::from_str(&value)
+            .or_else(|_| Decimal::from_scientific(&value))
+            .map_err(|_| self.malformed(format!("'{name}' is not a number: '{value}'")))
+    }
+
+    /// Flex Query formats dates and times as configured in the query, the default being `20240131;093000`
+    fn date_time(&self, name: &str) -> Result<NaiveDateTime, IbkrReportParsingError> {
+        let value = self.text(name)?;
+        let normalized = value.replace(", ", " ").replace(';', " ");
+        const DATE_TIME_FORMATS: [&str; 4] = ["%Y%m%d %H%M%S", "%Y-%m-%d %H:%M:%S", "%Y%m%d %H:%M:%S", "%m/%d/%Y %H:%M:%S"];
+        const DATE_FORMATS: [&str; 3] = ["%Y%m%d", "%Y-%m-%d", "%m/%d/%Y"];
+        DATE_TIME_FORMATS.iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(&normalized, format).ok())
+            .or_else(|| DATE_FORMATS.iter()
+                .find_map(|format| NaiveDate::parse_from_str(&normalized, format).ok())
+                .map(|date| date.and_time(NaiveTime::MIN)))
+            .ok_or_else(|| self.malformed(format!("'{name}' is not a date: '{value}'")))
+    }
+
+    /// Time of an operation, which is only a date in some sections of a statement
+    fn operation_date_time(&self) -> Result<NaiveDateTime, IbkrReportParsingError> {
+        if self.optional("dateTime").is_some() {
+            self.date_time("dateTime")
+        } else {
+            self.date_time("reportDate")
+        }
+    }
+
+    fn trade(&self) -> Result<Trade, IbkrReportParsingError> {
+        let buy_sell = self.text("buySell")?;
+        let side = match buy_sell.as_str() {
+            v if v.starts_with("BUY") => TradeSide::Buy,
+            v if v.starts_with("SELL") => TradeSide::Sell,
+            _ => return Err(self.malformed(format!("unknown side '{buy_sell}'"))),
+        };
+        Ok(Trade {
+            line: self.line,
+            account_id: self.optional("accountId").unwrap_or_default(),
+            asset_category: self.text("assetCategory")?,
+            symbol: self.text("symbol")?,
+            isin: self.optional("isin"),
+            description: self.optional("description").unwrap_or_default(),
+            currency: self.text("currency")?,
+            trade_id: self.text("tradeID")?,
+            ib_order_id: self.optional("ibOrderID"),
+            date_time: self.operation_date_time()?,
+            side,
+            quantity: self.decimal("quantity")?,
+            trade_price: self.decimal("tradePrice")?,
+            proceeds: self.decimal("proceeds")?,
+            ib_commission: self.decimal("ibCommission")?,
+            ib_commission_currency: self.optional("ibCommissionCurrency").map_or_else(|| self.text("currency"), Ok)?,
+            fifo_pnl_realized: self.decimal("fifoPnlRealized")?,
+            exchange: self.optional("exchange"),
+        })
+    }
+
+    fn cash_transaction(&self) -> Result<CashTransaction, IbkrReportParsingError> {
+        Ok(CashTransaction {
+            account_id: self.optional("accountId").unwrap_or_default(),
+            transaction_type: self.text("type")?,
+            transaction_id: self.text("transactionID")?,
+            symbol: self.optional("symbol"),
+            isin: self.optional("isin"),
+            description: self.optional("description").unwrap_or_default(),
+            currency: self.text("currency")?,
+            amount: self.decimal("amount")?,
+            date_time: self.operation_date_time()?,
+            action_id: self.optional("actionID"),
+        })
+    }
+
+    fn corporate_action(&self) -> Result<CorporateAction, IbkrReportParsingError> {
+        Ok(CorporateAction {
+            line: self.line,
+            account_id: self.optional("accountId").unwrap_or_default(),
+            action_type: self.text("type")?,
+            transaction_id: self.text("transactionID")?,
+            action_id: self.optional("actionID"),
+            symbol: self.text("symbol")?,
+            isin: self.optional("isin"),
+            description: self.optional("description").unwrap_or_default(),
+            currency: self.text("currency")?,
+            quantity: self.decimal("quantity")?,
+            proceeds: self.decimal("proceeds")?,
+            date_time: self.operation_date_time()?,
+        })
+    }
+
+    fn open_position(&self) -> Result<OpenPosition, IbkrReportParsingError> {
+        Ok(OpenPosition {
+            account_id: self.optional("accountId").unwrap_or_default(),
+            symbol: self.text("symbol")?,
+            isin: self.optional("isin"),
+            currency: self.text("currency")?,
+            position: self.decimal("position")?,
+            report_date: self.date_time("reportDate")?,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use tokio::fs::File;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn parses_report() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("testdata/ibkr_flex_report.xml");
+        let file = File::open(d).await.unwrap();
+
+        let report = parse_report(file).await.unwrap();
+        assert_eq!(report.trades.len(), 4); // order summaries are skipped
+        assert_eq!(report.cash_transactions.len(), 5);
+        assert_eq!(report.corporate_actions.len(), 1);
+        assert_eq!(report.open_positions.len(), 2); // lots are skipped
+
+        let sale = report.trades.iter().find(|t| t.trade_id == "611223344").unwrap();
+        assert!(sale.side == TradeSide::Sell);
+        assert_eq!(sale.quantity, Decimal::from(-3));
+        assert_eq!(sale.date_time.to_string(), "2024-02-20 15:31:07");
+    }
+
+    #[test]
+    fn rejects_other_xml() {
+        let content = r#"<gesmes:Envelope><Cube><Cube time="2024-02-23"/></Cube></gesmes:Envelope>"#;
+        assert!(matches!(parse_report_xml(content.as_bytes()), Err(IbkrReportParsingError::NotFlexQuery)));
+
+        let malformed = r#"<FlexQueryResponse><FlexStatements><FlexStatement><Trades>
+            <Trade assetCategory="STK" symbol="VOO" currency="USD" tradeID="1" dateTime="20240115;093512" buySell="BUY" quantity="many"/>
+        </Trades></FlexStatement></FlexStatements></FlexQueryResponse>"#;
+        assert!(matches!(parse_report_xml(malformed.as_bytes()), Err(IbkrReportParsingError::MalformedRecord { .. })));
+    }
+}
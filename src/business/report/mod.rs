@@ -1,6 +1,11 @@
+pub mod custom_csv;
+pub mod degiro;
 pub mod exante;
 pub mod freedomfinance;
+pub mod ibkr;
+pub mod trading212;
 
+pub mod parser;
 pub mod model;
 pub mod repository;
 pub mod resource;
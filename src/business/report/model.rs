@@ -1,6 +1,9 @@
 
 
 
+use std::collections::HashMap;
+
+use async_graphql::SimpleObject;
 use diesel::{Insertable};
 
 
@@ -8,29 +11,88 @@ use serde::{Deserialize, Serialize};
 
 use uuid::Uuid;
 
-use crate::{business::{fiscal_transaction::model::FiscalTransaction, model::BrokerType, trade_operation::model::TradeOperation}, database::schema::{self}};
+use crate::{business::{currency_conversion::model::CurrencyConversion, fiscal_transaction::model::FiscalTransaction, model::BrokerType, position_snapshot::model::SnapshotPosition, security_transfer::model::SecurityTransfer, trade_operation::model::TradeOperation}, database::schema::{self}};
 
 
 #[derive(Serialize)]
 pub struct AbstractReport {
     pub broker: BrokerType,
     pub trade_operations: Vec<TradeOperation>,
-    pub fiscal_transactions: Vec<FiscalTransaction>
+    pub fiscal_transactions: Vec<FiscalTransaction>,
+    pub security_transfers: Vec<SecurityTransfer>,
+    pub currency_conversions: Vec<CurrencyConversion>,
+    /// Positions and cash balances as the broker sees them at the end of the report
+    pub position_snapshot: Vec<SnapshotPosition>,
+    /// Rows which can't be read, and are left out of the report
+    pub diagnostics: Vec<RowDiagnostic>,
+}
+
+/// A row of a report which can't be read
+#[derive(Serialize, SimpleObject, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RowDiagnostic {
+    /// Line of the file, counted from 1
+    pub line: usize,
+    /// Part of the report with the row, like trades or transactions
+    pub section: String,
+    pub column: Option<String>,
+    /// Raw value of the column
+    pub value: Option<String>,
+    pub message: String,
+}
+
+impl std::fmt::Display for RowDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {} of {}", self.line, self.section)?;
+        if let Some(column) = &self.column {
+            write!(f, ", column '{column}'")?;
+        }
+        if let Some(value) = &self.value {
+            write!(f, " with '{value}'")?;
+        }
+        write!(f, ": {}", self.message)
+    }
 }
 
+/// Identifiers of rows of reports without ids of their own, made of the contents of a row
+/// and a counter of rows with the same contents
+#[derive(Default)]
+pub struct ExternalIds {
+    seen: HashMap<String, usize>,
+}
+
+impl ExternalIds {
+    pub fn next(&mut self, key: String) -> String {
+        let count = self.seen.entry(key.clone()).or_default();
+        *count += 1;
+        if *count == 1 { key } else { format!("{key}/{count}") }
+    }
+}
 
 pub struct ReportProcessingResult {
     pub id: Uuid,
+    pub broker: BrokerType,
     pub fiscal_transactions: usize,
-    pub trade_operations: usize
+    pub trade_operations: usize,
+    pub security_transfers: usize,
+    pub currency_conversions: usize,
+    pub diagnostics: Vec<RowDiagnostic>,
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum ReportProcessingError {
     #[error(transparent)]
-    ExanteReportParsingError { #[from] source: super::exante::model::ExanteReportParsingError },
-    #[error(transparent)]
-    FreedomfinanceReportParsingError { #[from] source: super::freedomfinance::model::FreedomfinanceReportParsingError },
+    IO { #[from] source: std::io::Error },
+    #[error("{format} report can't be read: {source}")]
+    ParsingError { format: &'static str, source: Box<dyn std::error::Error + Send + Sync> },
+    #[error("Reports of other brokers are read with a CSV mapping profile of the portfolio")]
+    MissingCsvMappingProfile,
+    #[error("Format of the report is not recognized, please choose the brokerage it was exported from")]
+    UnknownReportFormat,
+    #[error("Reports of {0} can't be imported")]
+    UnsupportedBroker(BrokerType),
+    #[error("{} rows of the report can't be read, like the {}. Rows which can be read may be imported without them.", diagnostics.len(), diagnostics[0])]
+    InvalidRows { diagnostics: Vec<RowDiagnostic> },
 }
 
 // --- orm model
@@ -0,0 +1,107 @@
+use futures_util::future::BoxFuture;
+
+use crate::business::{csv_mapping::model::CsvMappingProfile, model::BrokerType};
+
+use super::model::{AbstractReport, ReportProcessingError};
+
+/// A format of reports of a broker. Importing reports of a new broker takes a module with
+/// an implementation of this trait, registered in `ReportParsers`.
+pub trait ReportParser: Send + Sync {
+    fn broker(&self) -> BrokerType;
+
+    /// Name of the format as it's shown to users
+    fn name(&self) -> &'static str;
+
+    /// Which files of the broker are read, and where to get them
+    fn description(&self) -> &'static str;
+
+    /// Whether the content looks like a report of this format, to detect it on upload
+    fn is_suitable(&self, content: &[u8]) -> bool;
+
+    /// Reports are read with a CSV mapping profile, so they can't be detected
+    fn requires_csv_mapping_profile(&self) -> bool {
+        false
+    }
+
+    fn parse<'a>(&'a self, content: &'a [u8], profile: Option<&'a CsvMappingProfile>) -> BoxFuture<'a, Result<AbstractReport, ReportProcessingError>>;
+
+    /// Wraps an error of the own parser of the format
+    fn parsing_error<E>(&self, source: E) -> ReportProcessingError
+    where
+        Self: Sized,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        ReportProcessingError::ParsingError { format: self.name(), source: Box::new(source) }
+    }
+}
+
+pub struct ReportParsers {
+    parsers: Vec<Box<dyn ReportParser>>,
+}
+
+impl Default for ReportParsers {
+    /// All supported formats, in the order they are tried when detecting one
+    fn default() -> Self {
+        ReportParsers {
+            parsers: vec![
+                Box::new(super::freedomfinance::parse::FreedomfinanceReportParser),
+                Box::new(super::ibkr::parse::IbkrReportParser),
+                Box::new(super::exante::parse::ExanteReportParser),
+                Box::new(super::trading212::parse::Trading212ReportParser),
+                Box::new(super::degiro::parse::DegiroReportParser),
+                Box::new(super::custom_csv::parse::CustomCsvReportParser),
+            ],
+        }
+    }
+}
+
+impl ReportParsers {
+    pub fn iter(&self) -> impl Iterator<Item = &dyn ReportParser> {
+        self.parsers.iter().map(|parser| parser.as_ref())
+    }
+
+    pub fn get(&self, broker: BrokerType) -> Option<&dyn ReportParser> {
+        self.iter().find(|parser| parser.broker() == broker)
+    }
+
+    pub fn detect(&self, content: &[u8]) -> Option<&dyn ReportParser> {
+        self.iter()
+            .filter(|parser| !parser.requires_csv_mapping_profile())
+            .find(|parser| parser.is_suitable(content))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detects_broker_of_every_testdata_report() {
+        let parsers = ReportParsers::default();
+        let testdata = [
+            ("exante_small_report.csv", BrokerType::Exante),
+            ("exante_empty_report.csv", BrokerType::Exante),
+            ("freedomfinance_report.json", BrokerType::Freedomfinance),
+            ("ibkr_flex_report.xml", BrokerType::Ibkr),
+            ("degiro_transactions.csv", BrokerType::Degiro),
+            ("degiro_account_de.csv", BrokerType::Degiro),
+            ("trading212_history.csv", BrokerType::Trading212),
+        ];
+        for (name, broker) in testdata {
+            let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            d.push("testdata");
+            d.push(name);
+            let content = std::fs::read(d).unwrap();
+            assert_eq!(parsers.detect(&content).map(|parser| parser.broker()), Some(broker), "{name}");
+        }
+        assert!(parsers.detect(b"Date;Amount\n01.01.2024;10\n").is_none());
+    }
+
+    #[tokio::test]
+    async fn wraps_errors_of_parsers() {
+        let parsers = ReportParsers::default();
+        let error = parsers.get(BrokerType::Trading212).unwrap().parse(b"Date,Amount\n", None).await.err().unwrap();
+        assert!(matches!(error, ReportProcessingError::ParsingError { format: "Trading 212", .. }));
+    }
+}
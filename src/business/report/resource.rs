@@ -1,34 +1,91 @@
 
-use async_graphql::{Context, SimpleObject, Object, Upload, UploadValue};
+use async_graphql::{Context, ErrorExtensions, SimpleObject, Object, Upload, UploadValue};
+use chrono::NaiveDateTime;
+use rust_decimal::Decimal;
 use tokio_util::compat::FuturesAsyncReadCompatExt;
 use serde::Serialize;
 use uuid::Uuid;
 
-use crate::business::model::BrokerType;
+use crate::business::csv_mapping::model::CsvMappingProfile;
+use crate::business::currency_conversion::model::{CurrencyConversion, SelectCurrencyConversion};
+use crate::business::model::{BrokerType, FieldChange, Money};
+use crate::business::security_transfer::model::{SecurityTransfer, SelectSecurityTransfer, TransferDirection};
 use crate::business::portfolio::security::is_portfolio_owner;
+use crate::business::user_transaction::resource::UserTransaction;
+use crate::web::errors::DescriptiveError;
 use crate::web::graphql::{get_claims, get_state};
-use super::model::ReportProcessingResult;
-use super::service::process_report;
+use crate::ApplicationState;
+use super::model::{ReportProcessingResult, RowDiagnostic};
+use super::parser::ReportParser;
+use super::service::{preview_report, process_report};
 
 
 
+#[derive(Default)]
+pub struct ReportQuery;
+#[Object(rename_fields="camelCase", rename_args="camelCase")]
+impl ReportQuery {
+    /// Formats of reports which can be uploaded, in the order they are tried when the brokerage is not given
+    async fn supported_report_formats(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<ReportFormat>> {
+        let state = get_state(ctx)?;
+        Ok(state.report_parsers.iter().map(ReportFormat::from).collect())
+    }
+}
+
 #[derive(Default)]
 pub struct ReportMutation;
 #[Object(rename_fields="camelCase", rename_args="camelCase")]
 impl ReportMutation {
-    async fn upload_report(&self, ctx: &Context<'_>, portfolio_id: Uuid, brokerage: BrokerType, upload: Upload) -> async_graphql::Result<ReportUploadResult> {
+    /// Without a brokerage, it's detected from the content of the file.
+    /// Files of brokers without an own importer are uploaded as `OTHER` and read with a CSV mapping profile of the portfolio.
+    /// A report with rows which can't be read is refused, unless `skipInvalidRows` imports the rest of it.
+    async fn upload_report(
+        &self,
+        ctx: &Context<'_>,
+        portfolio_id: Uuid,
+        brokerage: Option<BrokerType>,
+        csv_mapping_profile_id: Option<Uuid>,
+        skip_invalid_rows: Option<bool>,
+        upload: Upload
+    ) -> async_graphql::Result<ReportUploadResult> {
         let upload_value: UploadValue = upload.value(ctx)?;
         let claims = get_claims(ctx)?;
         let state = get_state(ctx)?;
 
         is_portfolio_owner(state, claims.sub, portfolio_id)?;
 
+        let csv_mapping_profile = find_csv_mapping_profile(state, portfolio_id, csv_mapping_profile_id)?;
+
         let original_filename = upload_value.filename.clone();
         let async_read = upload_value.into_async_read();
         let async_read = FuturesAsyncReadCompatExt::compat(async_read);
-        let parsed_report = process_report(state, portfolio_id, brokerage, async_read, original_filename).await?;
+        // the error of rows which can't be read lists them in its extensions
+        let parsed_report = process_report(state, portfolio_id, brokerage, csv_mapping_profile, async_read, original_filename, skip_invalid_rows.unwrap_or_default())
+            .await
+            .map_err(|err| err.extend())?;
         Ok(ReportUploadResult::from(parsed_report))
     }
+
+    /// Read a report the way `uploadReport` does and show what it would import, without storing anything
+    async fn preview_report(&self, ctx: &Context<'_>, portfolio_id: Uuid, brokerage: Option<BrokerType>, csv_mapping_profile_id: Option<Uuid>, upload: Upload) -> async_graphql::Result<ReportPreview> {
+        let upload_value: UploadValue = upload.value(ctx)?;
+        let claims = get_claims(ctx)?;
+        let state = get_state(ctx)?;
+
+        is_portfolio_owner(state, claims.sub, portfolio_id)?;
+        let csv_mapping_profile = find_csv_mapping_profile(state, portfolio_id, csv_mapping_profile_id)?;
+
+        let async_read = FuturesAsyncReadCompatExt::compat(upload_value.into_async_read());
+        Ok(preview_report(state, portfolio_id, brokerage, csv_mapping_profile, async_read).await?)
+    }
+}
+
+fn find_csv_mapping_profile(state: &ApplicationState, portfolio_id: Uuid, id: Option<Uuid>) -> async_graphql::Result<Option<CsvMappingProfile>> {
+    let Some(id) = id else { return Ok(None) };
+    let profile = state.repository.find_csv_mapping_profile(id)?
+        .filter(|profile| profile.portfolio_id == portfolio_id)
+        .ok_or(DescriptiveError::NotFound { resource: "csv mapping profile".to_string() })?;
+    Ok(Some(CsvMappingProfile::try_from(profile)?))
 }
 
 
@@ -39,14 +96,163 @@ impl ReportMutation {
 #[serde(rename_all = "camelCase")]
 pub struct ReportUploadResult {
     pub id: Uuid,
+    /// Brokerage the report was read as, which is the detected one when none was given
+    pub brokerage: BrokerType,
     pub fiscal_transactions: usize,
-    pub trade_operations: usize
+    pub trade_operations: usize,
+    pub security_transfers: usize,
+    pub currency_conversions: usize,
+    /// Rows which can't be read, and were not imported
+    pub diagnostics: Vec<RowDiagnostic>,
+}
+
+#[derive(Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportFormat {
+    pub brokerage: BrokerType,
+    pub name: String,
+    pub description: String,
+    /// Reports are read with a CSV mapping profile of the portfolio, and their format is never detected
+    pub requires_csv_mapping_profile: bool,
+}
+
+impl From<&dyn ReportParser> for ReportFormat {
+    fn from(value: &dyn ReportParser) -> Self {
+        ReportFormat {
+            brokerage: value.broker(),
+            name: value.name().to_string(),
+            description: value.description().to_string(),
+            requires_csv_mapping_profile: value.requires_csv_mapping_profile(),
+        }
+    }
+}
+
+/// What an upload would import into a portfolio
+#[derive(Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportPreview {
+    /// Brokerage the report is read as, which is the detected one when none was given
+    pub brokerage: BrokerType,
+    pub new_trade_operations: Vec<UserTransaction>,
+    pub updated_trade_operations: Vec<UpdatedOperation>,
+    pub new_fiscal_transactions: Vec<UserTransaction>,
+    pub updated_fiscal_transactions: Vec<UpdatedOperation>,
+    pub new_security_transfers: Vec<PreviewedSecurityTransfer>,
+    pub updated_security_transfers: Vec<UpdatedSecurityTransfer>,
+    pub new_currency_conversions: Vec<PreviewedCurrencyConversion>,
+    pub updated_currency_conversions: Vec<UpdatedCurrencyConversion>,
+    /// Operations, transfers and conversions which are imported already as they are in the report
+    pub unchanged_operations: usize,
+    /// Rows which can't be read, and would not be imported
+    pub diagnostics: Vec<RowDiagnostic>,
+    /// Types of transactions which are not recognized, and are imported as UNRECOGNIZED
+    pub unrecognized_types: Vec<String>,
+}
+
+/// An imported operation, with the fields which the upload changes
+#[derive(Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdatedOperation {
+    pub operation: UserTransaction,
+    pub changes: Vec<FieldChange>,
+}
+
+/// Securities moved in or out of the portfolio by an upload
+#[derive(Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewedSecurityTransfer {
+    /// Id of the imported transfer, absent for a new one
+    pub security_transfer_id: Option<Uuid>,
+    pub brokerage: Option<BrokerType>,
+    pub date_time: NaiveDateTime,
+    pub direction: TransferDirection,
+    pub ticker: String,
+    pub isin: Option<String>,
+    pub quantity: i32,
+    pub cost_basis: Option<Money>,
+    pub is_split: bool,
+}
+
+impl From<SecurityTransfer> for PreviewedSecurityTransfer {
+    fn from(value: SecurityTransfer) -> Self {
+        Self {
+            security_transfer_id: None,
+            brokerage: value.broker,
+            date_time: value.date_time,
+            direction: value.direction,
+            ticker: value.instrument_symbol,
+            isin: value.isin,
+            quantity: value.quantity,
+            cost_basis: value.cost_basis,
+            is_split: value.is_split,
+        }
+    }
+}
+
+impl From<SelectSecurityTransfer> for PreviewedSecurityTransfer {
+    fn from(value: SelectSecurityTransfer) -> Self {
+        Self {
+            security_transfer_id: Some(value.id),
+            ..value.i.into()
+        }
+    }
+}
+
+/// An imported transfer, with the fields which the upload changes
+#[derive(Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdatedSecurityTransfer {
+    pub security_transfer: PreviewedSecurityTransfer,
+    pub changes: Vec<FieldChange>,
+}
+
+/// Money exchanged by an upload
+#[derive(Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewedCurrencyConversion {
+    /// Id of the imported conversion, absent for a new one
+    pub currency_conversion_id: Option<Uuid>,
+    pub brokerage: Option<BrokerType>,
+    pub date_time: NaiveDateTime,
+    pub sold: Money,
+    pub bought: Money,
+    pub rate: Decimal,
+}
+
+impl From<CurrencyConversion> for PreviewedCurrencyConversion {
+    fn from(value: CurrencyConversion) -> Self {
+        Self {
+            currency_conversion_id: None,
+            brokerage: value.broker,
+            date_time: value.date_time,
+            sold: value.sold,
+            bought: value.bought,
+            rate: value.rate,
+        }
+    }
+}
+
+impl From<SelectCurrencyConversion> for PreviewedCurrencyConversion {
+    fn from(value: SelectCurrencyConversion) -> Self {
+        Self {
+            currency_conversion_id: Some(value.id),
+            ..value.i.into()
+        }
+    }
+}
+
+/// An imported conversion, with the fields which the upload changes
+#[derive(Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdatedCurrencyConversion {
+    pub currency_conversion: PreviewedCurrencyConversion,
+    pub changes: Vec<FieldChange>,
 }
 
 impl From<ReportProcessingResult> for ReportUploadResult {
     fn from(value: ReportProcessingResult) -> Self {
-        let ReportProcessingResult { id, fiscal_transactions, trade_operations } = value;
-        ReportUploadResult { id, fiscal_transactions, trade_operations }
+        let ReportProcessingResult { id, broker, fiscal_transactions, trade_operations, security_transfers, currency_conversions, diagnostics } = value;
+        ReportUploadResult { id, brokerage: broker, fiscal_transactions, trade_operations, security_transfers, currency_conversions, diagnostics }
     }
 }
 
@@ -1,27 +1,45 @@
+use std::collections::{BTreeSet, HashMap};
+
+use tokio::io::AsyncReadExt;
 use uuid::Uuid;
 
-use crate::{business::{fiscal_transaction::model::{FiscalTransactionType, InsertFiscalTransaction}, model::BrokerType, report::model::InsertReportUpload, trade_operation::model::InsertTradeOperation}, web::errors::DescriptiveError, ApplicationState};
+use crate::{business::{csv_mapping::model::CsvMappingProfile, currency_conversion::model::{InsertCurrencyConversion, SelectCurrencyConversion}, fiscal_transaction::model::{FiscalTransactionType, InsertFiscalTransaction, SelectFiscalTransaction}, lot::service::recalculate_lot_assignments, model::{BrokerType, OperationSource}, position_snapshot::model::InsertSnapshotPosition, report::model::InsertReportUpload, security_transfer::model::{InsertSecurityTransfer, SelectSecurityTransfer}, trade_operation::model::{InsertTradeOperation, SelectTradeOperation}}, web::errors::DescriptiveError, ApplicationState};
 
 use super::model::{AbstractReport, ReportProcessingError, ReportProcessingResult};
+use super::resource::{ReportPreview, UpdatedCurrencyConversion, UpdatedOperation, UpdatedSecurityTransfer};
+
+/// Reads an upload with the parser of the broker, or of the detected format when none is given
+async fn parse_upload<R: tokio::io::AsyncRead + Unpin>(
+    state: &ApplicationState,
+    broker: Option<BrokerType>,
+    csv_mapping_profile: Option<&CsvMappingProfile>,
+    mut reader: R,
+) -> Result<(BrokerType, AbstractReport), DescriptiveError> {
+    let mut content = Vec::new();
+    reader.read_to_end(&mut content).await.map_err(ReportProcessingError::from)?;
+    // a mapping profile is only given for reports of other brokers
+    let broker = broker.or(csv_mapping_profile.map(|_| BrokerType::Other));
+    let parser = match broker {
+        Some(broker) => state.report_parsers.get(broker).ok_or(ReportProcessingError::UnsupportedBroker(broker))?,
+        None => state.report_parsers.detect(&content).ok_or(ReportProcessingError::UnknownReportFormat)?,
+    };
+    Ok((parser.broker(), parser.parse(&content, csv_mapping_profile).await?))
+}
 
 pub async fn process_report<R: tokio::io::AsyncRead + Unpin>(
     state: &ApplicationState,
     portfolio_id: Uuid,
-    broker: BrokerType,
+    broker: Option<BrokerType>,
+    csv_mapping_profile: Option<CsvMappingProfile>,
     reader: R,
-    original_filename: String
+    original_filename: String,
+    skip_invalid_rows: bool,
 ) -> Result<ReportProcessingResult, DescriptiveError> {
-    let parsed: Result<AbstractReport, ReportProcessingError> = match broker {
-        BrokerType::Exante => super::exante::parse::parse_report(reader)
-            .await
-            .map(|ok| ok.into())
-            .map_err(|err| err.into()),
-        BrokerType::Freedomfinance => super::freedomfinance::parse::parse_report(reader)
-            .await
-            .map(|ok| ok.into())
-            .map_err(|err| err.into()),
-    };
-    let AbstractReport { fiscal_transactions: transactions, trade_operations, .. } = parsed?;
+    let (broker, report) = parse_upload(state, broker, csv_mapping_profile.as_ref(), reader).await?;
+    let AbstractReport { fiscal_transactions: transactions, trade_operations, security_transfers, currency_conversions, position_snapshot, diagnostics, .. } = report;
+    if !diagnostics.is_empty() && !skip_invalid_rows {
+        return Err(ReportProcessingError::InvalidRows { diagnostics }.into());
+    }
 
 
     for each in transactions.iter() {
@@ -50,9 +68,162 @@ pub async fn process_report<R: tokio::io::AsyncRead + Unpin>(
         }).collect()
     )?;
 
+    let inserted_security_transfers = state.repository.create_security_transfers(
+        security_transfers.into_iter().map(|st| InsertSecurityTransfer {
+            portfolio_id,
+            report_upload_id: Some(report_upload_id),
+            security_transfer: st,
+        }).collect()
+    )?;
+
+    let inserted_currency_conversions = state.repository.create_currency_conversions(
+        currency_conversions.into_iter().map(|cc| InsertCurrencyConversion {
+            portfolio_id,
+            report_upload_id: Some(report_upload_id),
+            currency_conversion: cc,
+        }).collect()
+    )?;
+
+    state.repository.create_snapshot_positions(
+        position_snapshot.into_iter().map(|position| InsertSnapshotPosition {
+            portfolio_id,
+            report_upload_id,
+            broker,
+            position,
+        }).collect()
+    )?;
+
+    recalculate_lot_assignments(state, portfolio_id)?;
+
     Ok(ReportProcessingResult {
         id: report_upload_id,
+        broker,
         fiscal_transactions: inserted_transactions,
         trade_operations: inserted_trade_opertaions,
+        security_transfers: inserted_security_transfers,
+        currency_conversions: inserted_currency_conversions,
+        diagnostics,
     })
 }
+
+/// What an upload would import into the portfolio, without storing anything. Operations which
+/// were imported already are matched by the key the import upserts them on: the portfolio,
+/// their source and external id.
+pub async fn preview_report<R: tokio::io::AsyncRead + Unpin>(
+    state: &ApplicationState,
+    portfolio_id: Uuid,
+    broker: Option<BrokerType>,
+    csv_mapping_profile: Option<CsvMappingProfile>,
+    reader: R,
+) -> Result<ReportPreview, DescriptiveError> {
+    let (broker, report) = parse_upload(state, broker, csv_mapping_profile.as_ref(), reader).await?;
+
+    let external_ids: Vec<String> = report.trade_operations.iter().filter_map(|t| t.external_id.clone()).collect();
+    let mut imported_trade_operations: HashMap<(OperationSource, String), SelectTradeOperation> = state.repository
+        .list_trade_operations_by_external_ids(portfolio_id, &external_ids)?
+        .into_iter()
+        .filter_map(|t| Some(((t.i.operation_source, t.i.external_id.clone()?), t)))
+        .collect();
+    let external_ids: Vec<String> = report.fiscal_transactions.iter().filter_map(|t| t.external_id.clone()).collect();
+    let mut imported_fiscal_transactions: HashMap<(OperationSource, String), SelectFiscalTransaction> = state.repository
+        .list_fiscal_transactions_by_external_ids(portfolio_id, &external_ids)?
+        .into_iter()
+        .filter_map(|t| Some(((t.i.operation_source, t.i.external_id.clone()?), t)))
+        .collect();
+    let external_ids: Vec<String> = report.security_transfers.iter().filter_map(|t| t.external_id.clone()).collect();
+    let mut imported_security_transfers: HashMap<(OperationSource, String), SelectSecurityTransfer> = state.repository
+        .list_security_transfers_by_external_ids(portfolio_id, &external_ids)?
+        .into_iter()
+        .filter_map(|t| Some(((t.i.operation_source, t.i.external_id.clone()?), t)))
+        .collect();
+    let external_ids: Vec<String> = report.currency_conversions.iter().filter_map(|c| c.external_id.clone()).collect();
+    let mut imported_currency_conversions: HashMap<(OperationSource, String), SelectCurrencyConversion> = state.repository
+        .list_currency_conversions_by_external_ids(portfolio_id, &external_ids)?
+        .into_iter()
+        .filter_map(|c| Some(((c.i.operation_source, c.i.external_id.clone()?), c)))
+        .collect();
+
+    let mut preview = ReportPreview {
+        brokerage: broker,
+        new_trade_operations: vec![],
+        updated_trade_operations: vec![],
+        new_fiscal_transactions: vec![],
+        updated_fiscal_transactions: vec![],
+        new_security_transfers: vec![],
+        updated_security_transfers: vec![],
+        new_currency_conversions: vec![],
+        updated_currency_conversions: vec![],
+        unchanged_operations: 0,
+        diagnostics: report.diagnostics,
+        unrecognized_types: report.fiscal_transactions.iter()
+            .filter_map(|t| match &t.operation_type {
+                FiscalTransactionType::Unrecognized(variant) => Some(variant.clone()),
+                _ => None,
+            })
+            .collect::<BTreeSet<String>>()
+            .into_iter()
+            .collect(),
+    };
+
+    for trade_operation in report.trade_operations {
+        let imported = trade_operation.external_id.clone()
+            .and_then(|external_id| imported_trade_operations.remove(&(trade_operation.operation_source, external_id)));
+        match imported {
+            None => preview.new_trade_operations.push(trade_operation.into()),
+            Some(imported) => {
+                let changes = trade_operation.changes_from(&imported.i);
+                if changes.is_empty() {
+                    preview.unchanged_operations += 1;
+                } else {
+                    preview.updated_trade_operations.push(UpdatedOperation { operation: imported.into(), changes });
+                }
+            },
+        }
+    }
+    for fiscal_transaction in report.fiscal_transactions {
+        let imported = fiscal_transaction.external_id.clone()
+            .and_then(|external_id| imported_fiscal_transactions.remove(&(fiscal_transaction.operation_source, external_id)));
+        match imported {
+            None => preview.new_fiscal_transactions.push(fiscal_transaction.into()),
+            Some(imported) => {
+                let changes = fiscal_transaction.changes_from(&imported.i);
+                if changes.is_empty() {
+                    preview.unchanged_operations += 1;
+                } else {
+                    preview.updated_fiscal_transactions.push(UpdatedOperation { operation: imported.into(), changes });
+                }
+            },
+        }
+    }
+    for security_transfer in report.security_transfers {
+        let imported = security_transfer.external_id.clone()
+            .and_then(|external_id| imported_security_transfers.remove(&(security_transfer.operation_source, external_id)));
+        match imported {
+            None => preview.new_security_transfers.push(security_transfer.into()),
+            Some(imported) => {
+                let changes = security_transfer.changes_from(&imported.i);
+                if changes.is_empty() {
+                    preview.unchanged_operations += 1;
+                } else {
+                    preview.updated_security_transfers.push(UpdatedSecurityTransfer { security_transfer: imported.into(), changes });
+                }
+            },
+        }
+    }
+    for currency_conversion in report.currency_conversions {
+        let imported = currency_conversion.external_id.clone()
+            .and_then(|external_id| imported_currency_conversions.remove(&(currency_conversion.operation_source, external_id)));
+        match imported {
+            None => preview.new_currency_conversions.push(currency_conversion.into()),
+            Some(imported) => {
+                let changes = currency_conversion.changes_from(&imported.i);
+                if changes.is_empty() {
+                    preview.unchanged_operations += 1;
+                } else {
+                    preview.updated_currency_conversions.push(UpdatedCurrencyConversion { currency_conversion: imported.into(), changes });
+                }
+            },
+        }
+    }
+    Ok(preview)
+}
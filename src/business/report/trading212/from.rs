@@ -0,0 +1,343 @@
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use serde_json::json;
+
+use super::super::model::{AbstractReport, RowDiagnostic};
+use super::model::{ActionKind, Report, Transaction};
+
+use crate::business::{currency_conversion::model::CurrencyConversion, fiscal_transaction::model::{FiscalTransaction, FiscalTransactionType}, model::{BrokerType, Money, OperationSource}, security_transfer::model::{SecurityTransfer, TransferDirection}, trade_operation::model::{TradeOperation, TradeOperationSide}};
+
+impl From<Report> for AbstractReport {
+    fn from(value: Report) -> Self {
+        let mut report = AbstractReport {
+            broker: BrokerType::Trading212,
+            trade_operations: vec![],
+            fiscal_transactions: vec![],
+            security_transfers: vec![],
+            currency_conversions: vec![],
+            position_snapshot: vec![],
+            diagnostics: vec![],
+        };
+        for transaction in value.transactions {
+            let imported = match transaction.kind() {
+                ActionKind::Buy | ActionKind::Sell => add_trade(&mut report, transaction),
+                ActionKind::Dividend => add_dividend(&mut report, transaction),
+                ActionKind::CurrencyConversion => add_currency_conversion(&mut report, transaction),
+                ActionKind::SplitOpen | ActionKind::SplitClose => {
+                    report.security_transfers.extend(split(transaction));
+                    Ok(())
+                },
+                ActionKind::Deposit | ActionKind::Withdrawal | ActionKind::Interest | ActionKind::Other => {
+                    fiscal_transaction(transaction).map(|transaction| report.fiscal_transactions.push(transaction))
+                },
+            };
+            if let Err(diagnostic) = imported {
+                report.diagnostics.push(diagnostic);
+            }
+        }
+        report
+    }
+}
+
+impl Transaction {
+    /// Dividends have no id in the export, so it's made of what identifies them
+    fn external_id(&self) -> String {
+        self.id.clone().unwrap_or_else(|| format!("{}/{}/{}", self.action, self.time, self.ticker.as_deref().unwrap_or_default()))
+    }
+
+    fn total_money(&self) -> Option<Money> {
+        Some(Money::new(self.total?, self.total_currency.clone()?))
+    }
+
+    /// The row can't be imported because of the value of the `column`
+    fn diagnostic(&self, column: &str, value: Option<String>, message: String) -> RowDiagnostic {
+        RowDiagnostic {
+            line: self.line,
+            section: "Transactions".to_string(),
+            column: Some(column.to_string()),
+            value,
+            message,
+        }
+    }
+
+    fn missing(&self, column: &str) -> RowDiagnostic {
+        self.diagnostic(column, None, format!("{} at {} has an empty \"{}\"", self.action, self.time, column))
+    }
+
+    /// Rows with money in them can't be imported without the total
+    fn missing_total(&self) -> RowDiagnostic {
+        self.missing(if self.total.is_none() { "Total" } else { "Currency (Total)" })
+    }
+
+    /// Dividend per share in the currency of the total. The exchange rate of a row is the number of
+    /// units of the currency of the price per share for one of the total.
+    fn amount_per_share(&self) -> Option<Decimal> {
+        let amount = self.price_per_share?;
+        if self.price_currency == self.total_currency {
+            return Some(amount);
+        }
+        let rate = self.exchange_rate.as_deref()?.parse::<Decimal>().ok().filter(|rate| !rate.is_zero())?;
+        Some((amount / rate).round_dp(10).normalize())
+    }
+
+    /// Sum of fees charged in the currency of the total, which is the currency of the account
+    fn fees_in_total_currency(&self) -> Decimal {
+        let currency = self.total_currency.as_ref();
+        [
+            (self.charge_amount, &self.charge_amount_currency),
+            (self.stamp_duty, &self.stamp_duty_currency),
+            (self.conversion_fee, &self.conversion_fee_currency),
+        ].into_iter()
+            .filter(|(_, fee_currency)| fee_currency.is_none() || fee_currency.as_ref() == currency)
+            .filter_map(|(amount, _)| amount)
+            .map(|amount| amount.abs())
+            .sum()
+    }
+
+    fn metadata(&self) -> serde_json::Value {
+        json!({
+            "action": self.action,
+            "name": self.name,
+            "isin": self.isin,
+            "exchange_rate": self.exchange_rate,
+            "notes": self.notes,
+            "charge_amount": self.charge_amount,
+            "stamp_duty": self.stamp_duty,
+            "conversion_fee": self.conversion_fee,
+        })
+    }
+}
+
+/// A trade in a currency other than the one of the account is paid with an implicit conversion,
+/// which is recorded as a conversion of its own
+fn add_trade(report: &mut AbstractReport, value: Transaction) -> Result<(), RowDiagnostic> {
+    let ticker = value.ticker.clone().ok_or_else(|| value.missing("Ticker"))?;
+    let shares = value.shares.ok_or_else(|| value.missing("No. of shares"))?;
+    let price = value.price_per_share.ok_or_else(|| value.missing("Price / share"))?;
+    let price_currency = value.price_currency.clone().ok_or_else(|| value.missing("Currency (Price / share)"))?;
+    let total = value.total_money().ok_or_else(|| value.missing_total())?;
+    let quantity = shares.abs().to_i32().filter(|quantity| *quantity > 0).ok_or_else(|| value.diagnostic(
+        "No. of shares", Some(shares.to_string()), format!("quantity of {ticker} is zero or too large"),
+    ))?;
+    let side = if value.kind() == ActionKind::Sell { TradeOperationSide::Sell } else { TradeOperationSide::Buy };
+    let fees = value.fees_in_total_currency();
+    let summ = Money::new(shares.abs() * price, price_currency.clone());
+
+    if price_currency != total.currency {
+        let (sold, bought) = match side {
+            TradeOperationSide::Buy => (Money::new(total.amount.abs() - fees, total.currency.clone()), summ.clone()),
+            TradeOperationSide::Sell => (summ.clone(), Money::new(total.amount.abs() + fees, total.currency.clone())),
+        };
+        report.currency_conversions.push(CurrencyConversion {
+            operation_source: OperationSource::Trading212Report,
+            broker: Some(BrokerType::Trading212),
+            external_id: Some(format!("fx/{}", value.external_id())),
+            date_time: value.time,
+            rate: CurrencyConversion::implied_rate(&sold, &bought),
+            sold,
+            bought,
+            metadata: json!({ "exchange_rate": value.exchange_rate, "trade_id": value.id }),
+        });
+    }
+
+    report.trade_operations.push(TradeOperation {
+        operation_source: OperationSource::Trading212Report,
+        broker: Some(BrokerType::Trading212),
+        external_id: Some(value.external_id()),
+        date_time: value.time,
+        reported_pnl: match side {
+            TradeOperationSide::Sell => value.result.map(|result| Money::new(result, total.currency.clone())),
+            TradeOperationSide::Buy => None,
+        },
+        side,
+        instrument_symbol: ticker,
+        isin: value.isin.clone(),
+        price: Money::new(price, price_currency),
+        quantity,
+        commission: Some(Money::new(fees, total.currency)).filter(|commission| !commission.amount.is_zero()),
+        order_id: None,
+        summ,
+        metadata: value.metadata(),
+    });
+    Ok(())
+}
+
+/// The total of a dividend is net of the withholding tax, which is split off when both are
+/// in the same currency
+fn add_dividend(report: &mut AbstractReport, value: Transaction) -> Result<(), RowDiagnostic> {
+    let mut total = value.total_money().ok_or_else(|| value.missing_total())?;
+    let tax = value.withholding_tax
+        .filter(|tax| !tax.is_zero() && value.withholding_tax_currency.as_ref() == Some(&total.currency))
+        .map(|tax| Money::new(-tax.abs(), total.currency.clone()));
+    if let Some(tax) = tax.as_ref() {
+        total.amount -= tax.amount;
+    }
+
+    let mut metadata = value.metadata();
+    if let Some(metadata) = metadata.as_object_mut() {
+        metadata.insert("withholding_tax".to_string(), json!(value.withholding_tax));
+        metadata.insert("withholding_tax_currency".to_string(), json!(value.withholding_tax_currency));
+        metadata.insert("shares".to_string(), json!(value.shares));
+    }
+    let transaction = |external_id: String, amount: Money, operation_type: FiscalTransactionType| FiscalTransaction {
+        operation_source: OperationSource::Trading212Report,
+        broker: Some(BrokerType::Trading212),
+        external_id: Some(external_id),
+        date_time: value.time,
+        symbol_id: value.ticker.clone(),
+        amount,
+        operation_type,
+        commission: None,
+        metadata: metadata.clone(),
+        ex_date: None,
+        amount_per_share: None,
+    };
+    report.fiscal_transactions.push(FiscalTransaction {
+        amount_per_share: value.amount_per_share(),
+        ..transaction(value.external_id(), total, FiscalTransactionType::Dividend)
+    });
+    if let Some(tax) = tax {
+        report.fiscal_transactions.push(transaction(format!("tax/{}", value.external_id()), tax, FiscalTransactionType::Tax));
+    }
+    Ok(())
+}
+
+fn add_currency_conversion(report: &mut AbstractReport, value: Transaction) -> Result<(), RowDiagnostic> {
+    let (Some(from), Some(from_currency), Some(to), Some(to_currency)) = (
+        value.conversion_from_amount, value.conversion_from_currency.clone(),
+        value.conversion_to_amount, value.conversion_to_currency.clone(),
+    ) else {
+        // older exports only have the total of a conversion, which is not enough to know both sides
+        report.fiscal_transactions.push(fiscal_transaction(value)?);
+        return Ok(());
+    };
+    let sold = Money::new(from.abs(), from_currency);
+    let bought = Money::new(to.abs(), to_currency);
+    if let Some(fee) = value.conversion_fee.filter(|fee| !fee.is_zero()) {
+        let currency = value.conversion_fee_currency.clone().unwrap_or_else(|| sold.currency.clone());
+        report.fiscal_transactions.push(FiscalTransaction {
+            operation_source: OperationSource::Trading212Report,
+            broker: Some(BrokerType::Trading212),
+            external_id: Some(format!("fee/{}", value.external_id())),
+            date_time: value.time,
+            symbol_id: None,
+            amount: Money::new(-fee.abs(), currency),
+            operation_type: FiscalTransactionType::Commission,
+            commission: None,
+            metadata: value.metadata(),
+            ex_date: None,
+            amount_per_share: None,
+        });
+    }
+    report.currency_conversions.push(CurrencyConversion {
+        operation_source: OperationSource::Trading212Report,
+        broker: Some(BrokerType::Trading212),
+        external_id: Some(value.external_id()),
+        date_time: value.time,
+        rate: CurrencyConversion::implied_rate(&sold, &bought),
+        sold,
+        bought,
+        metadata: value.metadata(),
+    });
+    Ok(())
+}
+
+fn split(value: Transaction) -> Option<SecurityTransfer> {
+    let quantity = value.shares?.abs().to_i32().filter(|quantity| *quantity > 0)?;
+    Some(SecurityTransfer {
+        operation_source: OperationSource::Trading212Report,
+        broker: Some(BrokerType::Trading212),
+        external_id: Some(value.external_id()),
+        date_time: value.time,
+        direction: if value.kind() == ActionKind::SplitClose { TransferDirection::Out } else { TransferDirection::In },
+        is_split: true,
+        instrument_symbol: value.ticker.clone()?,
+        isin: value.isin.clone(),
+        quantity,
+        cost_basis: None,
+        metadata: value.metadata(),
+    })
+}
+
+fn fiscal_transaction(value: Transaction) -> Result<FiscalTransaction, RowDiagnostic> {
+    let mut amount = value.total_money().ok_or_else(|| value.missing_total())?;
+    let kind = value.kind();
+    if kind == ActionKind::Withdrawal {
+        amount.amount = -amount.amount.abs();
+    }
+    Ok(FiscalTransaction {
+        operation_source: OperationSource::Trading212Report,
+        broker: Some(BrokerType::Trading212),
+        external_id: Some(value.external_id()),
+        date_time: value.time,
+        symbol_id: value.ticker.clone(),
+        amount,
+        operation_type: match kind {
+            ActionKind::Deposit | ActionKind::Withdrawal => FiscalTransactionType::FundingWithdrawal,
+            ActionKind::Interest => FiscalTransactionType::Interest,
+            _ => FiscalTransactionType::Unrecognized(value.action.clone()),
+        },
+        commission: None,
+        metadata: value.metadata(),
+        ex_date: None,
+        amount_per_share: None,
+    })
+}
+
+
+#[cfg(test)]
+mod test {
+    use tokio::fs::File;
+
+    use super::*;
+    use super::super::parse::parse_report;
+
+    #[tokio::test]
+    async fn maps_history_with_implicit_conversions() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("testdata/trading212_history.csv");
+        let report: AbstractReport = parse_report(File::open(d).await.unwrap()).await.unwrap().into();
+
+        assert_eq!(report.trade_operations.len(), 3);
+        let purchase = &report.trade_operations[0];
+        assert_eq!(purchase.summ, Money::new(Decimal::new(174200, 2), "USD".to_string()));
+        assert_eq!(purchase.commission, Some(Money::new(Decimal::new(159, 2), "EUR".to_string())));
+
+        // the explicit conversion and the implicit ones of both trades in dollars
+        let conversions: Vec<(Decimal, Decimal)> = report.currency_conversions.iter()
+            .map(|c| (c.sold.amount, c.bought.amount))
+            .collect();
+        assert_eq!(conversions, vec![
+            (Decimal::new(159087, 2), Decimal::new(174200, 2)),
+            (Decimal::new(50000, 2), Decimal::new(54725, 2)),
+            (Decimal::new(90024, 2), Decimal::new(84057, 2)),
+        ]);
+
+        let types: Vec<String> = report.fiscal_transactions.iter().map(|t| t.operation_type.to_string()).collect();
+        assert_eq!(types, vec!["FundingWithdrawal", "Commission", "Dividend", "Interest", "FundingWithdrawal"]);
+        let dividend = &report.fiscal_transactions[2];
+        assert_eq!(dividend.amount, Money::new(Decimal::new(486, 2), "EUR".to_string())); // tax was withheld in dollars
+        assert_eq!(dividend.amount_per_share, Some(Decimal::new(14285714286, 10))); // 1.54 USD at 1.0780
+        assert_eq!(report.fiscal_transactions[4].amount.amount, Decimal::from(-1000));
+    }
+
+    #[tokio::test]
+    async fn reports_rows_it_cant_import() {
+        let content = "Action,Time,ISIN,Ticker,Name,No. of shares,Price / share,Currency (Price / share),Exchange rate,Total,Currency (Total),ID\n\
+            Market buy,2024-01-08 15:30:12,US9229083632,VOO,Vanguard S&P 500,0,435.50,USD,1.0950,0,EUR,EOF0000001\n\
+            Market buy,2024-01-08 15:31:12,US9229083632,,Vanguard S&P 500,1,435.50,USD,1.0950,397.72,EUR,EOF0000002\n\
+            Dividend (Ordinary),2024-02-01 12:00:00,US9229083632,VOO,Vanguard S&P 500,4,1.3,USD,1.0950,,EUR,\n\
+            Deposit,2024-01-05 10:00:00,,,,,,,,2000.00,,EOF0000003\n";
+        let report: AbstractReport = parse_report(content.as_bytes()).await.unwrap().into();
+
+        assert!(report.trade_operations.is_empty() && report.fiscal_transactions.is_empty());
+        let diagnostics: Vec<(usize, Option<&str>, Option<&str>)> = report.diagnostics.iter()
+            .map(|d| (d.line, d.column.as_deref(), d.value.as_deref()))
+            .collect();
+        assert_eq!(diagnostics, vec![
+            (2, Some("No. of shares"), Some("0")),
+            (3, Some("Ticker"), None),
+            (4, Some("Total"), None),
+            (5, Some("Currency (Total)"), None),
+        ]);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod from;
+pub mod model;
+pub mod parse;
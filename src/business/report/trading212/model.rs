@@ -0,0 +1,137 @@
+use chrono::NaiveDateTime;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+/// A row of the history export. Columns of fees and taxes are only present when the
+/// exported period has any of them.
+#[derive(Deserialize)]
+pub struct Transaction {
+    /// Line of the row in the file
+    #[serde(skip)]
+    pub line: usize,
+    #[serde(rename = "Action")]
+    pub action: String,
+    #[serde(rename = "Time", with = "time_format")]
+    pub time: NaiveDateTime,
+    #[serde(rename = "ISIN")]
+    pub isin: Option<String>,
+    #[serde(rename = "Ticker")]
+    pub ticker: Option<String>,
+    #[serde(rename = "Name")]
+    pub name: Option<String>,
+    #[serde(rename = "No. of shares")]
+    pub shares: Option<Decimal>,
+    #[serde(rename = "Price / share")]
+    pub price_per_share: Option<Decimal>,
+    #[serde(rename = "Currency (Price / share)")]
+    pub price_currency: Option<String>,
+    #[serde(rename = "Exchange rate")]
+    pub exchange_rate: Option<String>, // a number or "Not available"
+    #[serde(rename = "Result")]
+    pub result: Option<Decimal>,
+    #[serde(rename = "Total")]
+    pub total: Option<Decimal>,
+    #[serde(rename = "Currency (Total)")]
+    pub total_currency: Option<String>,
+    #[serde(rename = "Withholding tax")]
+    pub withholding_tax: Option<Decimal>,
+    #[serde(rename = "Currency (Withholding tax)")]
+    pub withholding_tax_currency: Option<String>,
+    #[serde(rename = "Charge amount")]
+    pub charge_amount: Option<Decimal>,
+    #[serde(rename = "Currency (Charge amount)")]
+    pub charge_amount_currency: Option<String>,
+    #[serde(rename = "Stamp duty reserve tax")]
+    pub stamp_duty: Option<Decimal>,
+    #[serde(rename = "Currency (Stamp duty reserve tax)")]
+    pub stamp_duty_currency: Option<String>,
+    #[serde(rename = "Currency conversion fee")]
+    pub conversion_fee: Option<Decimal>,
+    #[serde(rename = "Currency (Currency conversion fee)")]
+    pub conversion_fee_currency: Option<String>,
+    #[serde(rename = "Currency conversion from amount")]
+    pub conversion_from_amount: Option<Decimal>,
+    #[serde(rename = "Currency (Currency conversion from amount)")]
+    pub conversion_from_currency: Option<String>,
+    #[serde(rename = "Currency conversion to amount")]
+    pub conversion_to_amount: Option<Decimal>,
+    #[serde(rename = "Currency (Currency conversion to amount)")]
+    pub conversion_to_currency: Option<String>,
+    #[serde(rename = "Notes")]
+    pub notes: Option<String>,
+    #[serde(rename = "ID")]
+    pub id: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ActionKind {
+    Buy,
+    Sell,
+    Dividend,
+    Deposit,
+    Withdrawal,
+    Interest,
+    CurrencyConversion,
+    /// New shares of a split
+    SplitOpen,
+    /// Shares replaced by a split
+    SplitClose,
+    Other,
+}
+
+impl Transaction {
+    /// Kind of the `action`, like "Market buy" or "Dividend (Ordinary)"
+    pub fn kind(&self) -> ActionKind {
+        let action = self.action.to_lowercase();
+        match action.as_str() {
+            v if v.ends_with(" buy") => ActionKind::Buy,
+            v if v.ends_with(" sell") => ActionKind::Sell,
+            v if v.starts_with("dividend") => ActionKind::Dividend,
+            "deposit" => ActionKind::Deposit,
+            "withdrawal" => ActionKind::Withdrawal,
+            "interest on cash" | "lending interest" => ActionKind::Interest,
+            "currency conversion" => ActionKind::CurrencyConversion,
+            "stock split open" => ActionKind::SplitOpen,
+            "stock split close" => ActionKind::SplitClose,
+            _ => ActionKind::Other,
+        }
+    }
+
+    /// Actions whose number of shares becomes a quantity of a trade or a transfer
+    pub fn moves_shares(&self) -> bool {
+        matches!(self.kind(), ActionKind::Buy | ActionKind::Sell | ActionKind::SplitOpen | ActionKind::SplitClose)
+    }
+}
+
+pub struct Report {
+    pub transactions: Vec<Transaction>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Trading212ReportParsingError {
+    #[error(transparent)]
+    IO { #[from] source: std::io::Error },
+    #[error(transparent)]
+    Csv { #[from] source: csv::Error },
+    #[error("This file starts with an unknown header, expected the one of a Trading 212 history export")]
+    UnknownHeader,
+    #[error("{action} of {shares} shares of {ticker} at {time} has a fraction of a share, which can't be stored yet")]
+    FractionalQuantity { action: String, ticker: String, shares: Decimal, time: NaiveDateTime },
+}
+
+mod time_format {
+    use chrono::NaiveDateTime;
+    use serde::{self, Deserialize, Deserializer};
+
+    /// Times are exported with or without milliseconds
+    const FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<NaiveDateTime, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        NaiveDateTime::parse_from_str(&s, FORMAT)
+            .map_err(serde::de::Error::custom)
+    }
+}
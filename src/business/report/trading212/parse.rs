@@ -0,0 +1,117 @@
+use futures_util::future::BoxFuture;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::business::{csv_mapping::model::CsvMappingProfile, model::BrokerType};
+use crate::business::report::{model::{AbstractReport, ReportProcessingError}, parser::ReportParser};
+
+use super::model::{Report, Trading212ReportParsingError, Transaction};
+
+pub async fn parse_report<R: AsyncRead + Unpin>(
+    mut reader: R,
+) -> Result<Report, Trading212ReportParsingError> {
+    let mut content = String::new();
+    reader.read_to_string(&mut content).await?;
+    parse_report_csv(content.trim_start_matches('\u{feff}'))
+}
+
+pub struct Trading212ReportParser;
+
+impl ReportParser for Trading212ReportParser {
+    fn broker(&self) -> BrokerType {
+        BrokerType::Trading212
+    }
+
+    fn name(&self) -> &'static str {
+        "Trading 212"
+    }
+
+    fn description(&self) -> &'static str {
+        "History export in CSV from the history section of the app, with whole shares only"
+    }
+
+    fn is_suitable(&self, content: &[u8]) -> bool {
+        let content = String::from_utf8_lossy(content);
+        let mut reader = csv::Reader::from_reader(content.trim_start_matches('\u{feff}').as_bytes());
+        reader.headers().is_ok_and(is_header_suitable)
+    }
+
+    fn parse<'a>(&'a self, content: &'a [u8], _profile: Option<&'a CsvMappingProfile>) -> BoxFuture<'a, Result<AbstractReport, ReportProcessingError>> {
+        Box::pin(async move {
+            parse_report(content).await
+                .map(|report| report.into())
+                .map_err(|err| self.parsing_error(err))
+        })
+    }
+}
+
+fn is_header_suitable(header: &csv::StringRecord) -> bool {
+    header.iter().any(|h| h == "Action") && header.iter().any(|h| h == "Time")
+}
+
+fn parse_report_csv(content: &str) -> Result<Report, Trading212ReportParsingError> {
+    let mut reader = csv::Reader::from_reader(content.as_bytes());
+    let header = reader.headers()?.clone();
+    if !is_header_suitable(&header) {
+        return Err(Trading212ReportParsingError::UnknownHeader);
+    }
+
+    let mut transactions = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let line = record.position().map(|position| position.line() as usize).unwrap_or_default();
+        let transaction = Transaction { line, ..record.deserialize(Some(&header))? };
+        // quantities are whole numbers everywhere else, so a file with fractions is refused entirely
+        if let Some(shares) = transaction.shares.filter(|s| transaction.moves_shares() && !s.fract().is_zero()) {
+            return Err(Trading212ReportParsingError::FractionalQuantity {
+                action: transaction.action,
+                ticker: transaction.ticker.unwrap_or_default(),
+                shares,
+                time: transaction.time,
+            });
+        }
+        transactions.push(transaction);
+    }
+    Ok(Report { transactions })
+}
+
+
+#[cfg(test)]
+mod test {
+    use rust_decimal::Decimal;
+    use tokio::fs::File;
+
+    use super::*;
+    use super::super::model::ActionKind;
+
+    #[tokio::test]
+    async fn parses_report() {
+        let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("testdata/trading212_history.csv");
+        let report = parse_report(File::open(d).await.unwrap()).await.unwrap();
+
+        let kinds: Vec<ActionKind> = report.transactions.iter().map(|t| t.kind()).collect();
+        assert_eq!(kinds, vec![
+            ActionKind::Deposit,
+            ActionKind::Buy,
+            ActionKind::CurrencyConversion,
+            ActionKind::Buy,
+            ActionKind::Dividend,
+            ActionKind::Interest,
+            ActionKind::Sell,
+            ActionKind::Withdrawal,
+        ]);
+        assert_eq!(report.transactions[1].time.to_string(), "2024-01-08 15:30:12.417");
+        assert_eq!(report.transactions[1].shares, Some(Decimal::from(4)));
+    }
+
+    #[test]
+    fn refuses_fractional_shares() {
+        let content = "Action,Time,ISIN,Ticker,Name,No. of shares,Price / share,Currency (Price / share),Exchange rate,Total,Currency (Total),ID\n\
+            Market buy,2024-01-08 15:30:12,US9229083632,VOO,Vanguard S&P 500,0.5112,435.50,USD,1.0950,203.32,EUR,EOF0000001\n";
+        let error = parse_report_csv(content).err().unwrap();
+        assert!(matches!(error, Trading212ReportParsingError::FractionalQuantity { .. }));
+        assert!(error.to_string().contains("0.5112 shares of VOO"));
+
+        assert!(matches!(parse_report_csv("Date,Product\n"), Err(Trading212ReportParsingError::UnknownHeader)));
+    }
+}
@@ -0,0 +1,111 @@
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Selectable, Queryable};
+
+
+use serde::{Deserialize, Serialize};
+use serde_enum_str::{Deserialize_enum_str,Serialize_enum_str};
+use uuid::Uuid;
+
+use crate::{business::model::{BrokerType, FieldChange, Money, OperationSource}, database::schema};
+
+#[derive(Deserialize_enum_str, Serialize_enum_str)]
+#[derive(diesel_derive_enum::DbEnum, Debug, async_graphql::Enum, Copy, Clone, Eq, PartialEq)]
+#[ExistingTypePath = "crate::database::schema::sql_types::TransferDirectionType"]
+pub enum TransferDirection {
+    /// Securities arrive from an other broker or depository
+    In,
+    /// Securities leave to an other broker or depository
+    Out
+}
+
+/// Securities moved between the portfolio and an other broker without being traded
+#[derive(Serialize,Deserialize,Insertable,Selectable,Queryable)]
+#[diesel(table_name = crate::database::schema::security_transfer)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct SecurityTransfer {
+    pub operation_source: OperationSource,
+    pub broker: Option<BrokerType>,
+    pub external_id: Option<String>,
+    pub date_time: NaiveDateTime,
+    pub direction: TransferDirection,
+    pub instrument_symbol: String,
+    pub isin: Option<String>,
+    pub quantity: i32, // always positive
+    pub cost_basis: Option<Money>, // total cost of transferred securities carried over from the other broker
+    pub metadata: serde_json::Value,
+    /// Securities are split or merged by the issuer, the invested amount stays the same
+    pub is_split: bool,
+}
+
+impl SecurityTransfer {
+    /// Fields which an import of this transfer changes in the already imported one
+    pub fn changes_from(&self, imported: &SecurityTransfer) -> Vec<FieldChange> {
+        [
+            FieldChange::of("dateTime", &imported.date_time, &self.date_time),
+            FieldChange::of("direction", &imported.direction, &self.direction),
+            FieldChange::of("instrumentSymbol", &imported.instrument_symbol, &self.instrument_symbol),
+            FieldChange::of("isin", &imported.isin, &self.isin),
+            FieldChange::of("quantity", &imported.quantity, &self.quantity),
+            FieldChange::of("costBasis", &imported.cost_basis, &self.cost_basis),
+            FieldChange::of("metadata", &imported.metadata, &self.metadata),
+            FieldChange::of("isSplit", &imported.is_split, &self.is_split),
+        ].into_iter().flatten().collect()
+    }
+}
+
+// --- orm model
+
+#[derive(Deserialize, Insertable)]
+#[diesel(table_name = schema::security_transfer )]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct InsertSecurityTransfer {
+    pub portfolio_id: Uuid,
+    pub report_upload_id: Option<Uuid>,
+    #[diesel(embed)]
+    pub security_transfer: SecurityTransfer
+}
+
+#[derive(Deserialize, Queryable, Selectable)]
+#[diesel(table_name = schema::security_transfer )]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct SelectSecurityTransfer {
+    pub id: Uuid,
+    pub portfolio_id: Uuid,
+    #[diesel(embed)]
+    pub i: SecurityTransfer
+}
+
+#[cfg(test)]
+mod test {
+    use rust_decimal::Decimal;
+
+    use super::*;
+
+    fn transfer() -> SecurityTransfer {
+        SecurityTransfer {
+            operation_source: OperationSource::FreedomfinanceReport,
+            broker: Some(BrokerType::Freedomfinance),
+            external_id: Some("1".to_string()),
+            date_time: NaiveDateTime::default(),
+            direction: TransferDirection::In,
+            instrument_symbol: "VOO.US".to_string(),
+            isin: None,
+            quantity: 2,
+            cost_basis: Some(Money::new(Decimal::new(7010, 1), "USD".to_string())),
+            metadata: serde_json::Value::Null,
+            is_split: false,
+        }
+    }
+
+    #[test]
+    fn lists_changed_fields_only() {
+        let updated = SecurityTransfer {
+            quantity: 3,
+            // the same cost basis with another scale is not a change
+            cost_basis: Some(Money::new(Decimal::new(70100, 2), "USD".to_string())),
+            ..transfer()
+        };
+        let fields: Vec<String> = updated.changes_from(&transfer()).into_iter().map(|change| change.field).collect();
+        assert_eq!(fields, vec!["quantity"]);
+    }
+}
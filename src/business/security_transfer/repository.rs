@@ -0,0 +1,70 @@
+use diesel::{insert_into, prelude::*, upsert::excluded};
+use uuid::Uuid;
+
+use crate::database::{schema::{self, security_transfer::dsl}, CommonRepository, RepositoryError};
+
+use super::model::{InsertSecurityTransfer, SelectSecurityTransfer};
+
+impl CommonRepository {
+    pub fn list_security_transfers(&self, portfolio_id: Uuid) -> Result<Vec<SelectSecurityTransfer>, RepositoryError> {
+        Ok(dsl::security_transfer
+            .filter(dsl::portfolio_id.eq(portfolio_id))
+            .select(SelectSecurityTransfer::as_select())
+            .load(&mut self.pool.get()?)?)
+    }
+
+    pub fn list_security_transfers_by_external_ids(&self, portfolio_id: Uuid, external_ids: &[String]) -> Result<Vec<SelectSecurityTransfer>, RepositoryError> {
+        Ok(dsl::security_transfer
+            .filter(dsl::portfolio_id.eq(portfolio_id))
+            .filter(dsl::external_id.eq_any(external_ids))
+            .select(SelectSecurityTransfer::as_select())
+            .load(&mut self.pool.get()?)?)
+    }
+
+    pub fn list_portfolio_ids_of_security_transfers(&self, ids: &[Uuid]) -> Result<Vec<Uuid>, RepositoryError> {
+        Ok(dsl::security_transfer
+            .filter(dsl::id.eq_any(ids))
+            .select(dsl::portfolio_id)
+            .distinct()
+            .load(&mut self.pool.get()?)?)
+    }
+
+    pub fn create_security_transfer(&self, security_transfer: InsertSecurityTransfer) -> Result<Uuid, RepositoryError> {
+        Ok(diesel::insert_into(dsl::security_transfer)
+            .values(security_transfer)
+            .returning(dsl::id)
+            .get_result::<Uuid>(&mut self.pool.get()?)?)
+    }
+
+    pub fn delete_security_transfers_with_user_id(&self, ids: Vec<Uuid>, app_user_id: Uuid) -> Result<usize, RepositoryError> {
+        let valid_ids: Vec<Uuid> = dsl::security_transfer
+            .inner_join(crate::database::schema::portfolio::dsl::portfolio)
+            .filter(crate::database::schema::portfolio::dsl::app_user_id.eq(app_user_id))
+            .filter(dsl::id.eq_any(ids))
+            .select(dsl::id)
+            .load(&mut self.pool.get()?)?;
+        let affected = diesel::delete(dsl::security_transfer
+            .filter(dsl::id.eq_any(valid_ids)))
+            .execute(&mut self.pool.get()?)?;
+        Ok(affected)
+    }
+
+    pub fn create_security_transfers(&self, security_transfers: Vec<InsertSecurityTransfer>) -> Result<usize, RepositoryError> {
+        Ok(insert_into(schema::security_transfer::dsl::security_transfer)
+            .values(security_transfers)
+            .on_conflict((dsl::portfolio_id, dsl::operation_source, dsl::external_id))
+            .do_update()
+            .set((
+                dsl::report_upload_id.eq(excluded(dsl::report_upload_id)),
+                dsl::date_time.eq(excluded(dsl::date_time)),
+                dsl::direction.eq(excluded(dsl::direction)),
+                dsl::instrument_symbol.eq(excluded(dsl::instrument_symbol)),
+                dsl::isin.eq(excluded(dsl::isin)),
+                dsl::quantity.eq(excluded(dsl::quantity)),
+                dsl::cost_basis.eq(excluded(dsl::cost_basis)),
+                dsl::metadata.eq(excluded(dsl::metadata)),
+                dsl::is_split.eq(excluded(dsl::is_split)),
+            ))
+            .execute(&mut self.pool.get()?)?)
+    }
+}
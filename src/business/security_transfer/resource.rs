@@ -0,0 +1,108 @@
+use async_graphql::{Context, CustomValidator, InputObject, InputValueError, Object};
+use chrono::NaiveDateTime;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::business::lot::service::recalculate_lot_assignments;
+use crate::business::portfolio::security::is_portfolio_owner;
+use crate::business::model::{BrokerType, Money, OperationSource};
+use crate::web::graphql::{get_claims, get_state};
+
+use super::model::{InsertSecurityTransfer, SecurityTransfer, TransferDirection};
+
+
+#[derive(Default)]
+pub struct SecurityTransferMutation;
+#[Object(rename_fields="camelCase", rename_args="camelCase")]
+impl SecurityTransferMutation {
+    /// Record securities moved in or out of a portfolio identified by id without being traded
+    async fn create_security_transfer(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(custom = "CreateSecurityTransferValidator{}"))]
+        create_request: CreateSecurityTransfer
+    ) -> async_graphql::Result<Uuid> {
+        let claims = get_claims(ctx)?;
+        let state = get_state(ctx)?;
+
+        is_portfolio_owner(state, claims.sub, create_request.portfolio_id)?;
+        let portfolio_id = create_request.portfolio_id;
+        let created = state.repository.create_security_transfer(create_request.into())?;
+        recalculate_lot_assignments(state, portfolio_id)?;
+        Ok(created)
+    }
+
+    /// Delete multiple security transfers. Returns number of deleted rows.
+    async fn delete_security_transfers(&self, ctx: &Context<'_>, ids: Vec<Uuid>) -> async_graphql::Result<usize> {
+        let claims = get_claims(ctx)?;
+        let state = get_state(ctx)?;
+        let portfolio_ids = state.repository.list_portfolio_ids_of_security_transfers(&ids)?;
+        let affected = state.repository.delete_security_transfers_with_user_id(ids, claims.sub)?;
+        for portfolio_id in portfolio_ids {
+            if is_portfolio_owner(state, claims.sub, portfolio_id).is_ok() {
+                recalculate_lot_assignments(state, portfolio_id)?;
+            }
+        }
+        Ok(affected)
+    }
+}
+
+// --- model
+
+#[derive(InputObject,Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateSecurityTransfer {
+    pub portfolio_id: Uuid,
+    /// Ticker of the transferred security, instrument symbol.
+    pub ticker: String,
+    pub direction: TransferDirection,
+    /// Quantity of transferred securities. Must be positive.
+    pub quantity: i32,
+    /// Total cost of the transferred securities carried over from the other broker.
+    /// Its currency is the currency the security is traded in. Must be positive.
+    pub cost_basis: Money,
+    /// Optionally provide an ISIN identificator.
+    pub isin: Option<String>,
+    /// Date and time at which the securities were transferred in a local timezone.
+    pub date_time: NaiveDateTime,
+    /// Associate this transfer with a broker. Not required.
+    pub brokerage: Option<BrokerType>,
+}
+
+impl From<CreateSecurityTransfer> for InsertSecurityTransfer {
+    fn from(val: CreateSecurityTransfer) -> Self {
+        InsertSecurityTransfer {
+            portfolio_id: val.portfolio_id,
+            report_upload_id: None,
+            security_transfer: SecurityTransfer {
+                operation_source: OperationSource::Manual,
+                broker: val.brokerage,
+                external_id: None,
+                date_time: val.date_time,
+                direction: val.direction,
+                instrument_symbol: val.ticker,
+                isin: val.isin,
+                quantity: val.quantity,
+                cost_basis: Some(val.cost_basis),
+                metadata: serde_json::Value::Null,
+                is_split: false,
+            },
+        }
+    }
+}
+
+// --- validation
+
+struct CreateSecurityTransferValidator { }
+
+impl CustomValidator<CreateSecurityTransfer> for CreateSecurityTransferValidator {
+    fn check(&self, value: &CreateSecurityTransfer) -> Result<(), InputValueError<CreateSecurityTransfer>> {
+        if value.quantity <= 0 {
+            Err(InputValueError::custom("quantity must be positive"))
+        } else if value.cost_basis.amount.is_sign_negative() {
+            Err(InputValueError::custom("costBasis.amount must be positive"))
+        } else {
+            Ok(())
+        }
+    }
+}
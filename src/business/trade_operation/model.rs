@@ -8,7 +8,7 @@ use serde::{Deserialize, Serialize};
 use serde_enum_str::{Deserialize_enum_str,Serialize_enum_str};
 use uuid::Uuid;
 
-use crate::{business::model::{BrokerType, Money, OperationSource}, database::schema};
+use crate::{business::model::{BrokerType, FieldChange, Money, OperationSource}, database::schema};
 
 #[derive(Deserialize_enum_str, Serialize_enum_str)]
 #[derive(diesel_derive_enum::DbEnum, Debug, async_graphql::Enum, Copy, Clone, Eq, PartialEq)]
@@ -35,6 +35,26 @@ pub struct TradeOperation {
     pub order_id: Option<String>,
     pub summ: Money, // always positive traded volume without comission
     pub metadata: serde_json::Value,
+    pub reported_pnl: Option<Money>, // realized profit or loss according to the broker
+}
+
+impl TradeOperation {
+    /// Fields which an import of this operation changes in the already imported one
+    pub fn changes_from(&self, imported: &TradeOperation) -> Vec<FieldChange> {
+        [
+            FieldChange::of("dateTime", &imported.date_time, &self.date_time),
+            FieldChange::of("side", &imported.side, &self.side),
+            FieldChange::of("instrumentSymbol", &imported.instrument_symbol, &self.instrument_symbol),
+            FieldChange::of("isin", &imported.isin, &self.isin),
+            FieldChange::of("price", &imported.price, &self.price),
+            FieldChange::of("quantity", &imported.quantity, &self.quantity),
+            FieldChange::of("commission", &imported.commission, &self.commission),
+            FieldChange::of("orderId", &imported.order_id, &self.order_id),
+            FieldChange::of("summ", &imported.summ, &self.summ),
+            FieldChange::of("reportedPnl", &imported.reported_pnl, &self.reported_pnl),
+            FieldChange::of("metadata", &imported.metadata, &self.metadata),
+        ].into_iter().flatten().collect()
+    }
 }
 
 // --- orm model
@@ -59,3 +79,44 @@ pub struct SelectTradeOperation {
     #[diesel(embed)]
     pub i: TradeOperation
 }
+
+
+#[cfg(test)]
+mod test {
+    use rust_decimal::Decimal;
+
+    use super::*;
+
+    fn purchase() -> TradeOperation {
+        TradeOperation {
+            operation_source: OperationSource::Manual,
+            broker: None,
+            external_id: Some("1".to_string()),
+            date_time: NaiveDateTime::default(),
+            side: TradeOperationSide::Buy,
+            instrument_symbol: "VWCE".to_string(),
+            isin: None,
+            price: Money::new(Decimal::new(10830, 2), "EUR".to_string()),
+            quantity: 10,
+            commission: None,
+            order_id: None,
+            summ: Money::new(Decimal::new(108300, 2), "EUR".to_string()),
+            metadata: serde_json::Value::Null,
+            reported_pnl: None,
+        }
+    }
+
+    #[test]
+    fn lists_changed_fields_only() {
+        let imported = purchase();
+        let updated = TradeOperation {
+            // the same price with another scale is not a change
+            price: Money::new(Decimal::new(1083, 1), "EUR".to_string()),
+            quantity: 11,
+            summ: Money::new(Decimal::new(119130, 2), "EUR".to_string()),
+            ..purchase()
+        };
+        let fields: Vec<String> = updated.changes_from(&imported).into_iter().map(|change| change.field).collect();
+        assert_eq!(fields, vec!["quantity", "summ"]);
+    }
+}
@@ -29,6 +29,23 @@ impl CommonRepository {
             .optional()?)
     }
 
+    /// Operations of the portfolio with the given external ids, of any operation source
+    pub fn list_trade_operations_by_external_ids(&self, portfolio_id: Uuid, external_ids: &[String]) -> Result<Vec<SelectTradeOperation>, RepositoryError> {
+        Ok(dsl::trade_operation
+            .filter(dsl::portfolio_id.eq(portfolio_id))
+            .filter(dsl::external_id.eq_any(external_ids))
+            .select(SelectTradeOperation::as_select())
+            .load(&mut self.pool.get()?)?)
+    }
+
+    pub fn list_portfolio_ids_of_trade_operations(&self, ids: &[Uuid]) -> Result<Vec<Uuid>, RepositoryError> {
+        Ok(dsl::trade_operation
+            .filter(dsl::id.eq_any(ids))
+            .select(dsl::portfolio_id)
+            .distinct()
+            .load(&mut self.pool.get()?)?)
+    }
+
     pub fn create_trade_operation(&self, trade_operation: InsertTradeOperation) -> Result<Uuid, RepositoryError> {
         Ok(diesel::insert_into(dsl::trade_operation)
             .values(trade_operation)
@@ -58,7 +75,7 @@ impl CommonRepository {
     pub fn create_trade_operations(&self, trade_operations: Vec<InsertTradeOperation>) -> Result<usize, RepositoryError> {
         Ok(insert_into(schema::trade_operation::dsl::trade_operation)
             .values(trade_operations)
-            .on_conflict((dsl::operation_source, dsl::external_id))
+            .on_conflict((dsl::portfolio_id, dsl::operation_source, dsl::external_id))
             .do_update()
             .set((
                 dsl::report_upload_id.eq(excluded(dsl::report_upload_id)),
@@ -74,6 +91,7 @@ impl CommonRepository {
                 dsl::order_id.eq(excluded(dsl::order_id)),
                 dsl::summ.eq(excluded(dsl::summ)),
                 dsl::metadata.eq(excluded(dsl::metadata)),
+                dsl::reported_pnl.eq(excluded(dsl::reported_pnl)),
             ))
             .execute(&mut self.pool.get()?)?)
     }
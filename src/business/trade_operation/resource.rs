@@ -4,6 +4,7 @@ use chrono::NaiveDateTime;
 use serde::Deserialize;
 use uuid::Uuid;
 
+use crate::business::lot::service::recalculate_lot_assignments;
 use crate::business::portfolio::security::is_portfolio_owner;
 use crate::business::model::{BrokerType, Money};
 use crate::web::graphql::{get_claims, get_state};
@@ -26,7 +27,9 @@ impl TradeOperationMutation {
         let state = get_state(ctx)?;
 
         is_portfolio_owner(state, claims.sub, create_request.portfolio_id)?;
+        let portfolio_id = create_request.portfolio_id;
         let created = state.repository.create_trade_operation(create_request.into())?;
+        recalculate_lot_assignments(state, portfolio_id)?;
         Ok(created)
     }
 
@@ -35,7 +38,14 @@ impl TradeOperationMutation {
     async fn delete_trade_operations(&self, ctx: &Context<'_>, ids: Vec<Uuid>) -> async_graphql::Result<usize> {
         let claims = get_claims(ctx)?;
         let state = get_state(ctx)?;
-        Ok(state.repository.delete_trade_operations_with_user_id(ids, claims.sub)?)
+        let portfolio_ids = state.repository.list_portfolio_ids_of_trade_operations(&ids)?;
+        let affected = state.repository.delete_trade_operations_with_user_id(ids, claims.sub)?;
+        for portfolio_id in portfolio_ids {
+            if is_portfolio_owner(state, claims.sub, portfolio_id).is_ok() {
+                recalculate_lot_assignments(state, portfolio_id)?;
+            }
+        }
+        Ok(affected)
     }
 }
 
@@ -83,6 +93,7 @@ impl From<CreateTradeOperation> for InsertTradeOperation {
                 external_id: None,
                 commission: None,
                 metadata: serde_json::Value::Null,
+                reported_pnl: None,
             },
         }
     }
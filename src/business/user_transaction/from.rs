@@ -1,4 +1,4 @@
-use crate::business::{fiscal_transaction::model::{FiscalTransactionType, SelectFiscalTransaction}, trade_operation::model::{SelectTradeOperation, TradeOperationSide}};
+use crate::business::{fiscal_transaction::model::{FiscalTransaction, FiscalTransactionType, SelectFiscalTransaction}, model::Money, trade_operation::model::{SelectTradeOperation, TradeOperation, TradeOperationSide}};
 
 use super::resource::{UserTransaction, UserTransactionTradeSide, UserTransactionType};
 
@@ -11,6 +11,10 @@ impl From<FiscalTransactionType> for UserTransactionType {
             FiscalTransactionType::Commission => UserTransactionType::Comission,
             FiscalTransactionType::FundingWithdrawal => UserTransactionType::FundingWithdrawal,
             FiscalTransactionType::RevertedDividend => UserTransactionType::RevertedDividend,
+            FiscalTransactionType::Interest => UserTransactionType::Interest,
+            FiscalTransactionType::CurrencyExchange => UserTransactionType::CurrencyExchange,
+            FiscalTransactionType::Rollover => UserTransactionType::Rollover,
+            FiscalTransactionType::CorporateAction => UserTransactionType::CorporateAction,
             FiscalTransactionType::Unrecognized(_) => UserTransactionType::Unrecognized
         }
     }
@@ -21,17 +25,29 @@ impl From<FiscalTransactionType> for UserTransactionType {
 impl From<SelectFiscalTransaction> for UserTransaction {
     fn from(value: SelectFiscalTransaction) -> Self {
         Self {
-            unrecognized_type: if let FiscalTransactionType::Unrecognized(ref t) = value.i.operation_type { Some(t.clone()) } else { None },
-            brokerage: value.i.broker,
-            user_transaction_type: value.i.operation_type.into(),
-            date_time: value.i.date_time,
-            summ: value.i.amount,
-            ticker: value.i.symbol_id,
+            fiscal_transaction_id: Some(value.id),
+            ..value.i.into()
+        }
+    }
+}
+
+/// A transaction which is not stored yet, like one of a previewed report
+impl From<FiscalTransaction> for UserTransaction {
+    fn from(value: FiscalTransaction) -> Self {
+        Self {
+            unrecognized_type: if let FiscalTransactionType::Unrecognized(ref t) = value.operation_type { Some(t.clone()) } else { None },
+            brokerage: value.broker,
+            user_transaction_type: value.operation_type.into(),
+            date_time: value.date_time,
+            ex_date: value.ex_date,
+            amount_per_share: value.amount_per_share.map(|amount| Money::new(amount, value.amount.currency.clone())),
+            summ: value.amount,
+            ticker: value.symbol_id,
             price: None,
             quantity: None,
             trade_side: None,
             trade_operation_id: None,
-            fiscal_transaction_id: Some(value.id),
+            fiscal_transaction_id: None,
         }
     }
 }
@@ -47,22 +63,34 @@ impl From<TradeOperationSide> for UserTransactionTradeSide {
 
 impl From<SelectTradeOperation> for UserTransaction {
     fn from(value: SelectTradeOperation) -> Self {
-        let operation_signum = match value.i.side {
+        Self {
+            trade_operation_id: Some(value.id),
+            ..value.i.into()
+        }
+    }
+}
+
+/// An operation which is not stored yet, like one of a previewed report
+impl From<TradeOperation> for UserTransaction {
+    fn from(value: TradeOperation) -> Self {
+        let operation_signum = match value.side {
             crate::business::trade_operation::model::TradeOperationSide::Buy => -1,
             crate::business::trade_operation::model::TradeOperationSide::Sell => 1,
         };
         Self {
             user_transaction_type: UserTransactionType::Trade,
-            brokerage: value.i.broker,
-            date_time: value.i.date_time,
-            summ: value.i.summ * operation_signum,
-            ticker: Some(value.i.instrument_symbol),
-            price: Some(value.i.price),
-            quantity: Some(value.i.quantity),
-            trade_side: Some(value.i.side.into()),
-            trade_operation_id: Some(value.id),
+            brokerage: value.broker,
+            date_time: value.date_time,
+            summ: value.summ * operation_signum,
+            ticker: Some(value.instrument_symbol),
+            price: Some(value.price),
+            quantity: Some(value.quantity),
+            trade_side: Some(value.side.into()),
+            trade_operation_id: None,
             fiscal_transaction_id: None,
             unrecognized_type: None,
+            ex_date: None,
+            amount_per_share: None,
         }
     }
 }
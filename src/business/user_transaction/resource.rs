@@ -39,6 +39,14 @@ pub enum UserTransactionType {
     Comission,
     FundingWithdrawal,
     RevertedDividend,
+    /// Interest paid or charged on cash
+    Interest,
+    /// One side of a conversion of money between currencies
+    CurrencyExchange,
+    /// Financing charge for carrying a position overnight
+    Rollover,
+    /// Money paid out in a corporate action other than a dividend
+    CorporateAction,
 }
 
 #[derive(async_graphql::Enum, Copy, Clone, Eq, PartialEq, Serialize)]
@@ -74,6 +82,10 @@ pub struct UserTransaction {
     pub unrecognized_type: Option<String>,
     /// Timestamp of an instant when the transaction occurred.
     pub date_time: chrono::prelude::NaiveDateTime,
+    /// Appears in DIVIDEND of brokers which report it. First day the instrument trades without the dividend.
+    pub ex_date: Option<chrono::prelude::NaiveDate>,
+    /// Appears in DIVIDEND of brokers which report it. Dividend paid for a single instrument.
+    pub amount_per_share: Option<Money>,
 
 
 }
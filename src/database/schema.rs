@@ -5,6 +5,10 @@ pub mod sql_types {
     #[diesel(postgres_type(name = "broker_type"))]
     pub struct BrokerType;
 
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "cost_basis_method_type"))]
+    pub struct CostBasisMethodType;
+
     #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "custom_money"))]
     pub struct CustomMoney;
@@ -20,6 +24,10 @@ pub mod sql_types {
     #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "trade_side_type"))]
     pub struct TradeSideType;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "transfer_direction_type"))]
+    pub struct TransferDirectionType;
 }
 
 diesel::table! {
@@ -43,6 +51,37 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    csv_mapping_profile (id) {
+        id -> Uuid,
+        portfolio_id -> Uuid,
+        label -> Varchar,
+        mapping -> Jsonb,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::OperationSourceType;
+    use super::sql_types::BrokerType;
+    use super::sql_types::CustomMoney;
+
+    currency_conversion (id) {
+        id -> Uuid,
+        portfolio_id -> Uuid,
+        report_upload_id -> Nullable<Uuid>,
+        operation_source -> OperationSourceType,
+        broker -> Nullable<BrokerType>,
+        external_id -> Nullable<Varchar>,
+        date_time -> Timestamp,
+        sold -> CustomMoney,
+        bought -> CustomMoney,
+        rate -> Numeric,
+        metadata -> Jsonb,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use super::sql_types::OperationSourceType;
@@ -62,14 +101,81 @@ diesel::table! {
         metadata -> Jsonb,
         broker -> Nullable<BrokerType>,
         report_upload_id -> Nullable<Uuid>,
+        ex_date -> Nullable<Date>,
+        amount_per_share -> Nullable<Numeric>,
     }
 }
 
 diesel::table! {
+    fx_rate (id) {
+        id -> Uuid,
+        date -> Date,
+        #[max_length = 3]
+        base_currency -> Varchar,
+        #[max_length = 3]
+        quote_currency -> Varchar,
+        rate -> Numeric,
+        source -> Varchar,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::CustomMoney;
+
+    lot_assignment (id) {
+        id -> Uuid,
+        portfolio_id -> Uuid,
+        sell_trade_operation_id -> Uuid,
+        buy_trade_operation_id -> Nullable<Uuid>,
+        quantity -> Int4,
+        cost_basis -> CustomMoney,
+        manual -> Bool,
+        buy_security_transfer_id -> Nullable<Uuid>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::CostBasisMethodType;
+
     portfolio (id) {
         id -> Uuid,
         app_user_id -> Uuid,
         label -> Varchar,
+        cost_basis_method -> CostBasisMethodType,
+        #[max_length = 3]
+        base_currency -> Varchar,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::BrokerType;
+
+    position_snapshot (id) {
+        id -> Uuid,
+        portfolio_id -> Uuid,
+        report_upload_id -> Uuid,
+        broker -> BrokerType,
+        taken_at -> Timestamp,
+        instrument_symbol -> Nullable<Varchar>,
+        isin -> Nullable<Varchar>,
+        quantity -> Numeric,
+        currency -> Varchar,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::CustomMoney;
+
+    price_history (id) {
+        id -> Uuid,
+        ticker -> Varchar,
+        date -> Date,
+        close -> CustomMoney,
+        source -> Varchar,
     }
 }
 
@@ -86,6 +192,31 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::OperationSourceType;
+    use super::sql_types::BrokerType;
+    use super::sql_types::TransferDirectionType;
+    use super::sql_types::CustomMoney;
+
+    security_transfer (id) {
+        id -> Uuid,
+        portfolio_id -> Uuid,
+        report_upload_id -> Nullable<Uuid>,
+        operation_source -> OperationSourceType,
+        broker -> Nullable<BrokerType>,
+        external_id -> Nullable<Varchar>,
+        date_time -> Timestamp,
+        direction -> TransferDirectionType,
+        instrument_symbol -> Varchar,
+        isin -> Nullable<Varchar>,
+        quantity -> Int4,
+        cost_basis -> Nullable<CustomMoney>,
+        metadata -> Jsonb,
+        is_split -> Bool,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use super::sql_types::OperationSourceType;
@@ -110,22 +241,39 @@ diesel::table! {
         summ -> CustomMoney,
         metadata -> Jsonb,
         broker -> Nullable<BrokerType>,
+        reported_pnl -> Nullable<CustomMoney>,
     }
 }
 
 diesel::joinable!(app_user_login_method -> app_user (app_user_id));
+diesel::joinable!(csv_mapping_profile -> portfolio (portfolio_id));
+diesel::joinable!(currency_conversion -> portfolio (portfolio_id));
+diesel::joinable!(currency_conversion -> report_upload (report_upload_id));
 diesel::joinable!(fiscal_transaction -> portfolio (portfolio_id));
 diesel::joinable!(fiscal_transaction -> report_upload (report_upload_id));
+diesel::joinable!(lot_assignment -> portfolio (portfolio_id));
+diesel::joinable!(lot_assignment -> security_transfer (buy_security_transfer_id));
 diesel::joinable!(portfolio -> app_user (app_user_id));
+diesel::joinable!(position_snapshot -> portfolio (portfolio_id));
+diesel::joinable!(position_snapshot -> report_upload (report_upload_id));
 diesel::joinable!(report_upload -> portfolio (portfolio_id));
+diesel::joinable!(security_transfer -> portfolio (portfolio_id));
+diesel::joinable!(security_transfer -> report_upload (report_upload_id));
 diesel::joinable!(trade_operation -> portfolio (portfolio_id));
 diesel::joinable!(trade_operation -> report_upload (report_upload_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     app_user,
     app_user_login_method,
+    csv_mapping_profile,
+    currency_conversion,
     fiscal_transaction,
+    fx_rate,
+    lot_assignment,
     portfolio,
+    position_snapshot,
+    price_history,
     report_upload,
+    security_transfer,
     trade_operation,
 );
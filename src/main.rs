@@ -21,6 +21,7 @@ use tower_http::services::ServeDir;
 use std::net::SocketAddr;
 use std::sync::Arc;
 
+use crate::business::report::parser::ReportParsers;
 use crate::settings::Settings;
 use crate::web::graphql::{QueryRoot,MutationRoot};
 use crate::database::CommonRepository;
@@ -31,6 +32,7 @@ pub struct ApplicationState {
     pub google_jwt_parser: jsonwebtoken_google::Parser,
     pub repository: CommonRepository,
     pub redis: redis::Client,
+    pub report_parsers: ReportParsers,
 }
 
 #[tokio::main]
@@ -60,6 +62,7 @@ async fn main() {
         )),
         google_jwt_parser: jsonwebtoken_google::Parser::new(&settings.auth.google.client_id),
         redis: establish_redis_connection(&settings.datasource.redis_url),
+        report_parsers: ReportParsers::default(),
         settings,
     };
 
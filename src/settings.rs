@@ -2,6 +2,7 @@ use std::env;
 
 use config::{Config, ConfigError, Environment, File};
 use serde::Deserialize;
+use uuid::Uuid;
 
 #[derive(Debug, Deserialize)]
 pub struct Settings {
@@ -9,6 +10,8 @@ pub struct Settings {
     pub web: WebSettings,
     pub auth: AuthSettings,
     pub datasource: Datasources,
+    #[serde(default)]
+    pub market_data: MarketDataSettings,
 }
 
 #[derive(Debug, Deserialize)]
@@ -21,6 +24,9 @@ pub struct AuthSettings {
     pub server_secret: String,
     pub password_salt: String,
     pub google: AuthProviderSettings,
+    /// Users who may change data shared by every user, like exchange rates
+    #[serde(default)]
+    pub admin_user_ids: Vec<Uuid>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -37,6 +43,12 @@ pub struct Datasources {
     pub run_migrations: bool,
 }
 
+#[derive(Debug, Default, Deserialize)]
+pub struct MarketDataSettings {
+    /// Directory with daily close prices stored as `<ticker>.csv` files
+    pub csv_directory: Option<String>,
+}
+
 impl Settings {
     pub fn from_config() -> Result<Self, ConfigError> {
         let env_name = env::var("ENV_NAME").unwrap_or_else(|_| "local".into());
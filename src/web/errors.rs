@@ -1,4 +1,4 @@
-use crate::{business::report::model::ReportProcessingError, database::RepositoryError};
+use crate::{business::{fx_rate::model::MissingFxRateError, price::model::MarketDataError, report::model::ReportProcessingError}, database::RepositoryError};
 
 use serde::Serialize;
 
@@ -20,10 +20,16 @@ pub enum DescriptiveError {
     Forbidden(String),
     #[error("Resource \"{resource}\" with requested parameters could not be found.")]
     NotFound { resource: String },
+    #[error("Request can't be fulfilled: {0}")]
+    BadRequest(String),
     #[error(transparent)]
     RepositoryError( #[from] RepositoryError ),
     #[error(transparent)]
-    ReportProcessingError( #[from] ReportProcessingError)
+    ReportProcessingError( #[from] ReportProcessingError),
+    #[error(transparent)]
+    MarketDataError( #[from] MarketDataError),
+    #[error(transparent)]
+    MissingFxRateError( #[from] MissingFxRateError),
 }
 
 impl async_graphql::ErrorExtensions for DescriptiveError {
@@ -41,11 +47,26 @@ impl async_graphql::ErrorExtensions for DescriptiveError {
                 DescriptiveError::NotFound{..} => {
                     e.set("code", "NOT_FOUND");
                 },
+                DescriptiveError::BadRequest(reason) => {
+                    e.set("code", "BAD_REQUEST");
+                    e.set("reason", reason);
+                },
                 DescriptiveError::RepositoryError(_) => {
                     e.set("code", "REPOSITORY_ERROR");
                 },
-                DescriptiveError::ReportProcessingError(_) => {
+                DescriptiveError::ReportProcessingError(err) => {
                     e.set("code", "REPORT_PROCESSING_ERROR");
+                    if let ReportProcessingError::InvalidRows { diagnostics } = err {
+                        if let Ok(diagnostics) = async_graphql::to_value(diagnostics) {
+                            e.set("diagnostics", diagnostics);
+                        }
+                    }
+                },
+                DescriptiveError::MarketDataError(_) => {
+                    e.set("code", "MARKET_DATA_ERROR");
+                },
+                DescriptiveError::MissingFxRateError(_) => {
+                    e.set("code", "MISSING_FX_RATE");
                 },
             })
     }
@@ -13,12 +13,18 @@ use axum::routing::get;
 use axum::{Extension, Router};
 
 use crate::auth::service::AuthClaims;
+use crate::business::csv_mapping::resource::CsvMappingMutation;
+use crate::business::currency_conversion::resource::CurrencyConversionMutation;
 use crate::business::fiscal_transaction::resource::FiscalTransactionMutation;
+use crate::business::fx_rate::resource::{FxRateQuery, FxRateMutation};
+use crate::business::lot::resource::LotMutation;
+use crate::business::price::resource::{PriceQuery, PriceMutation};
+use crate::business::security_transfer::resource::SecurityTransferMutation;
 use crate::business::trade_operation::resource::TradeOperationMutation;
 use crate::business::user_transaction::resource::UserTransactionQuery;
 use crate::ApplicationState;
 use crate::business::portfolio::resource::{PortfolioQuery, PortfolioMutation};
-use crate::business::report::resource::ReportMutation;
+use crate::business::report::resource::{ReportMutation, ReportQuery};
 
 pub mod model {
     // use serde::Serialize;
@@ -58,6 +64,16 @@ pub fn get_claims<'ctx>(ctx: &Context<'ctx>) -> async_graphql::Result<&'ctx Auth
         .ok_or_else(|| super::errors::DescriptiveError::Unauthorized.extend() );
 }
 
+/// Claims of an admin, who may change data shared by every user
+pub fn get_admin_claims<'ctx>(ctx: &Context<'ctx>) -> async_graphql::Result<&'ctx AuthClaims> {
+    let claims = get_claims(ctx)?;
+    if get_state(ctx)?.settings.auth.admin_user_ids.contains(&claims.sub) {
+        Ok(claims)
+    } else {
+        Err(super::errors::DescriptiveError::Forbidden("Only admins may change data shared by every user".to_string()).extend())
+    }
+}
+
 pub fn get_state<'ctx>(ctx: &Context<'ctx>) -> async_graphql::Result<&'ctx Arc<ApplicationState>> {
     return ctx.data::<Arc<ApplicationState>>();
 }
@@ -66,9 +82,9 @@ pub fn get_state<'ctx>(ctx: &Context<'ctx>) -> async_graphql::Result<&'ctx Arc<A
 // --- default and miscellaneous queries and mutations
 
 #[derive(MergedObject, Default)]
-pub struct QueryRoot(MiscellaneousQuery, PortfolioQuery, UserTransactionQuery);
+pub struct QueryRoot(MiscellaneousQuery, PortfolioQuery, UserTransactionQuery, PriceQuery, FxRateQuery, ReportQuery);
 #[derive(MergedObject, Default)]
-pub struct MutationRoot(PortfolioMutation, ReportMutation, FiscalTransactionMutation, TradeOperationMutation);
+pub struct MutationRoot(PortfolioMutation, ReportMutation, FiscalTransactionMutation, TradeOperationMutation, LotMutation, PriceMutation, FxRateMutation, SecurityTransferMutation, CurrencyConversionMutation, CsvMappingMutation);
 pub type ServiceSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
 
 